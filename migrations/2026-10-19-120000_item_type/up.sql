@@ -0,0 +1 @@
+ALTER TABLE items ADD COLUMN item_type VARCHAR(32) NOT NULL DEFAULT 'note';
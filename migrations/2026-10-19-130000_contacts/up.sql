@@ -0,0 +1,12 @@
+CREATE TABLE contacts (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  name VARCHAR(255) NOT NULL UNIQUE,
+  public_key VARCHAR(255) NOT NULL,
+  date_added DATETIME NOT NULL
+);
+CREATE TABLE identities (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  public_key VARCHAR(255) NOT NULL,
+  secret_key TEXT NOT NULL,
+  date_added DATETIME NOT NULL
+)
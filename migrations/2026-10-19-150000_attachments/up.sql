@@ -0,0 +1,15 @@
+CREATE TABLE attachments (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  item_id INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+  file_name VARCHAR(255) NOT NULL,
+  mime_type VARCHAR(255) NOT NULL,
+  size BIGINT NOT NULL,
+  date_added DATETIME NOT NULL,
+  UNIQUE (item_id, file_name)
+);
+CREATE TABLE attachment_chunks (
+  attachment_id INTEGER NOT NULL REFERENCES attachments(id) ON DELETE CASCADE,
+  chunk_index INTEGER NOT NULL,
+  data BLOB NOT NULL,
+  PRIMARY KEY (attachment_id, chunk_index)
+)
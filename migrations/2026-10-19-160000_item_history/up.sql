@@ -0,0 +1,9 @@
+CREATE TABLE item_history (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  item_id INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+  contents TEXT,
+  fields TEXT,
+  item_type VARCHAR(32) NOT NULL,
+  date_saved DATETIME NOT NULL
+);
+CREATE INDEX item_history_item_id ON item_history (item_id, date_saved)
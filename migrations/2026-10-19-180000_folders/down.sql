@@ -0,0 +1,15 @@
+CREATE TABLE items_without_folders (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  title VARCHAR(255) NOT NULL UNIQUE,
+  contents TEXT,
+  date_added DATETIME NOT NULL,
+  date_last_modified DATETIME NOT NULL,
+  item_type VARCHAR(32) NOT NULL DEFAULT 'note',
+  fields TEXT,
+  deleted_at DATETIME
+);
+INSERT INTO items_without_folders (id, title, contents, date_added, date_last_modified, item_type, fields, deleted_at)
+  SELECT id, title, contents, date_added, date_last_modified, item_type, fields, deleted_at FROM items;
+DROP TABLE items;
+ALTER TABLE items_without_folders RENAME TO items;
+DROP TABLE folders;
@@ -0,0 +1,24 @@
+CREATE TABLE folders (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  parent_id INTEGER REFERENCES folders(id) ON DELETE CASCADE,
+  name VARCHAR(255) NOT NULL,
+  date_added DATETIME NOT NULL
+);
+CREATE UNIQUE INDEX folders_parent_id_name ON folders (IFNULL(parent_id, 0), name);
+-- titles are unique per folder now, SQLite can only drop the table wide UNIQUE by copying the table
+CREATE TABLE items_with_folders (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  title VARCHAR(255) NOT NULL,
+  contents TEXT,
+  date_added DATETIME NOT NULL,
+  date_last_modified DATETIME NOT NULL,
+  item_type VARCHAR(32) NOT NULL DEFAULT 'note',
+  fields TEXT,
+  deleted_at DATETIME,
+  folder_id INTEGER REFERENCES folders(id)
+);
+INSERT INTO items_with_folders (id, title, contents, date_added, date_last_modified, item_type, fields, deleted_at)
+  SELECT id, title, contents, date_added, date_last_modified, item_type, fields, deleted_at FROM items;
+DROP TABLE items;
+ALTER TABLE items_with_folders RENAME TO items;
+CREATE UNIQUE INDEX items_folder_id_title ON items (IFNULL(folder_id, 0), title)
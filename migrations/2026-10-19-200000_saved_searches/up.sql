@@ -0,0 +1,6 @@
+CREATE TABLE saved_searches (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  name TEXT NOT NULL UNIQUE,
+  query TEXT NOT NULL,
+  date_added DATETIME NOT NULL
+);
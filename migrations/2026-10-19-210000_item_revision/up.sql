@@ -0,0 +1 @@
+ALTER TABLE items ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
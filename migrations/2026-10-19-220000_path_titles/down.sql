@@ -0,0 +1 @@
+DROP TABLE path_title_items;
@@ -0,0 +1,7 @@
+-- the items titled like Work/AWS/root before folders existed, filed into folders once by folders::migrate_path_titles
+CREATE TABLE path_title_items (
+  item_id INTEGER PRIMARY KEY NOT NULL
+);
+-- a vault with folders already has its paths filed, a / left at the top level is part of a title
+INSERT INTO path_title_items (item_id)
+  SELECT id FROM items WHERE folder_id IS NULL AND title LIKE '%/%' AND NOT EXISTS (SELECT 1 FROM folders);
@@ -0,0 +1,230 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path;
+use age::secrecy::SecretString;
+use base64::prelude::*;
+use chrono::prelude::*;
+use magic_crypt::MagicCrypt256;
+use serde::{Deserialize, Serialize};
+use crate::attachment;
+use crate::attachment_actions;
+use crate::folders;
+use crate::import::ImportedItem;
+use crate::models;
+use crate::tags;
+
+/// Bumped whenever a change to `Document` can't be read by older versions. Version 2 moved the folders of an item from its title to `path`.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// The decrypted vault as it is stored inside an archive, or as plain `--format json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Document {
+    pub format_version: u32,
+    #[serde(default)]
+    pub exported_at: Option<NaiveDateTime>,
+    pub items: Vec<DocumentItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentItem {
+    /// Id of the item in the exporting vault, informational only: imports match items by title.
+    #[serde(default)]
+    pub id: Option<i32>,
+    /// The folders the item is in, from the top level down.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<String>,
+    pub title: String,
+    #[serde(default)]
+    pub item_type: String,
+    pub contents: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<models::Field>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<DocumentAttachment>,
+    /// Missing dates, e.g. in hand written documents, default to the time of the import.
+    #[serde(default)]
+    pub date_added: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub date_last_modified: Option<NaiveDateTime>,
+}
+
+/// An attachment with its data base64 encoded, so the document stays plain JSON.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentAttachment {
+    pub file_name: String,
+    pub data: String,
+}
+
+impl Document {
+    pub fn from_items(items: &[models::Item], mc: &MagicCrypt256) -> Result<Document, Box<dyn Error>> {
+        let folder_tree = folders::FolderTree::load()?;
+        let mut document_items = vec![];
+        for item in items.iter() {
+            document_items.push(DocumentItem {
+                id: Some(item.id),
+                path: folder_tree.path(item.folder_id),
+                title: item.title.clone(),
+                item_type: item.item_type.clone(),
+                contents: item.decrypt_contents(mc)?,
+                fields: item.decrypt_fields(mc)?,
+                tags: tags::item_names(item.id, mc)?,
+                attachments: document_attachments(item, mc)?,
+                date_added: Some(item.date_added),
+                date_last_modified: Some(item.date_last_modified),
+            });
+        }
+        Ok(Document {
+            format_version: FORMAT_VERSION,
+            exported_at: Some(Utc::now().naive_utc()),
+            items: document_items,
+        })
+    }
+
+    pub fn into_imported_items(self) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+        let mut imported_items = vec![];
+        for mut i in self.items.into_iter() {
+            if self.format_version < 2 {
+                // titles were paths then
+                (i.path, i.title) = folders::split_path(&i.title);
+            }
+            let mut attachments = vec![];
+            for a in i.attachments.into_iter() {
+                let data = BASE64_STANDARD.decode(&a.data).map_err(|e| format!("invalid attachment {} of {}: {}", a.file_name, i.title, e))?;
+                attachments.push((a.file_name, data));
+            }
+            imported_items.push(ImportedItem {
+                path: i.path,
+                item_type: i.item_type.parse::<models::ItemType>().ok(),
+                title: i.title,
+                notes: Some(i.contents),
+                fields: i.fields,
+                tags: i.tags,
+                attachments,
+                date_added: i.date_added,
+                date_last_modified: i.date_last_modified,
+                ..Default::default()
+            });
+        }
+        Ok(imported_items)
+    }
+}
+
+fn document_attachments(item: &models::Item, mc: &MagicCrypt256) -> Result<Vec<DocumentAttachment>, Box<dyn Error>> {
+    let mut document_attachments = vec![];
+    for attachment in attachment_actions::find_by_item_id(item.id)?.iter() {
+        document_attachments.push(DocumentAttachment {
+            file_name: attachment.file_name.clone(),
+            data: BASE64_STANDARD.encode(attachment::read(attachment, mc)?),
+        });
+    }
+    Ok(document_attachments)
+}
+
+/// Writes the items as a JSON document encrypted with an age passphrase (scrypt).
+pub fn write(path: &path::Path, items: &[models::Item], mc: &MagicCrypt256, passphrase: &str) -> Result<(), Box<dyn Error>> {
+    let document = Document::from_items(items, mc)?;
+    let recipient = age::scrypt::Recipient::new(SecretString::from(passphrase.to_string()));
+    let encrypted = age::encrypt(&recipient, &serde_json::to_vec(&document)?)?;
+    fs::write(path, encrypted)?;
+    info!("wrote {} items to {}", items.len(), path.to_string_lossy());
+    Ok(())
+}
+
+pub fn read(path: &path::Path, passphrase: &str) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+    let identity = age::scrypt::Identity::new(SecretString::from(passphrase.to_string()));
+    let document = parse_json(&age::decrypt(&identity, &fs::read(path)?)?)?;
+    info!("read {} items from {}, exported at {:?}", document.items.len(), path.to_string_lossy(), document.exported_at);
+    document.into_imported_items()
+}
+
+/// Writes the items as an unencrypted, pretty printed JSON document.
+pub fn write_json(path: &path::Path, items: &[models::Item], mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    let document = Document::from_items(items, mc)?;
+    let mut file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    serde_json::to_writer_pretty(&mut file, &document)?;
+    file.write_all(b"\n")?;
+    info!("wrote {} items to {}", items.len(), path.to_string_lossy());
+    Ok(())
+}
+
+pub fn parse_json(data: &[u8]) -> Result<Document, Box<dyn Error>> {
+    let document: Document = serde_json::from_slice(data)?;
+    if document.format_version > FORMAT_VERSION {
+        return Err(format!("the document was written by a newer senoru (format version {})", document.format_version).into());
+    }
+    Ok(document)
+}
+
+pub fn read_json(path: &path::Path) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+    let document = parse_json(&fs::read(path)?)?;
+    info!("read {} items from {}", document.items.len(), path.to_string_lossy());
+    document.into_imported_items()
+}
+
+/// Tells a senoru JSON document apart from other `.json` exports by its `format_version` field.
+pub fn is_json_document(path: &path::Path) -> bool {
+    fs::File::open(path)
+        .ok()
+        .and_then(|f| serde_json::from_reader::<_, serde_json::Value>(io::BufReader::new(f)).ok())
+        .is_some_and(|v| v.get("format_version").is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use magic_crypt::MagicCryptTrait;
+    use crate::db;
+    use crate::item_actions;
+
+    #[test]
+    fn write_then_read() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let folder_id = folders::find_or_create(&["archive test".to_string()]).unwrap();
+        let mut new_item = models::NewItem::new("a/b".into());
+        new_item.folder_id = folder_id;
+        new_item.contents = Some(mc.encrypt_str_to_base64("contents"));
+        new_item.encrypt_fields(&mc, &[models::Field::new(models::FieldKind::Text, "Field", "value")]).unwrap();
+        item_actions::insert(&new_item).unwrap();
+        let item = item_actions::find_by_folder_and_title(folder_id, "a/b").unwrap().unwrap();
+        attachment::attach_bytes(item.id, "file.txt", b"data", &mc).unwrap();
+        tags::tag_items(&[item.id], "archive test", &mc).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("vault.senoru");
+        write(&file, std::slice::from_ref(&item), &mc, "passphrase").unwrap();
+        assert!(read(&file, "wrong").is_err());
+        let imported_items = read(&file, "passphrase").unwrap();
+        assert_eq!(imported_items.len(), 1);
+        let imported_item = &imported_items[0];
+        assert_eq!((imported_item.path.clone(), imported_item.title.as_str()), (vec!["archive test".to_string()], "a/b"));
+        assert_eq!(imported_item.contents(), "contents");
+        assert_eq!(imported_item.fields, vec![models::Field::new(models::FieldKind::Text, "Field", "value")]);
+        assert_eq!(imported_item.attachments, vec![("file.txt".to_string(), b"data".to_vec())]);
+        assert_eq!(imported_item.tags, vec!["archive test".to_string()]);
+        assert_eq!(imported_item.date_added, Some(item.date_added));
+
+        let json = dir.path().join("vault.json");
+        write_json(&json, &[item], &mc).unwrap();
+        assert!(is_json_document(&json));
+        assert_eq!(read_json(&json).unwrap()[0].title, "a/b");
+    }
+
+    #[test]
+    fn read_older_and_newer_documents() {
+        let document = parse_json(br#"{ "format_version": 1, "items": [{ "title": "Work/AWS/root", "contents": "x" }] }"#).unwrap();
+        let imported_items = document.into_imported_items().unwrap();
+        assert_eq!((imported_items[0].path.clone(), imported_items[0].title.as_str()), (vec!["Work".to_string(), "AWS".to_string()], "root"));
+        assert_eq!(imported_items[0].resolved_item_type(), models::ItemType::Note);
+
+        let document = parse_json(br#"{ "format_version": 2, "items": [{ "title": "a/b", "contents": "x", "attachments": [{ "file_name": "f", "data": "!" }] }] }"#).unwrap();
+        assert!(document.into_imported_items().is_err());
+        assert!(parse_json(br#"{ "format_version": 3, "items": [] }"#).is_err());
+        assert!(parse_json(br#"{ "items": [] }"#).is_err());
+    }
+}
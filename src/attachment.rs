@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path;
+use diesel::SqliteConnection;
+use magic_crypt::MagicCrypt256;
+use magic_crypt::MagicCryptTrait;
+use crate::attachment_actions;
+use crate::models;
+
+/// Files are encrypted 1 MiB at a time, so neither attaching nor saving needs the whole file in memory.
+pub const CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// Reduces a name to its last path component, so an attachment can never be saved outside the folder it is saved to.
+pub fn safe_file_name(file_name: &str) -> Result<String, Box<dyn Error>> {
+    match path::Path::new(file_name).file_name().map(|n| n.to_string_lossy()) {
+        Some(name) if !name.is_empty() && name != "." && name != ".." => Ok(name.to_string()),
+        _ => Err(format!("{} is not a valid file name", file_name).into()),
+    }
+}
+
+fn attach_reader(item_id: i32, file_name: &str, size: u64, mut reader: impl Read, mc: &MagicCrypt256) -> Result<models::Attachment, Box<dyn Error>> {
+    let file_name = safe_file_name(file_name)?;
+    if attachment_actions::find_by_file_name(item_id, &file_name)?.is_some() {
+        return Err(format!("the item already has an attachment named {}", file_name).into());
+    }
+    let new_attachment = models::NewAttachment::new(item_id, file_name, size as i64);
+    let chunks = std::iter::from_fn(move || {
+        let mut chunk = vec![];
+        match reader.by_ref().take(CHUNK_SIZE).read_to_end(&mut chunk) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(mc.encrypt_bytes_to_bytes(&chunk))),
+            Err(e) => Some(Err(e.into())),
+        }
+    });
+    let attachment = attachment_actions::insert(&new_attachment, chunks)?;
+    info!("attached {} ({} bytes, {}) to item {}", attachment.file_name, attachment.size, attachment.mime_type, item_id);
+    Ok(attachment)
+}
+
+/// Stores a file with an item, encrypted with the master key.
+pub fn attach(item: &models::Item, file: &path::Path, mc: &MagicCrypt256) -> Result<models::Attachment, Box<dyn Error>> {
+    let file_name = file.file_name().ok_or("no file name")?.to_string_lossy().to_string();
+    let reader = fs::File::open(file)?;
+    let size = reader.metadata()?.len();
+    attach_reader(item.id, &file_name, size, io::BufReader::new(reader), mc)
+}
+
+/// Stores data under a name taken from an import, which is reduced to a plain file name first.
+pub fn attach_bytes(item_id: i32, file_name: &str, data: &[u8], mc: &MagicCrypt256) -> Result<models::Attachment, Box<dyn Error>> {
+    attach_reader(item_id, &safe_file_name(file_name)?, data.len() as u64, data, mc)
+}
+
+/// Decrypts the attachment chunk by chunk into a writer.
+pub fn write_to(attachment: &models::Attachment, writer: &mut impl Write, mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    for chunk_index in attachment_actions::find_chunk_indexes(attachment.id)? {
+        let chunk = attachment_actions::find_chunk(attachment.id, chunk_index)?;
+        writer.write_all(&mc.decrypt_bytes_to_bytes(&chunk.data)?)?;
+    }
+    Ok(())
+}
+
+pub fn read(attachment: &models::Attachment, mc: &MagicCrypt256) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::with_capacity(attachment.size as usize);
+    write_to(attachment, &mut data, mc)?;
+    Ok(data)
+}
+
+/// Writes the decrypted attachment to a file only the current user can read.
+pub fn save(attachment: &models::Attachment, file: &path::Path, mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    let mut writer = io::BufWriter::new(fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(file)?);
+    write_to(attachment, &mut writer, mc)?;
+    writer.flush()?;
+    info!("saved {} to {}", attachment.file_name, file.to_string_lossy());
+    Ok(())
+}
+
+/// Re-encrypts every attachment chunk after the master key changed, one chunk at a time.
+pub fn change_master_key(conn: &mut SqliteConnection, old_mc: &MagicCrypt256, new_mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    attachment_actions::reencrypt_all(conn, |data| Ok(new_mc.encrypt_bytes_to_bytes(&old_mc.decrypt_bytes_to_bytes(data)?)))
+}
+
+fn drag_dir() -> path::PathBuf {
+    std::env::temp_dir().join(format!("senoru-{}", std::process::id()))
+}
+
+/// Decrypts the attachment into a private temporary folder so it can be dragged to other applications.
+pub fn drag_file(attachment: &models::Attachment, mc: &MagicCrypt256) -> Result<path::PathBuf, Box<dyn Error>> {
+    let dir = drag_dir().join(attachment.id.to_string());
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    let file = dir.join(safe_file_name(&attachment.file_name)?);
+    save(attachment, &file, mc)?;
+    Ok(file)
+}
+
+/// Removes the files left behind by drags, the drop target may still be copying them until the window closes.
+pub fn remove_drag_files() {
+    let dir = drag_dir();
+    if dir.exists() {
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            warn!("failed to remove {}: {}", dir.to_string_lossy(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::item_actions;
+
+    #[test]
+    fn safe_file_names() {
+        assert_eq!(safe_file_name("report.pdf").unwrap(), "report.pdf");
+        assert_eq!(safe_file_name("../../.ssh/authorized_keys").unwrap(), "authorized_keys");
+        assert_eq!(safe_file_name("/etc/passwd").unwrap(), "passwd");
+        assert_eq!(safe_file_name("notes/").unwrap(), "notes");
+        for name in ["", ".", "..", "/", "a/..", "../"] {
+            assert!(safe_file_name(name).is_err(), "{:?} should be rejected", name);
+        }
+    }
+
+    #[test]
+    fn files_are_stored_in_chunks() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        item_actions::insert(&models::NewItem::new("attachment chunks test".into())).unwrap();
+        let item = item_actions::find_by_folder_and_title(None, "attachment chunks test").unwrap().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("large.bin");
+        let data: Vec<u8> = (0..CHUNK_SIZE + 10).map(|i| (i % 251) as u8).collect();
+        fs::write(&file, &data).unwrap();
+
+        let attachment = attach(&item, &file, &mc).unwrap();
+        assert_eq!((attachment.file_name.as_str(), attachment.size), ("large.bin", data.len() as i64));
+        assert_eq!(attachment_actions::find_chunk_indexes(attachment.id).unwrap(), vec![0, 1]);
+        assert_eq!(read(&attachment, &mc).unwrap(), data);
+        let saved = dir.path().join("saved.bin");
+        save(&attachment, &saved, &mc).unwrap();
+        assert_eq!(fs::read(&saved).unwrap(), data);
+        assert!(attach(&item, &file, &mc).is_err());
+
+        assert!(attachment_actions::delete(attachment.id).unwrap());
+        assert!(attachment_actions::find_chunk_indexes(attachment.id).unwrap().is_empty());
+        assert!(attachment_actions::find_by_item_id(item.id).unwrap().is_empty());
+    }
+}
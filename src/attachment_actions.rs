@@ -0,0 +1,92 @@
+use std::error::Error;
+use diesel::debug_query;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+
+use crate::db;
+use crate::models;
+use crate::schema::{attachment_chunks, attachments};
+
+pub fn find_by_item_id(item_id: i32) -> Result<Vec<models::Attachment>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let attachments = attachments::table.filter(attachments::dsl::item_id.eq(item_id)).order(attachments::dsl::file_name);
+    debug!("{}", debug_query::<Sqlite, _>(&attachments));
+    let results = attachments.load::<models::Attachment>(&mut conn)?;
+    Ok(results)
+}
+
+/// The items that have at least one attachment.
+pub fn find_item_ids() -> Result<Vec<i32>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = attachments::table.select(attachments::dsl::item_id).distinct().load::<i32>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn find_by_file_name(item_id: i32, file_name: &str) -> Result<Option<models::Attachment>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let attachment = attachments::table.filter(attachments::dsl::item_id.eq(item_id)).filter(attachments::dsl::file_name.eq(file_name));
+    debug!("{}", debug_query::<Sqlite, _>(&attachment));
+    let results = attachment.first::<models::Attachment>(&mut conn).optional()?;
+    Ok(results)
+}
+
+/// Inserts the attachment and its encrypted chunks in one transaction, nothing is kept if a chunk fails.
+pub fn insert(new_attachment: &models::NewAttachment, chunks: impl Iterator<Item = Result<Vec<u8>, Box<dyn Error>>>) -> Result<models::Attachment, Box<dyn Error>> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    conn.transaction::<_, Box<dyn Error>, _>(|conn| {
+        diesel::insert_into(attachments::table).values(new_attachment).execute(conn)?;
+        let attachment = attachments::table
+            .filter(attachments::dsl::item_id.eq(new_attachment.item_id))
+            .filter(attachments::dsl::file_name.eq(&new_attachment.file_name))
+            .first::<models::Attachment>(conn)?;
+        for (chunk_index, data) in chunks.enumerate() {
+            let chunk = models::AttachmentChunk {
+                attachment_id: attachment.id,
+                chunk_index: chunk_index as i32,
+                data: data?,
+            };
+            diesel::insert_into(attachment_chunks::table).values(&chunk).execute(conn)?;
+        }
+        Ok(attachment)
+    })
+}
+
+pub fn find_chunk_indexes(attachment_id: i32) -> Result<Vec<i32>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = attachment_chunks::table
+        .filter(attachment_chunks::dsl::attachment_id.eq(attachment_id))
+        .order(attachment_chunks::dsl::chunk_index)
+        .select(attachment_chunks::dsl::chunk_index)
+        .load::<i32>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn find_chunk(attachment_id: i32, chunk_index: i32) -> Result<models::AttachmentChunk, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = attachment_chunks::table.find((attachment_id, chunk_index)).first::<models::AttachmentChunk>(&mut conn)?;
+    Ok(results)
+}
+
+/// Rewrites every chunk in the vault with `reencrypt`, loading them one at a time, on the caller's connection so it can be part of a larger transaction.
+pub fn reencrypt_all(conn: &mut SqliteConnection, reencrypt: impl Fn(&[u8]) -> Result<Vec<u8>, Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    let keys = attachment_chunks::table
+        .order((attachment_chunks::dsl::attachment_id, attachment_chunks::dsl::chunk_index))
+        .select((attachment_chunks::dsl::attachment_id, attachment_chunks::dsl::chunk_index))
+        .load::<(i32, i32)>(conn)?;
+    for key in keys {
+        let mut chunk = attachment_chunks::table.find(key).first::<models::AttachmentChunk>(conn)?;
+        chunk.data = reencrypt(&chunk.data)?;
+        diesel::update(attachment_chunks::table.find(key)).set(&chunk).execute(conn)?;
+    }
+    Ok(())
+}
+
+pub fn delete(id: i32) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    conn.immediate_transaction(|conn| {
+        diesel::delete(attachment_chunks::table.filter(attachment_chunks::dsl::attachment_id.eq(id))).execute(conn)?;
+        let num_deleted = diesel::delete(attachments::table.filter(attachments::dsl::id.eq(id))).execute(conn)?;
+        debug!("num_deleted: {}", num_deleted);
+        Ok(num_deleted == 1)
+    })
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use chrono::prelude::*;
+use magic_crypt::MagicCrypt256;
+use magic_crypt::MagicCryptTrait;
+use crate::history;
+use crate::item_actions;
+use crate::models;
+
+/// How long typing has to pause before the contents are saved.
+pub const IDLE_MILLISECONDS: u64 = 1000;
+
+/// How a save went, reported back from the saving thread.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Outcome {
+    Saved(i32),
+    Failed(i32, String),
+    /// The item was written elsewhere since this window read it, the contents edit is kept aside for [`Saver::take_conflict`].
+    Conflict(i32),
+    /// The item was written elsewhere since this window read it, the fields edit is kept aside for [`Saver::take_fields_conflict`].
+    FieldsConflict(i32),
+}
+
+impl Outcome {
+    pub fn item_id(&self) -> i32 {
+        match self {
+            Outcome::Saved(item_id) | Outcome::Failed(item_id, _) | Outcome::Conflict(item_id) | Outcome::FieldsConflict(item_id) => *item_id,
+        }
+    }
+}
+
+/// What an edit changes of an item.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Edit {
+    Contents(String),
+    Fields(Vec<models::Field>),
+}
+
+impl Edit {
+    fn is_fields(&self) -> bool {
+        matches!(self, Edit::Fields(_))
+    }
+}
+
+/// An edit that was not saved because the item changed under it.
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    /// The item as this window last read or wrote it, what both edits started from.
+    pub base: models::Item,
+    pub edit: Edit,
+}
+
+enum Job {
+    Save { item_id: i32, edit: Edit, mc: MagicCrypt256 },
+    /// Answered once every save handed over before it is done.
+    Wait(mpsc::Sender<()>),
+}
+
+/// Encrypts and writes item contents and fields on a thread of its own, one at a time and in the order they were handed over, so typing never waits on the database.
+pub struct Saver {
+    sender: mpsc::Sender<Job>,
+    /// Each item as this window last read or wrote it, still encrypted.
+    seen: Arc<Mutex<HashMap<i32, models::Item>>>,
+    /// By item, and whether the edit is of the fields.
+    conflicts: Arc<Mutex<HashMap<(i32, bool), Conflict>>>,
+}
+
+impl Saver {
+    /// `on_done` is called on the saving thread after each save.
+    pub fn start(on_done: impl Fn(Outcome) + Send + 'static) -> Saver {
+        let (sender, receiver) = mpsc::channel();
+        let seen = Arc::new(Mutex::new(HashMap::new()));
+        let conflicts = Arc::new(Mutex::new(HashMap::new()));
+        let (thread_seen, thread_conflicts) = (Arc::clone(&seen), Arc::clone(&conflicts));
+        thread::spawn(move || {
+            // runs until the saver is dropped
+            for job in receiver.iter() {
+                match job {
+                    Job::Save { item_id, edit, mc } => {
+                        let saved = match &edit {
+                            Edit::Contents(contents) => save(item_id, contents, &mc, &thread_seen),
+                            Edit::Fields(fields) => save_fields(item_id, fields, &mc, &thread_seen),
+                        };
+                        let outcome = match saved {
+                            Ok(Some(item)) => {
+                                thread_seen.lock().unwrap().insert(item_id, item);
+                                Outcome::Saved(item_id)
+                            }
+                            Ok(None) => {
+                                let is_fields = edit.is_fields();
+                                let base = thread_seen.lock().unwrap().get(&item_id).cloned();
+                                if let Some(base) = base {
+                                    // only the newest edit is kept, it has everything the older ones had
+                                    thread_conflicts.lock().unwrap().insert((item_id, is_fields), Conflict { base, edit });
+                                }
+                                match is_fields {
+                                    true => Outcome::FieldsConflict(item_id),
+                                    false => Outcome::Conflict(item_id),
+                                }
+                            }
+                            Err(e) => {
+                                warn!("failed to save item {}: {}", item_id, e);
+                                Outcome::Failed(item_id, e.to_string())
+                            }
+                        };
+                        on_done(outcome);
+                    }
+                    Job::Wait(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        Saver { sender, seen, conflicts }
+    }
+
+    /// Records the item as shown, later saves of it are written at its revision. An item read before the last save of it was written is older than what was seen, and is left out.
+    pub fn seen(&self, item: &models::Item) {
+        let mut seen = self.seen.lock().unwrap();
+        if seen.get(&item.id).is_none_or(|seen| seen.revision <= item.revision) {
+            seen.insert(item.id, item.clone());
+        }
+    }
+
+    /// The contents edit of the item that ran into a conflict, if any, handed over once.
+    pub fn take_conflict(&self, item_id: i32) -> Option<Conflict> {
+        self.conflicts.lock().unwrap().remove(&(item_id, false))
+    }
+
+    /// The fields edit of the item that ran into a conflict, if any, handed over once.
+    pub fn take_fields_conflict(&self, item_id: i32) -> Option<Conflict> {
+        self.conflicts.lock().unwrap().remove(&(item_id, true))
+    }
+
+    pub fn save(&self, item_id: i32, contents: String, mc: MagicCrypt256) {
+        self.send(item_id, Edit::Contents(contents), mc);
+    }
+
+    /// Writes the fields of the item after the contents handed over so far.
+    pub fn save_fields(&self, item_id: i32, fields: Vec<models::Field>, mc: MagicCrypt256) {
+        self.send(item_id, Edit::Fields(fields), mc);
+    }
+
+    fn send(&self, item_id: i32, edit: Edit, mc: MagicCrypt256) {
+        if self.sender.send(Job::Save { item_id, edit, mc }).is_err() {
+            warn!("the saving thread is gone, item {} was not saved", item_id);
+        }
+    }
+
+    /// Blocks until the saves handed over so far are written.
+    pub fn wait(&self) {
+        let (done, finished) = mpsc::channel();
+        if self.sender.send(Job::Wait(done)).is_ok() {
+            let _ = finished.recv();
+        }
+    }
+}
+
+/// The item as stored, to be written at the revision it was seen at. Whatever was written since moves the stored revision on, and the write then fails.
+fn read_at_seen_revision(item_id: i32, seen: &Mutex<HashMap<i32, models::Item>>) -> Result<(models::Item, i32), Box<dyn Error>> {
+    let mut item = item_actions::find_by_id(item_id)?.ok_or_else(|| format!("no item with id: {}", item_id))?;
+    let stored_revision = item.revision;
+    if let Some(base) = seen.lock().unwrap().get(&item_id) {
+        item.revision = base.revision;
+    }
+    Ok((item, stored_revision))
+}
+
+/// Encrypts the contents into the item and saves it, keeping a version in the history when one is due. The item is written at the revision it was seen at, so nothing written in between is lost. The saved item, or `None` when the item was written elsewhere since it was seen and nothing was written.
+pub fn save(item_id: i32, contents: &str, mc: &MagicCrypt256, seen: &Mutex<HashMap<i32, models::Item>>) -> Result<Option<models::Item>, Box<dyn Error>> {
+    let (mut item, stored_revision) = read_at_seen_revision(item_id, seen)?;
+    let encrypted = mc.encrypt_str_to_base64(contents);
+    // the encryption is deterministic, so unchanged contents encrypt the same
+    if item.revision == stored_revision && item.contents.as_deref() == Some(encrypted.as_str()) {
+        return Ok(Some(item));
+    }
+    item.contents = Some(encrypted);
+    item.date_last_modified = Utc::now().naive_utc();
+    if !history::update(&mut item, mc)? {
+        return Ok(None);
+    }
+    debug!("saved item {} at revision {}", item_id, item.revision);
+    Ok(Some(item))
+}
+
+/// Encrypts the fields into the item and saves it, like [`save`] does the contents. `None` when the item was written elsewhere since it was seen and nothing was written.
+pub fn save_fields(item_id: i32, fields: &[models::Field], mc: &MagicCrypt256, seen: &Mutex<HashMap<i32, models::Item>>) -> Result<Option<models::Item>, Box<dyn Error>> {
+    let (mut item, _) = read_at_seen_revision(item_id, seen)?;
+    item.encrypt_fields(mc, fields)?;
+    item.date_last_modified = Utc::now().naive_utc();
+    if !history::update(&mut item, mc)? {
+        return Ok(None);
+    }
+    debug!("saved the fields of item {} at revision {}", item_id, item.revision);
+    Ok(Some(item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn fields_are_saved_on_the_saving_thread() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let mut new_item = models::NewItem::new("autosave fields test".into());
+        new_item.contents = Some(mc.encrypt_str_to_base64("notes"));
+        item_actions::insert(&new_item).unwrap();
+        let shown = item_actions::find_by_folder_and_title(None, "autosave fields test").unwrap().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let saver = Saver::start(move |outcome| sender.send(outcome).unwrap());
+        saver.seen(&shown);
+
+        let mine = vec![models::Field::new(models::FieldKind::Text, "user", "mine")];
+        saver.save(shown.id, "more notes".into(), mc.clone());
+        saver.save_fields(shown.id, mine.clone(), mc.clone());
+        saver.wait();
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![Outcome::Saved(shown.id), Outcome::Saved(shown.id)]);
+        let saved = item_actions::find_by_id(shown.id).unwrap().unwrap();
+        assert_eq!((saved.decrypt_contents(&mc).unwrap(), saved.decrypt_fields(&mc).unwrap()), ("more notes".to_string(), mine.clone()));
+
+        // fields changed elsewhere are in the way, the edit is kept aside
+        let mut other = saved.clone();
+        other.encrypt_fields(&mc, &[models::Field::new(models::FieldKind::Text, "user", "theirs")]).unwrap();
+        assert!(item_actions::update(&mut other).unwrap());
+        let newer = vec![models::Field::new(models::FieldKind::Text, "user", "newer")];
+        saver.save_fields(shown.id, newer.clone(), mc.clone());
+        saver.wait();
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![Outcome::FieldsConflict(shown.id)]);
+        assert!(saver.take_conflict(shown.id).is_none());
+        assert_eq!(saver.take_fields_conflict(shown.id).unwrap().edit, Edit::Fields(newer));
+
+        // an item read before the saves is older than what was seen
+        saver.seen(&shown);
+        saver.seen(&other);
+        saver.save(shown.id, "even more notes".into(), mc.clone());
+        saver.wait();
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![Outcome::Saved(shown.id)]);
+
+        // any write elsewhere moves the revision on, a rename too
+        let mut renamed = item_actions::find_by_id(shown.id).unwrap().unwrap();
+        renamed.title = "autosave fields test renamed".into();
+        assert!(item_actions::update(&mut renamed).unwrap());
+        saver.save(shown.id, "even more notes".into(), mc.clone());
+        saver.wait();
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![Outcome::Conflict(shown.id)]);
+        assert_eq!(saver.take_conflict(shown.id).unwrap().base.title, "autosave fields test");
+        saver.seen(&renamed);
+        saver.save(shown.id, "even more notes".into(), mc.clone());
+        saver.wait();
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![Outcome::Saved(shown.id)]);
+    }
+}
@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+use magic_crypt::MagicCrypt256;
+use magic_crypt::MagicCryptTrait;
+use crate::content_cache;
+use crate::db;
+use crate::item_actions;
+use crate::models;
+use crate::query;
+use crate::search;
+
+/// How many items are inserted per transaction while filling the vault.
+const BATCH_SIZE: usize = 1000;
+
+/// How many items are selected, first uncached and then again from the cache.
+const SELECTIONS: usize = 1000;
+
+/// Adds generated logins and notes, roughly the size of real ones.
+fn fill(num_items: usize, mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    let mut batch = vec![];
+    for n in 0..num_items {
+        let mut new_item = models::NewItem::new(format!("bench item {:06}", n));
+        let contents = format!("notes for item {}\n", n).repeat(20);
+        new_item.contents = Some(mc.encrypt_str_to_base64(contents));
+        if n % 2 == 0 {
+            new_item.item_type = models::ItemType::Login.to_string();
+            let fields = vec![
+                models::Field::new(models::FieldKind::Username, "Username", format!("user{}", n).as_str()),
+                models::Field::new(models::FieldKind::Password, "Password", format!("pw-{}-{}", n, n * 7919 % 104729).as_str()),
+                models::Field::new(models::FieldKind::Url, "URL", format!("https://site{}.example.com", n % 500).as_str()),
+            ];
+            new_item.encrypt_fields(mc, &fields)?;
+        }
+        batch.push(new_item);
+        if batch.len() == BATCH_SIZE {
+            item_actions::insert_all(&batch)?;
+            batch.clear();
+        }
+    }
+    item_actions::insert_all(&batch)?;
+    Ok(())
+}
+
+/// Times what opening, browsing and searching the vault does, as the GUI does it.
+fn run(key: &str) -> Result<Vec<(String, Duration)>, Box<dyn Error>> {
+    let mut timings = vec![];
+    let mut time = |name: &str, started: Instant| timings.push((name.to_string(), started.elapsed()));
+
+    let started = Instant::now();
+    let mc = crate::unlock(key)?;
+    time("unlock, checking the key", started);
+
+    let started = Instant::now();
+    let items = item_actions::find_all(None)?;
+    time("list, loading full items", started);
+
+    let started = Instant::now();
+    item_actions::find_all_summaries()?;
+    time("list, loading titles and metadata", started);
+
+    let started = Instant::now();
+    let empty_query = query::Query::default();
+    query::Context::load(&mc, &search::SearchIndex::default(), &[&empty_query])?;
+    time("list, everything the item tree needs", started);
+
+    let started = Instant::now();
+    let search_index = search::SearchIndex::build(&mc)?;
+    let text_query = query::parse("site42")?;
+    text_query.find_items(&query::Context::load(&mc, &search_index, &[&text_query])?);
+    time("first search, indexing every item", started);
+
+    let step = (items.len() / SELECTIONS).max(1);
+    let item_ids: Vec<i32> = items.iter().step_by(step).map(|item| item.id).collect();
+    let mut cache = content_cache::ContentCache::new(item_ids.len());
+    let started = Instant::now();
+    for item_id in item_ids.iter() {
+        cache.get(*item_id, &mc)?;
+    }
+    time(format!("select {} items, read and decrypted", item_ids.len()).as_str(), started);
+
+    let started = Instant::now();
+    for item_id in item_ids.iter() {
+        cache.get(*item_id, &mc)?;
+    }
+    time(format!("select {} items, from the cache", item_ids.len()).as_str(), started);
+
+    Ok(timings)
+}
+
+/// Run with `cargo test --release -- --ignored --nocapture bench`, SENORU_BENCH_ITEMS sets how many items to add.
+#[test]
+#[ignore]
+fn bench() {
+    db::init_test_db();
+    let num_items = std::env::var("SENORU_BENCH_ITEMS").ok().and_then(|n| n.parse().ok()).unwrap_or(50000);
+    let mc = new_magic_crypt!("test", 256);
+    let started = Instant::now();
+    fill(num_items, &mc).unwrap();
+    println!("{:>10.3}s  fill the vault with {} items", started.elapsed().as_secs_f64(), num_items);
+    for (name, duration) in run("test").unwrap().iter() {
+        println!("{:>10.3}s  {}", duration.as_secs_f64(), name);
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path;
+use chrono::prelude::*;
+use serde::Deserialize;
+use crate::import::ImportedItem;
+use crate::models;
+
+const LOGIN: i32 = 1;
+const SECURE_NOTE: i32 = 2;
+const CARD: i32 = 3;
+const IDENTITY: i32 = 4;
+const SSH_KEY: i32 = 5;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Export {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<Folder>,
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Folder {
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    #[serde(rename = "type")]
+    item_type: i32,
+    name: String,
+    notes: Option<String>,
+    folder_id: Option<String>,
+    #[serde(default)]
+    fields: Vec<Field>,
+    login: Option<Login>,
+    card: Option<HashMap<String, Option<String>>>,
+    identity: Option<HashMap<String, Option<String>>>,
+    ssh_key: Option<SshKey>,
+    creation_date: Option<String>,
+    revision_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Field {
+    name: Option<String>,
+    value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Login {
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+    #[serde(default)]
+    uris: Vec<Uri>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Uri {
+    uri: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SshKey {
+    private_key: Option<String>,
+    public_key: Option<String>,
+    key_fingerprint: Option<String>,
+}
+
+const CARD_FIELDS: [(&str, &str); 4] = [
+    ("cardholderName", "Cardholder"),
+    ("brand", "Brand"),
+    ("number", "Number"),
+    ("code", "Security code"),
+];
+
+const IDENTITY_FIELDS: [(&str, &str); 18] = [
+    ("title", "Title"),
+    ("firstName", "First name"),
+    ("middleName", "Middle name"),
+    ("lastName", "Last name"),
+    ("company", "Company"),
+    ("email", "Email"),
+    ("phone", "Phone"),
+    ("address1", "Address"),
+    ("address2", "Address 2"),
+    ("address3", "Address 3"),
+    ("city", "City"),
+    ("state", "State"),
+    ("postalCode", "Postal code"),
+    ("country", "Country"),
+    ("ssn", "Social security number"),
+    ("username", "Username"),
+    ("passportNumber", "Passport number"),
+    ("licenseNumber", "License number"),
+];
+
+fn parse_date(date: &Option<String>) -> Option<NaiveDateTime> {
+    date.as_ref().and_then(|d| DateTime::parse_from_rfc3339(d).ok()).map(|d| d.naive_utc())
+}
+
+fn labelled_fields(values: &HashMap<String, Option<String>>, labels: &[(&str, &str)]) -> Vec<models::Field> {
+    labels
+        .iter()
+        .filter_map(|(key, label)| values.get(*key).cloned().flatten().filter(|v| !v.is_empty()).map(|v| models::Field::labelled(label.to_string(), v)))
+        .collect()
+}
+
+/// Bitwarden keeps the month and year of a card's expiry apart, senoru has a single `MM/YYYY` field.
+fn card_fields(card: &HashMap<String, Option<String>>) -> Vec<models::Field> {
+    let mut fields = labelled_fields(card, &CARD_FIELDS);
+    let value = |key: &str| card.get(key).cloned().flatten().filter(|v| !v.is_empty());
+    if let (Some(month), Some(year)) = (value("expMonth"), value("expYear")) {
+        fields.push(models::Field::new(models::FieldKind::Expiry, "Expiry", &format!("{:0>2}/{}", month, year)));
+    }
+    fields
+}
+
+/// Reads an unencrypted Bitwarden JSON export, folders become the item path.
+pub fn read(path: &path::Path) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+    let export: Export = serde_json::from_reader(fs::File::open(path)?)?;
+    if export.encrypted {
+        return Err("encrypted Bitwarden exports are not supported, export as unencrypted JSON".into());
+    }
+    let folders: HashMap<String, String> = export.folders.into_iter().map(|f| (f.id, f.name)).collect();
+
+    let mut imported_items = vec![];
+    for item in export.items.into_iter() {
+        let mut imported_item = ImportedItem {
+            path: item
+                .folder_id
+                .as_ref()
+                .and_then(|id| folders.get(id))
+                .map(|name| name.split('/').map(String::from).collect())
+                .unwrap_or_default(),
+            title: item.name,
+            notes: item.notes,
+            date_added: parse_date(&item.creation_date),
+            date_last_modified: parse_date(&item.revision_date),
+            ..Default::default()
+        };
+        match item.item_type {
+            LOGIN => {
+                if let Some(login) = item.login {
+                    imported_item.username = login.username;
+                    imported_item.password = login.password;
+                    let mut uris = login.uris.into_iter().filter_map(|u| u.uri);
+                    imported_item.url = uris.next();
+                    uris.for_each(|u| imported_item.fields.push(models::Field::labelled("URL".into(), u)));
+                    if let Some(totp) = login.totp {
+                        imported_item.fields.push(models::Field::labelled("TOTP".into(), totp));
+                    }
+                }
+            }
+            CARD => {
+                imported_item.item_type = Some(models::ItemType::Card);
+                imported_item.fields.extend(card_fields(&item.card.unwrap_or_default()));
+            }
+            IDENTITY => {
+                imported_item.item_type = Some(models::ItemType::Identity);
+                imported_item.fields.extend(labelled_fields(&item.identity.unwrap_or_default(), &IDENTITY_FIELDS));
+            }
+            SSH_KEY => {
+                if let Some(ssh_key) = item.ssh_key {
+                    imported_item.item_type = Some(models::ItemType::SshKey);
+                    imported_item.notes = ssh_key.private_key;
+                    debug!("public key: {:?}, fingerprint: {:?}", ssh_key.public_key, ssh_key.key_fingerprint);
+                }
+            }
+            SECURE_NOTE => {}
+            t => warn!("unknown Bitwarden item type {} for {}", t, imported_item.title),
+        }
+        for field in item.fields.into_iter() {
+            imported_item.fields.push(models::Field::labelled(field.name.unwrap_or_default(), field.value.unwrap_or_default()));
+        }
+        imported_items.push(imported_item);
+    }
+    info!("read {} items from {}", imported_items.len(), path.to_string_lossy());
+    Ok(imported_items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"{
+        "encrypted": false,
+        "folders": [{ "id": "f1", "name": "Work/AWS" }],
+        "items": [
+            {
+                "type": 1,
+                "name": "root",
+                "folderId": "f1",
+                "notes": "MFA on the YubiKey",
+                "login": {
+                    "username": "admin",
+                    "password": "hunter2",
+                    "totp": "otpauth://totp/aws?secret=JBSWY3DPEHPK3PXP",
+                    "uris": [{ "uri": "https://aws.amazon.com" }, { "uri": "https://console.aws.amazon.com" }]
+                },
+                "fields": [{ "name": "Account", "value": "1234" }],
+                "creationDate": "2026-01-02T03:04:05.000Z",
+                "revisionDate": "2026-02-03T04:05:06.000Z"
+            },
+            {
+                "type": 3,
+                "name": "visa",
+                "folderId": null,
+                "card": { "cardholderName": "Jo", "number": "4111", "code": "123", "expMonth": "3", "expYear": "2030", "brand": "" }
+            },
+            { "type": 2, "name": "note", "notes": "text" }
+        ]
+    }"#;
+
+    fn read_str(json: &str) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("bitwarden.json");
+        fs::write(&file, json).unwrap();
+        read(&file)
+    }
+
+    #[test]
+    fn read_login() {
+        let imported_items = read_str(EXPORT).unwrap();
+        assert_eq!(imported_items.len(), 3);
+        let login = &imported_items[0];
+        assert_eq!(login.path, vec!["Work".to_string(), "AWS".to_string()]);
+        assert_eq!(login.title, "root");
+        assert_eq!(login.username.as_deref(), Some("admin"));
+        assert_eq!(login.password.as_deref(), Some("hunter2"));
+        assert_eq!(login.url.as_deref(), Some("https://aws.amazon.com"));
+        assert_eq!(login.notes.as_deref(), Some("MFA on the YubiKey"));
+        assert_eq!(
+            login.fields,
+            vec![
+                models::Field::new(models::FieldKind::Url, "URL", "https://console.aws.amazon.com"),
+                models::Field::new(models::FieldKind::Totp, "TOTP", "otpauth://totp/aws?secret=JBSWY3DPEHPK3PXP"),
+                models::Field::new(models::FieldKind::Text, "Account", "1234"),
+            ]
+        );
+        assert_eq!(login.date_added, NaiveDate::from_ymd_opt(2026, 1, 2).unwrap().and_hms_opt(3, 4, 5));
+        assert_eq!(login.resolved_item_type(), models::ItemType::Login);
+    }
+
+    #[test]
+    fn read_card_and_note() {
+        let imported_items = read_str(EXPORT).unwrap();
+        let card = &imported_items[1];
+        assert!(card.path.is_empty());
+        assert_eq!(card.item_type, Some(models::ItemType::Card));
+        assert_eq!(
+            card.fields,
+            vec![
+                models::Field::new(models::FieldKind::Text, "Cardholder", "Jo"),
+                models::Field::new(models::FieldKind::CardNumber, "Number", "4111"),
+                models::Field::new(models::FieldKind::Password, "Security code", "123"),
+                models::Field::new(models::FieldKind::Expiry, "Expiry", "03/2030"),
+            ]
+        );
+        let note = &imported_items[2];
+        assert_eq!((note.resolved_item_type(), note.contents()), (models::ItemType::Note, "text".to_string()));
+    }
+
+    #[test]
+    fn read_refuses_encrypted_exports() {
+        assert!(read_str(r#"{ "encrypted": true, "items": [] }"#).is_err());
+    }
+}
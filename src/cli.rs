@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path;
+use chrono::prelude::*;
+use crate::attachment;
+use crate::attachment_actions;
+use crate::contact_actions;
+use crate::db;
+use crate::export;
+use crate::folders;
+use crate::history;
+use crate::history_actions;
+use crate::import;
+use crate::item_actions;
+use crate::models;
+use crate::otp;
+use crate::query;
+use crate::saved_search_actions;
+use crate::saved_searches;
+use crate::search;
+use crate::share;
+use crate::ssh_agent;
+use crate::tags;
+
+/// Asks for the master key, or reads it from the `--key-file` when one is given.
+fn prompt_master_key() -> Result<magic_crypt::MagicCrypt256, Box<dyn Error>> {
+    let key = match std::env::var_os("SENORU_KEY_FILE") {
+        Some(key_file) => fs::read_to_string(&key_file)?.lines().next().unwrap_or_default().to_string(),
+        None => rpassword::prompt_password("Master key: ")?,
+    };
+    crate::unlock(&key).map_err(|e| format!("Invalid key: {}", e).into())
+}
+
+pub fn ssh_agent(socket: &path::Path, confirm: bool) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let mut agent = ssh_agent::Agent::new(confirm);
+    agent.load_keys(&mc)?;
+    ssh_agent::serve(socket, agent)
+}
+
+pub fn import(path: &path::Path, format: Option<import::Format>, mut options: import::ReadOptions, on_conflict: import::OnConflict, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let format = format.unwrap_or_else(|| import::Format::from_path(path));
+    match format {
+        import::Format::Kdbx => options.password = Some(rpassword::prompt_password("KeePass password: ")?).filter(|p| !p.is_empty()),
+        import::Format::Archive => options.password = Some(rpassword::prompt_password("Archive passphrase: ")?),
+        _ => {}
+    }
+    let (imported_items, errors) = import::read(path, format, &options)?;
+    if dry_run {
+        import::plan(&imported_items, on_conflict)?.iter().for_each(|action| println!("{}", action));
+        errors.iter().for_each(|(line, e)| println!("error  {}: {}", line, e));
+        return Ok(());
+    }
+    let mut report = import::import_items(&imported_items, on_conflict, &mc)?;
+    report.errors.extend(errors);
+    println!("{}", report.summary());
+    Ok(())
+}
+
+pub fn export(path: &path::Path, format: export::Format, titles: &[String], recipients: Vec<String>, yes: bool) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let items = export::find_items(titles)?;
+    let mut options = export::WriteOptions { recipients, ..Default::default() };
+    match format {
+        export::Format::Archive | export::Format::Kdbx => {
+            options.password = rpassword::prompt_password("Export password: ")?;
+            if options.password.is_empty() || options.password != rpassword::prompt_password("Repeat export password: ")? {
+                return Err("passwords are empty or do not match".into());
+            }
+        }
+        export::Format::Json | export::Format::Plaintext if !yes => {
+            print!("Write {} decrypted items to {}? Anyone who can read them will see your secrets [y/N] ", items.len(), path.to_string_lossy());
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                return Err("plaintext export cancelled".into());
+            }
+        }
+        _ => {}
+    }
+    export::write(path, format, &items, &mc, &options)?;
+    println!("{} items were written to: {}", items.len(), path.to_string_lossy());
+    Ok(())
+}
+
+pub fn share(titles: &[String], names: &[String], output: Option<&path::Path>) -> Result<(), Box<dyn Error>> {
+    let mut contacts = vec![];
+    for name in names.iter() {
+        contacts.push(contact_actions::find_by_name(name)?.ok_or_else(|| format!("no contact named: {}", name))?);
+    }
+    let mc = prompt_master_key()?;
+    let items = export::find_items(titles)?;
+    let message = share::share(&items, &contacts, &mc)?;
+    match output {
+        Some(path) => {
+            fs::write(path, message)?;
+            eprintln!("{} items were written to: {}", items.len(), path.to_string_lossy());
+        }
+        None => print!("{}", message),
+    }
+    Ok(())
+}
+
+pub fn receive(path: Option<&path::Path>, on_conflict: import::OnConflict) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let message = match path {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut message = String::new();
+            io::stdin().read_to_string(&mut message)?;
+            message
+        }
+    };
+    let imported_items = share::receive(&message, &mc)?;
+    let report = import::import_items(&imported_items, on_conflict, &mc)?;
+    println!("{}", report.summary());
+    Ok(())
+}
+
+pub fn list_contacts() -> Result<(), Box<dyn Error>> {
+    for contact in contact_actions::find_all()?.iter() {
+        println!("{}\t{}", contact.name, contact.public_key);
+    }
+    Ok(())
+}
+
+pub fn add_contact(name: &str, public_key: &str) -> Result<(), Box<dyn Error>> {
+    share::parse_public_key(public_key)?;
+    contact_actions::insert(&models::NewContact::new(name.to_string(), public_key.trim().to_string()))?;
+    Ok(())
+}
+
+pub fn remove_contact(name: &str) -> Result<(), Box<dyn Error>> {
+    if !contact_actions::delete_by_name(name)? {
+        return Err(format!("no contact named: {}", name).into());
+    }
+    Ok(())
+}
+
+pub fn public_key() -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    println!("{}", share::public_key(&mc)?);
+    Ok(())
+}
+
+pub fn otp(title: &str) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let mut item = find_item(title)?;
+    let mut fields = item.decrypt_fields(&mc)?;
+    let field = fields.iter_mut().find(|f| f.kind == models::FieldKind::Totp && !f.value.is_empty()).ok_or_else(|| format!("{} has no TOTP field", title))?;
+    let otp = field.value.parse::<otp::Otp>()?;
+    let (code, remaining) = otp.now();
+    match remaining {
+        Some(remaining) => eprintln!("valid for {}s", remaining),
+        None => {
+            // a counter based code can only be used once
+            if db::is_read_only() {
+                return Err(format!("{} has a counter based code, which can't be moved on while another senoru process has the vault open for writing", title).into());
+            }
+            field.value = otp::increment_counter(&field.value)?;
+            item.encrypt_fields(&mc, &fields)?;
+            if !history::update(&mut item, &mc)? {
+                return Err(format!("{} changed while its counter was moved on, try again", title).into());
+            }
+        }
+    }
+    println!("{}", code);
+    Ok(())
+}
+
+fn find_item(title: &str) -> Result<models::Item, Box<dyn Error>> {
+    match folders::find_item(title)? {
+        Some(item) if item.deleted_at.is_some() => Err(format!("{} is in the trash", title).into()),
+        Some(item) => Ok(item),
+        None => Err(format!("no item titled: {}", title).into()),
+    }
+}
+
+pub fn attach(title: &str, files: &[path::PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let item = find_item(title)?;
+    for file in files.iter() {
+        let attachment = attachment::attach(&item, file, &mc)?;
+        println!("attached {} ({} bytes, {})", attachment.file_name, attachment.size, attachment.mime_type);
+    }
+    if files.is_empty() {
+        for attachment in attachment_actions::find_by_item_id(item.id)?.iter() {
+            println!("{}\t{}\t{}", attachment.file_name, attachment.size, attachment.mime_type);
+        }
+    }
+    Ok(())
+}
+
+pub fn detach(title: &str, file_name: &str, output: Option<&path::Path>, keep: bool) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let item = find_item(title)?;
+    let attachment = attachment_actions::find_by_file_name(item.id, file_name)?.ok_or_else(|| format!("{} has no attachment named {}", title, file_name))?;
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None => path::PathBuf::from(attachment::safe_file_name(&attachment.file_name)?),
+    };
+    if output.exists() {
+        return Err(format!("{} already exists", output.to_string_lossy()).into());
+    }
+    attachment::save(&attachment, &output, &mc)?;
+    if !keep {
+        attachment_actions::delete(attachment.id)?;
+    }
+    println!("saved {} to {}", attachment.file_name, output.to_string_lossy());
+    Ok(())
+}
+
+pub fn history(title: &str, diff: Option<i32>, restore: Option<i32>) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let item = find_item(title)?;
+    let find_version = |id: i32| -> Result<models::ItemVersion, Box<dyn Error>> {
+        Ok(history_actions::find_by_id(id)?.filter(|v| v.item_id == item.id).ok_or_else(|| format!("{} has no version {}", title, id))?)
+    };
+    if let Some(id) = diff {
+        let version = find_version(id)?;
+        let old = history::text(&version.decrypt_contents(&mc)?, &version.decrypt_fields(&mc)?);
+        let new = history::text(&item.decrypt_contents(&mc)?, &item.decrypt_fields(&mc)?);
+        println!("{}", history::format_diff(&history::diff(&old, &new)));
+    } else if let Some(id) = restore {
+        let version = find_version(id)?;
+        history::restore(&item, &version)?;
+        println!("restored {} to the version of {}", title, history::format_date_saved(&version));
+    } else {
+        for version in history_actions::find_by_item_id(item.id)?.iter() {
+            let lines = version.decrypt_contents(&mc)?.lines().count();
+            println!("{}\t{}\t{} lines", version.id, history::format_date_saved(version), lines);
+        }
+    }
+    Ok(())
+}
+
+pub fn list(trash: bool, tag_names: &[String], saved: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let folder_tree = folders::FolderTree::load()?;
+    if let Some(name) = saved {
+        let mc = prompt_master_key()?;
+        return print_query_items(&saved_searches::load_query(name, &mc)?, tag_names, &mc);
+    }
+    if !trash {
+        // tags are encrypted, so filtering by them needs the key
+        let tag_index = match tag_names.is_empty() {
+            true => None,
+            false => Some(tags::TagIndex::load(&prompt_master_key()?)?),
+        };
+        let mut titles: Vec<String> = item_actions::find_all_summaries()?
+            .iter()
+            .filter(|item| tag_index.as_ref().is_none_or(|index| index.matches(item.id, tag_names)))
+            .map(|item| folder_tree.path_title(item.folder_id, &item.title))
+            .collect();
+        titles.sort();
+        titles.iter().for_each(|title| println!("{}", title));
+        return Ok(());
+    }
+    for item in item_actions::find_trash()?.iter() {
+        let deleted_at = item.deleted_at.map(|d| Local.from_utc_datetime(&d).format("%Y-%m-%d %H:%M:%S").to_string()).unwrap_or_default();
+        println!("{}\t{}", deleted_at, folder_tree.full_title(item));
+    }
+    Ok(())
+}
+
+pub fn search(text: &str, tag_names: &[String]) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    print_query_items(&query::parse(text)?, tag_names, &mc)
+}
+
+/// Prints the paths of the items matching the query and carrying the tags, closest matches first.
+fn print_query_items(query: &query::Query, tag_names: &[String], mc: &magic_crypt::MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    // decrypting every item for the index is only worth it when there are words to search for
+    let search_index = match query.has_text() {
+        true => search::SearchIndex::build(mc)?,
+        false => search::SearchIndex::default(),
+    };
+    let context = query::Context::load(mc, &search_index, &[query])?;
+    let items: HashMap<i32, &models::ItemSummary> = context.items().iter().map(|item| (item.id, item)).collect();
+    for item in query.find_items(&context).into_iter().filter(|id| context.tag_index().matches(*id, tag_names)).map(|id| items[&id]) {
+        println!("{}", context.folder_tree().path_title(item.folder_id, &item.title));
+    }
+    Ok(())
+}
+
+pub fn list_saved_searches() -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    for saved in saved_searches::list(&mc)?.iter() {
+        println!("{}\t{}", saved.name, saved.query);
+    }
+    Ok(())
+}
+
+pub fn save_search(name: &str, query: &str) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    saved_searches::save(name, query, &mc)?;
+    Ok(())
+}
+
+pub fn rename_saved_search(name: &str, new_name: &str) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let saved_search = saved_searches::find(name, &mc)?.ok_or_else(|| format!("no saved search named: {}", name))?;
+    saved_searches::rename(&saved_search, new_name, &mc)
+}
+
+pub fn remove_saved_search(name: &str) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let saved_search = saved_searches::find(name, &mc)?.ok_or_else(|| format!("no saved search named: {}", name))?;
+    saved_search_actions::delete(saved_search.id)?;
+    Ok(())
+}
+
+pub fn list_tags() -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    for (name, count) in tags::TagIndex::load(&mc)?.names().iter() {
+        println!("{}\t{}", name, count);
+    }
+    Ok(())
+}
+
+pub fn tag_items(tag: &str, titles: &[String]) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let item_ids = titles.iter().map(|title| find_item(title).map(|item| item.id)).collect::<Result<Vec<i32>, _>>()?;
+    let num_tagged = tags::tag_items(&item_ids, tag, &mc)?;
+    println!("tagged {} items with {}", num_tagged, tag);
+    Ok(())
+}
+
+pub fn untag_items(tag: &str, titles: &[String]) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let item_ids = titles.iter().map(|title| find_item(title).map(|item| item.id)).collect::<Result<Vec<i32>, _>>()?;
+    let num_untagged = tags::untag_items(&item_ids, tag, &mc)?;
+    println!("took {} off {} items", tag, num_untagged);
+    Ok(())
+}
+
+pub fn rename_tag(tag: &str, new_name: &str) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    tags::rename(tag, new_name, &mc)
+}
+
+pub fn merge_tags(tag_names: &[String], into: &str) -> Result<(), Box<dyn Error>> {
+    let mc = prompt_master_key()?;
+    let num_items = tags::merge(tag_names, into, &mc)?;
+    println!("{} items are now tagged {}", num_items, into);
+    Ok(())
+}
+
+pub fn move_item(title: &str, folder: &str) -> Result<(), Box<dyn Error>> {
+    let item = find_item(title)?;
+    let names: Vec<String> = folder.split(folders::SEPARATOR).map(str::trim).filter(|n| !n.is_empty()).map(String::from).collect();
+    folders::move_item(&item, folders::find_or_create(&names)?)?;
+    println!("{}", folders::full_title(&item_actions::find_by_id(item.id)?.ok_or("the item went missing")?)?);
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use std::error::Error;
+use diesel::debug_query;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+
+use crate::db;
+use crate::models;
+use crate::schema::{contacts, identities};
+
+pub fn find_all() -> Result<Vec<models::Contact>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = contacts::table.order(contacts::dsl::name).load::<models::Contact>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn find_by_name(name: &str) -> Result<Option<models::Contact>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let contact = contacts::table.filter(contacts::dsl::name.eq(name));
+    debug!("{}", debug_query::<Sqlite, _>(&contact));
+    let results = contact.first::<models::Contact>(&mut conn).optional()?;
+    Ok(results)
+}
+
+pub fn insert(contact: &models::NewContact) -> Result<(), diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let insert = diesel::insert_into(contacts::table).values(contact);
+    debug!("{}", debug_query::<Sqlite, _>(&insert));
+    insert.execute(&mut conn)?;
+    Ok(())
+}
+
+pub fn delete_by_name(name: &str) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let delete = diesel::delete(contacts::table.filter(contacts::dsl::name.eq(name)));
+    debug!("{}", debug_query::<Sqlite, _>(&delete));
+    let num_deleted = delete.execute(&mut conn)?;
+    debug!("num_deleted: {}", num_deleted);
+    Ok(num_deleted == 1)
+}
+
+pub fn find_identity() -> Result<Option<models::Identity>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = identities::table.order(identities::dsl::id).first::<models::Identity>(&mut conn).optional()?;
+    Ok(results)
+}
+
+pub fn insert_identity(identity: &models::NewIdentity) -> Result<(), diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    diesel::insert_into(identities::table).values(identity).execute(&mut conn)?;
+    Ok(())
+}
+
+/// Rewrites the vault identity with `reencrypt`, if there is one, on the caller's connection so it can be part of a larger transaction.
+pub fn reencrypt_identity(conn: &mut SqliteConnection, reencrypt: impl Fn(&mut models::Identity) -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    if let Some(mut identity) = identities::table.order(identities::dsl::id).first::<models::Identity>(conn).optional()? {
+        reencrypt(&mut identity)?;
+        diesel::update(identities::table.filter(identities::dsl::id.eq(identity.id))).set(&identity).execute(conn)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use magic_crypt::MagicCrypt256;
+use crate::item_actions;
+use crate::models;
+
+/// How many decrypted items are kept, enough to flip between recent items without holding a large vault in memory.
+pub const CAPACITY: usize = 256;
+
+/// An item with its contents and fields decrypted.
+#[derive(Clone, Debug)]
+pub struct DecryptedItem {
+    pub item: models::Item,
+    pub contents: String,
+    pub fields: Vec<models::Field>,
+}
+
+/// The most recently shown items, decrypted, so selecting one again reads neither the database nor decrypts. Whatever writes an item removes it, locking clears it all.
+pub struct ContentCache {
+    capacity: usize,
+    items: HashMap<i32, DecryptedItem>,
+    /// Least recently used first.
+    order: VecDeque<i32>,
+}
+
+impl Default for ContentCache {
+    fn default() -> Self {
+        ContentCache::new(CAPACITY)
+    }
+}
+
+impl ContentCache {
+    pub fn new(capacity: usize) -> ContentCache {
+        ContentCache {
+            capacity,
+            items: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// The decrypted item, from the cache or else read and decrypted, `None` when there's no item with that id.
+    pub fn get(&mut self, item_id: i32, mc: &MagicCrypt256) -> Result<Option<DecryptedItem>, Box<dyn Error>> {
+        if let Some(decrypted) = self.items.get(&item_id).cloned() {
+            self.touch(item_id);
+            return Ok(Some(decrypted));
+        }
+        let item = match item_actions::find_by_id(item_id)? {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let decrypted = DecryptedItem {
+            contents: item.decrypt_contents(mc)?,
+            fields: item.decrypt_fields(mc)?,
+            item,
+        };
+        self.insert(decrypted.clone());
+        Ok(Some(decrypted))
+    }
+
+    fn insert(&mut self, decrypted: DecryptedItem) {
+        let item_id = decrypted.item.id;
+        if self.items.insert(item_id, decrypted).is_some() {
+            self.touch(item_id);
+            return;
+        }
+        self.order.push_back(item_id);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.items.remove(&oldest);
+            }
+        }
+    }
+
+    fn touch(&mut self, item_id: i32) {
+        self.order.retain(|id| *id != item_id);
+        self.order.push_back(item_id);
+    }
+
+    /// Drops an item after it changed.
+    pub fn remove(&mut self, item_id: i32) {
+        if self.items.remove(&item_id).is_some() {
+            self.order.retain(|id| *id != item_id);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use magic_crypt::MagicCryptTrait;
+    use crate::db;
+
+    fn item(title: &str, mc: &MagicCrypt256) -> i32 {
+        let mut new_item = models::NewItem::new(title.into());
+        new_item.contents = Some(mc.encrypt_str_to_base64(title));
+        item_actions::insert(&new_item).unwrap();
+        item_actions::find_by_folder_and_title(None, title).unwrap().unwrap().id
+    }
+
+    fn cached(cache: &ContentCache) -> Vec<i32> {
+        cache.order.iter().copied().collect()
+    }
+
+    #[test]
+    fn least_recently_used_items_are_dropped() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let (a, b, c) = (item("cache test a", &mc), item("cache test b", &mc), item("cache test c", &mc));
+        let mut cache = ContentCache::new(2);
+        assert_eq!(cache.get(a, &mc).unwrap().unwrap().contents, "cache test a");
+        cache.get(b, &mc).unwrap();
+        // reading a again makes b the oldest
+        cache.get(a, &mc).unwrap();
+        assert_eq!(cached(&cache), vec![b, a]);
+        cache.get(c, &mc).unwrap();
+        assert_eq!(cached(&cache), vec![a, c]);
+        assert!(!cache.items.contains_key(&b));
+
+        // putting an item back counts as a use too, and doesn't drop anything
+        let decrypted = cache.items[&a].clone();
+        cache.insert(decrypted);
+        assert_eq!(cached(&cache), vec![c, a]);
+        assert_eq!(cache.items.len(), 2);
+
+        cache.remove(a);
+        assert_eq!(cached(&cache), vec![c]);
+        assert!(!cache.items.contains_key(&a));
+        cache.remove(a);
+        assert!(cache.get(-1, &mc).unwrap().is_none());
+        assert_eq!(cached(&cache), vec![c]);
+
+        cache.clear();
+        assert!(cache.items.is_empty() && cache.order.is_empty());
+    }
+}
@@ -0,0 +1,318 @@
+use std::path;
+use std::str::FromStr;
+use chrono::prelude::*;
+use crate::import::{ImportedItem, ReadResult};
+use crate::models;
+
+/// A senoru field a CSV column can be mapped onto, anything unknown becomes a custom field.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Target {
+    Title,
+    Username,
+    Password,
+    Url,
+    Notes,
+    Group,
+    Totp,
+    Created,
+    Modified,
+    Field(String),
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" => Err("empty field name".into()),
+            "title" => Ok(Target::Title),
+            "username" => Ok(Target::Username),
+            "password" => Ok(Target::Password),
+            "url" => Ok(Target::Url),
+            "notes" => Ok(Target::Notes),
+            "group" => Ok(Target::Group),
+            "totp" => Ok(Target::Totp),
+            "created" => Ok(Target::Created),
+            "modified" => Ok(Target::Modified),
+            field => Ok(Target::Field(field.to_string())),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Preset {
+    Chrome,
+    Firefox,
+    Lastpass,
+    Keepassxc,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 4] = [Preset::Chrome, Preset::Firefox, Preset::Lastpass, Preset::Keepassxc];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Chrome => "Chrome / Chromium",
+            Preset::Firefox => "Firefox",
+            Preset::Lastpass => "LastPass",
+            Preset::Keepassxc => "KeePassXC",
+        }
+    }
+
+    fn columns(&self) -> Vec<(Target, &'static str)> {
+        match self {
+            Preset::Chrome => vec![(Target::Title, "name"), (Target::Url, "url"), (Target::Username, "username"), (Target::Password, "password"), (Target::Notes, "note")],
+            Preset::Firefox => vec![
+                (Target::Url, "url"),
+                (Target::Username, "username"),
+                (Target::Password, "password"),
+                (Target::Created, "timeCreated"),
+                (Target::Modified, "timePasswordChanged"),
+            ],
+            Preset::Lastpass => vec![
+                (Target::Url, "url"),
+                (Target::Username, "username"),
+                (Target::Password, "password"),
+                (Target::Totp, "totp"),
+                (Target::Notes, "extra"),
+                (Target::Title, "name"),
+                (Target::Group, "grouping"),
+            ],
+            Preset::Keepassxc => vec![
+                (Target::Group, "Group"),
+                (Target::Title, "Title"),
+                (Target::Username, "Username"),
+                (Target::Password, "Password"),
+                (Target::Url, "URL"),
+                (Target::Notes, "Notes"),
+                (Target::Totp, "TOTP"),
+                (Target::Modified, "Last Modified"),
+                (Target::Created, "Created"),
+            ],
+        }
+    }
+
+    /// Finds the preset whose columns all appear in the header, notes and TOTP may be missing.
+    pub fn detect(headers: &[String]) -> Option<Preset> {
+        // KeePassXC and LastPass are checked first, their headers are supersets of the Chrome one
+        [Preset::Keepassxc, Preset::Lastpass, Preset::Firefox, Preset::Chrome].into_iter().find(|p| {
+            p.columns()
+                .iter()
+                .filter(|(t, _)| !matches!(t, Target::Notes | Target::Totp))
+                .all(|(_, c)| headers.iter().any(|h| h == c))
+        })
+    }
+}
+
+/// Which column feeds which field, given on the command line as `title=Name,username=Login,password=Pass`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Mapping {
+    pub columns: Vec<(Target, String)>,
+    /// Columns that are not mapped are kept as custom fields rather than dropped.
+    pub keep_unmapped: bool,
+}
+
+impl From<Preset> for Mapping {
+    fn from(preset: Preset) -> Self {
+        Mapping {
+            columns: preset.columns().into_iter().map(|(t, c)| (t, c.to_string())).collect(),
+            keep_unmapped: false,
+        }
+    }
+}
+
+impl FromStr for Mapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut columns = vec![];
+        for pair in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let (target, column) = pair.split_once('=').ok_or_else(|| format!("expected field=column, got: {}", pair))?;
+            columns.push((target.parse::<Target>()?, column.trim().to_string()));
+        }
+        if columns.is_empty() {
+            return Err("the mapping is empty".into());
+        }
+        Ok(Mapping { columns, keep_unmapped: true })
+    }
+}
+
+fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    if let Ok(number) = value.parse::<i64>() {
+        // Firefox writes milliseconds since the epoch
+        let seconds = if number > 100_000_000_000 { number / 1000 } else { number };
+        return DateTime::from_timestamp(seconds, 0).map(|d| d.naive_utc());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|d| d.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S"))
+        .ok()
+}
+
+fn title_from_url(url: &str) -> Option<String> {
+    let without_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    without_scheme.split(['/', '?', '#']).next().filter(|h| !h.is_empty()).map(String::from)
+}
+
+/// Reads a CSV export, returning the items and the line number and reason of every row that could not be used.
+pub fn read(path: &path::Path, mapping: Option<&Mapping>) -> ReadResult {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+    let headers: Vec<String> = reader.headers()?.iter().map(|h| h.trim_start_matches('\u{feff}').to_string()).collect();
+    let mapping = match mapping {
+        Some(m) => m.clone(),
+        None => Mapping::from(Preset::detect(&headers).ok_or("unknown CSV layout, choose a preset or give a column mapping")?),
+    };
+    debug!("mapping: {:?}", mapping);
+
+    let mut indexes: Vec<(Target, usize)> = vec![];
+    for (target, column) in mapping.columns.iter() {
+        match headers.iter().position(|h| h == column) {
+            Some(i) => indexes.push((target.clone(), i)),
+            None if matches!(target, Target::Notes | Target::Created | Target::Modified | Target::Totp) => {}
+            None => return Err(format!("column not found: {}", column).into()),
+        }
+    }
+
+    let mut imported_items = vec![];
+    let mut errors = vec![];
+    for record in reader.records() {
+        let record = match record {
+            Ok(r) => r,
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or_default();
+                errors.push((format!("line {}", line), e.to_string()));
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        if record.len() != headers.len() {
+            errors.push((format!("line {}", line), format!("expected {} columns, found {}", headers.len(), record.len())));
+            continue;
+        }
+
+        let mut imported_item = ImportedItem::default();
+        for (target, i) in indexes.iter() {
+            let value = record.get(*i).unwrap_or_default().to_string();
+            if value.is_empty() {
+                continue;
+            }
+            match target {
+                Target::Title => imported_item.title = value,
+                Target::Username => imported_item.username = Some(value),
+                Target::Password => imported_item.password = Some(value),
+                Target::Url => imported_item.url = Some(value),
+                Target::Notes => imported_item.notes = Some(value),
+                Target::Group => imported_item.path = value.split(['/', '\\']).filter(|g| !g.is_empty() && *g != "Root").map(String::from).collect(),
+                Target::Totp => imported_item.fields.push(models::Field::labelled("TOTP".into(), value)),
+                Target::Created => imported_item.date_added = parse_date(&value),
+                Target::Modified => imported_item.date_last_modified = parse_date(&value),
+                Target::Field(name) => imported_item.fields.push(models::Field::labelled(name.clone(), value)),
+            }
+        }
+        if mapping.keep_unmapped {
+            for (i, header) in headers.iter().enumerate() {
+                if !indexes.iter().any(|(_, j)| *j == i) && !record[i].is_empty() {
+                    imported_item.fields.push(models::Field::labelled(header.clone(), record[i].to_string()));
+                }
+            }
+        }
+
+        if imported_item.title.is_empty() {
+            match imported_item.url.as_deref().and_then(title_from_url).or_else(|| imported_item.username.clone()) {
+                Some(title) => imported_item.title = title,
+                None => {
+                    errors.push((format!("line {}", line), "no title, url or username".into()));
+                    continue;
+                }
+            }
+        }
+        imported_items.push(imported_item);
+    }
+    info!("read {} rows from {}, {} rejected", imported_items.len(), path.to_string_lossy(), errors.len());
+    Ok((imported_items, errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_str(csv: &str, mapping: Option<&Mapping>) -> ReadResult {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("export.csv");
+        std::fs::write(&file, csv).unwrap();
+        read(&file, mapping)
+    }
+
+    #[test]
+    fn parse_mapping() {
+        let mapping = "title=Name, password=Pass,Account number=acct".parse::<Mapping>().unwrap();
+        assert_eq!(
+            mapping.columns,
+            vec![(Target::Title, "Name".to_string()), (Target::Password, "Pass".to_string()), (Target::Field("Account number".into()), "acct".to_string())]
+        );
+        assert!(mapping.keep_unmapped);
+        assert!("title".parse::<Mapping>().is_err());
+        assert!("=Name".parse::<Mapping>().is_err());
+        assert!(",".parse::<Mapping>().is_err());
+    }
+
+    #[test]
+    fn detect_presets() {
+        let headers = |h: &str| h.split(',').map(String::from).collect::<Vec<String>>();
+        assert_eq!(Preset::detect(&headers("name,url,username,password,note")), Some(Preset::Chrome));
+        assert_eq!(Preset::detect(&headers("name,url,username,password")), Some(Preset::Chrome));
+        assert_eq!(Preset::detect(&headers("url,username,password,totp,extra,name,grouping,fav")), Some(Preset::Lastpass));
+        assert_eq!(Preset::detect(&headers("url,username,password,extra,name,grouping,fav")), Some(Preset::Lastpass));
+        assert_eq!(
+            Preset::detect(&headers("url,username,password,httpRealm,formActionOrigin,guid,timeCreated,timeLastUsed,timePasswordChanged")),
+            Some(Preset::Firefox)
+        );
+        assert_eq!(Preset::detect(&headers("Group,Title,Username,Password,URL,Notes,TOTP,Icon,Last Modified,Created")), Some(Preset::Keepassxc));
+        assert_eq!(Preset::detect(&headers("a,b")), None);
+    }
+
+    #[test]
+    fn read_keepassxc() {
+        let csv = "\u{feff}Group,Title,Username,Password,URL,Notes,TOTP,Icon,Last Modified,Created\n\
+                   Root/Work/AWS,root,admin,hunter2,https://aws,\"two\nlines\",otpauth://totp/x?secret=AB,0,2026-02-03T04:05:06Z,2026-01-02T03:04:05Z\n\
+                   Root,,,,https://example.com/login?x=1,,,0,,\n\
+                   Root,,,,,,,0,,\n\
+                   Root,short\n";
+        let (imported_items, errors) = read_str(csv, None).unwrap();
+        assert_eq!(imported_items.len(), 2);
+        let root = &imported_items[0];
+        assert_eq!((root.path.clone(), root.title.as_str()), (vec!["Work".to_string(), "AWS".to_string()], "root"));
+        assert_eq!(root.username.as_deref(), Some("admin"));
+        assert_eq!(root.notes.as_deref(), Some("two\nlines"));
+        assert_eq!(root.fields, vec![models::Field::new(models::FieldKind::Totp, "TOTP", "otpauth://totp/x?secret=AB")]);
+        assert_eq!(root.date_added, NaiveDate::from_ymd_opt(2026, 1, 2).unwrap().and_hms_opt(3, 4, 5));
+        assert_eq!(imported_items[1].title, "example.com");
+        assert_eq!(errors.iter().map(|(line, _)| line.as_str()).collect::<Vec<&str>>(), vec!["line 5", "line 6"]);
+    }
+
+    #[test]
+    fn read_lastpass_without_totp() {
+        let csv = "url,username,password,extra,name,grouping,fav\nhttps://bank,me,secret,note,bank,Money/Cards,0\n";
+        let (imported_items, errors) = read_str(csv, None).unwrap();
+        assert!(errors.is_empty());
+        let bank = &imported_items[0];
+        assert_eq!((bank.path.clone(), bank.title.as_str()), (vec!["Money".to_string(), "Cards".to_string()], "bank"));
+        assert_eq!((bank.password.as_deref(), bank.notes.as_deref()), (Some("secret"), Some("note")));
+        assert!(bank.fields.is_empty());
+    }
+
+    #[test]
+    fn read_with_mapping() {
+        let csv = "Name,Pass,acct,extra\nbank,secret,1234,kept\n";
+        let mapping = "title=Name,password=Pass,Account=acct".parse::<Mapping>().unwrap();
+        let (imported_items, errors) = read_str(csv, Some(&mapping)).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(imported_items[0].password.as_deref(), Some("secret"));
+        assert_eq!(
+            imported_items[0].fields,
+            vec![models::Field::new(models::FieldKind::Text, "Account", "1234"), models::Field::new(models::FieldKind::Text, "extra", "kept")]
+        );
+        assert!(read_str(csv, Some(&"title=Missing".parse::<Mapping>().unwrap())).is_err());
+        assert!(read_str("a,b\n1,2\n", None).is_err());
+    }
+}
@@ -1,30 +1,102 @@
+use diesel::connection::SimpleConnection;
 use diesel::prelude::*;
-use diesel::r2d2::{self, ConnectionManager};
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection};
 use std::env;
 use std::error::Error;
 use std::path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
 
 pub type DbPool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
+/// Connections kept open, enough for the GUI, the saving thread and the SSH agent to read at the same time.
+const MAX_CONNECTIONS: u32 = 4;
+
+/// How long a write waits for another connection, in this process or another, to finish its own.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
 lazy_static! {
     pub static ref DB_POOL: DbPool = create_db_connection_pool();
 }
 
+/// Set before the first connection when another window holds the vault's write lock, or for commands that only read.
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Opens every connection read-only from now on, SQLite then refuses any change. Has to be called before the pool is first used.
+pub fn set_read_only(read_only: bool) {
+    READ_ONLY.store(read_only, Ordering::SeqCst);
+}
+
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::SeqCst)
+}
+
+/// Sets up every connection the pool opens. With the write-ahead log readers never block on the writer, or the writer on readers, so the GUI, the CLI and the SSH agent can share a vault. SQLite still lets only one connection write at a time, the others wait for it up to the busy timeout.
+#[derive(Debug)]
+struct ConnectionOptions {
+    busy_timeout: Duration,
+    read_only: bool,
+}
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        // the busy timeout goes first, switching to the write-ahead log has to wait for other connections too
+        conn.batch_execute(
+            format!(
+                "PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL; PRAGMA foreign_keys = ON; PRAGMA secure_delete = ON;",
+                self.busy_timeout.as_millis()
+            )
+            .as_str(),
+        )
+        .map_err(r2d2::Error::QueryError)?;
+        if self.read_only {
+            conn.batch_execute("PRAGMA query_only = ON;").map_err(r2d2::Error::QueryError)?;
+        }
+        Ok(())
+    }
+}
+
 pub fn create_db_connection_pool() -> DbPool {
     let senoru_db = env::var("SENORU_DB").unwrap();
     debug!("using SENORU_DB: {}", senoru_db);
     let connspec = path::PathBuf::new().join(senoru_db);
     let manager = ConnectionManager::<SqliteConnection>::new(connspec.to_string_lossy());
-    let pool = r2d2::Pool::builder().max_size(1).build(manager).expect("Failed to create pool.");
-    pool
+    r2d2::Pool::builder()
+        .max_size(MAX_CONNECTIONS)
+        .connection_customizer(Box::new(ConnectionOptions {
+            busy_timeout: BUSY_TIMEOUT,
+            read_only: is_read_only(),
+        }))
+        .build(manager)
+        .expect("Failed to create pool.")
 }
 
 pub fn init_db() -> Result<(), Box<dyn Error>> {
     let mut conn = DB_POOL.get().expect("failed to get db connection from pool");
+    if is_read_only() {
+        // whoever holds the write lock sets up or upgrades the vault, a new one can't even be checked without writing to it
+        if conn.has_pending_migration(MIGRATIONS).unwrap_or(true) {
+            return Err("the vault needs setting up or upgrading, close any other senoru window and open it in senoru or run a command that changes it first".into());
+        }
+        return Ok(());
+    }
+    // migrations copy tables to change them, dropping the old table must not cascade to the rows referring to it
+    conn.batch_execute("PRAGMA foreign_keys = OFF;")?;
     conn.run_pending_migrations(MIGRATIONS).unwrap();
+    conn.batch_execute("PRAGMA foreign_keys = ON;")?;
     Ok(())
 }
 
+/// Points the pool at a new vault in a temporary directory and sets it up, once for every test in the process, as there is only the one pool. Tests share the vault, so each uses titles of its own.
+#[cfg(test)]
+pub fn init_test_db() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        let dir = tempfile::tempdir().expect("failed to create a temporary directory").keep();
+        env::set_var("SENORU_DB", dir.join("senoru.db"));
+        init_db().expect("failed to set up the test vault");
+    });
+}
@@ -0,0 +1,94 @@
+use std::error::Error;
+use std::fs;
+use std::io::prelude::*;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path;
+use magic_crypt::MagicCrypt256;
+use crate::archive;
+use crate::folders;
+use crate::item_actions;
+use crate::kdbx;
+use crate::models;
+use crate::pass;
+
+#[derive(clap::ValueEnum, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Format {
+    /// Passphrase-encrypted senoru archive
+    Archive,
+    /// Unencrypted senoru JSON document, only written after confirmation
+    Json,
+    Kdbx,
+    Pass,
+    /// One decrypted file per item, only written after confirmation
+    Plaintext,
+}
+
+impl Format {
+    pub fn default_file_name(&self) -> &'static str {
+        match self {
+            Format::Archive => "senoru-export.senoru",
+            Format::Json => "senoru-export.json",
+            Format::Kdbx => "senoru.kdbx",
+            Format::Pass => "password-store",
+            Format::Plaintext => "senoru-export",
+        }
+    }
+}
+
+/// How the export is protected, a password for KeePass and archives and GPG recipients for a password store.
+#[derive(Debug, Default, Clone)]
+pub struct WriteOptions {
+    pub password: String,
+    pub recipients: Vec<String>,
+}
+
+/// Looks up the items to export by their path, every item in the vault when none are given, ordered by path when all are exported.
+pub fn find_items(titles: &[String]) -> Result<Vec<models::Item>, Box<dyn Error>> {
+    let mut items = vec![];
+    if titles.is_empty() {
+        items = item_actions::find_all(None)?;
+    }
+    for title in titles.iter() {
+        match folders::find_item(title)?.filter(|i| i.deleted_at.is_none()) {
+            Some(item) => items.push(item),
+            None => return Err(format!("no item titled: {}", title).into()),
+        }
+    }
+    if titles.is_empty() {
+        let folder_tree = folders::FolderTree::load()?;
+        items.sort_by_cached_key(|item| folder_tree.full_title(item));
+    }
+    Ok(items)
+}
+
+pub fn write(path: &path::Path, format: Format, items: &[models::Item], mc: &MagicCrypt256, options: &WriteOptions) -> Result<(), Box<dyn Error>> {
+    match format {
+        Format::Archive => archive::write(path, items, mc, &options.password),
+        Format::Json => archive::write_json(path, items, mc),
+        Format::Kdbx => kdbx::write(path, items, mc, &options.password),
+        Format::Pass => pass::write(path, items, mc, &options.recipients),
+        Format::Plaintext => write_plaintext(path, items, mc),
+    }
+}
+
+/// Writes every item decrypted into its own file, readable only by the current user.
+fn write_plaintext(dir: &path::Path, items: &[models::Item], mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    let folder_tree = folders::FolderTree::load()?;
+    for item in items.iter() {
+        let full_title = folder_tree.full_title(item);
+        let parts: Vec<&str> = full_title.split('/').filter(|p| !p.is_empty() && *p != "." && *p != "..").collect();
+        let output_file = parts.iter().fold(dir.to_path_buf(), |p, f| p.join(f));
+        if let Some(parent) = output_file.parent() {
+            fs::DirBuilder::new().recursive(true).mode(0o700).create(parent)?;
+        }
+        let mut file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&output_file)?;
+        let fields = models::format_fields(&item.decrypt_fields(mc)?);
+        if !fields.is_empty() {
+            file.write_all(format!("{}\n\n", fields).as_bytes())?;
+        }
+        file.write_all(item.decrypt_contents(mc)?.as_bytes())?;
+    }
+    info!("wrote {} plaintext files to {}", items.len(), dir.to_string_lossy());
+    Ok(())
+}
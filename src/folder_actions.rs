@@ -0,0 +1,77 @@
+use diesel::debug_query;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+
+use crate::db;
+use crate::models;
+use crate::schema::folders;
+use crate::schema::path_title_items;
+
+pub fn find_all() -> Result<Vec<models::Folder>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = folders::table.order(folders::dsl::name).load::<models::Folder>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn find_by_id(id: i32) -> Result<Option<models::Folder>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = folders::table.find(id).first::<models::Folder>(&mut conn).optional()?;
+    Ok(results)
+}
+
+/// The folder named `name` directly inside `parent_id`, or at the top level for `None`.
+pub fn find_child(parent_id: Option<i32>, name: &str) -> Result<Option<models::Folder>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let query = folders::table.filter(folders::dsl::name.eq(name)).into_boxed();
+    let query = match parent_id {
+        Some(parent_id) => query.filter(folders::dsl::parent_id.eq(parent_id)),
+        None => query.filter(folders::dsl::parent_id.is_null()),
+    };
+    debug!("{}", debug_query::<Sqlite, _>(&query));
+    let results = query.first::<models::Folder>(&mut conn).optional()?;
+    Ok(results)
+}
+
+pub fn find_children(parent_id: i32) -> Result<Vec<models::Folder>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = folders::table.filter(folders::dsl::parent_id.eq(parent_id)).order(folders::dsl::name).load::<models::Folder>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn insert(new_folder: &models::NewFolder) -> Result<models::Folder, diesel::result::Error> {
+    {
+        let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+        let insert = diesel::insert_into(folders::table).values(new_folder);
+        debug!("{}", debug_query::<Sqlite, _>(&insert));
+        insert.execute(&mut conn)?;
+    }
+    find_child(new_folder.parent_id, &new_folder.name)?.ok_or(diesel::result::Error::NotFound)
+}
+
+pub fn update(folder: &models::Folder) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let update = diesel::update(folders::table.find(folder.id)).set(folder);
+    debug!("{}", debug_query::<Sqlite, _>(&update));
+    let num_updated = update.execute(&mut conn)?;
+    Ok(num_updated == 1)
+}
+
+pub fn delete(id: i32) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let num_deleted = diesel::delete(folders::table.find(id)).execute(&mut conn)?;
+    debug!("num_deleted: {}", num_deleted);
+    Ok(num_deleted == 1)
+}
+
+/// The items marked when the vault was upgraded to folders, their titles still to be filed into folders.
+pub fn find_path_title_item_ids() -> Result<Vec<i32>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = path_title_items::table.select(path_title_items::dsl::item_id).order(path_title_items::dsl::item_id).load::<i32>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn delete_path_title_item(item_id: i32) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let num_deleted = diesel::delete(path_title_items::table.find(item_id)).execute(&mut conn)?;
+    Ok(num_deleted == 1)
+}
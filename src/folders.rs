@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::error::Error;
+use crate::folder_actions;
+use crate::item_actions;
+use crate::models;
+
+/// Separates folder names from each other and from the item title in paths like `work/aws/root`.
+pub const SEPARATOR: char = '/';
+
+/// Splits a path into its folder names and the item title, empty parts are left out.
+pub fn split_path(path: &str) -> (Vec<String>, String) {
+    let mut names: Vec<String> = path.split(SEPARATOR).map(str::trim).filter(|n| !n.is_empty()).map(String::from).collect();
+    let title = names.pop().unwrap_or_default();
+    (names, title)
+}
+
+/// Every folder, loaded once so paths can be worked out without a query per item.
+pub struct FolderTree {
+    folders: HashMap<i32, models::Folder>,
+}
+
+impl FolderTree {
+    pub fn load() -> Result<FolderTree, Box<dyn Error>> {
+        let folders = folder_actions::find_all()?.into_iter().map(|f| (f.id, f)).collect();
+        Ok(FolderTree { folders })
+    }
+
+    pub fn folders(&self) -> impl Iterator<Item = &models::Folder> {
+        self.folders.values()
+    }
+
+    /// The folder names from the top level down to `folder_id`.
+    pub fn path(&self, folder_id: Option<i32>) -> Vec<String> {
+        let mut names = vec![];
+        let mut next = folder_id;
+        // bounded, so a broken parent chain can't loop forever
+        while let Some(folder) = next.and_then(|id| self.folders.get(&id)).filter(|_| names.len() <= self.folders.len()) {
+            names.push(folder.name.clone());
+            next = folder.parent_id;
+        }
+        names.reverse();
+        names
+    }
+
+    /// The item title prefixed with its folder path, the way the CLI, imports and exports name items.
+    pub fn full_title(&self, item: &models::Item) -> String {
+        self.path_title(item.folder_id, &item.title)
+    }
+
+    /// The title prefixed with the path of the folder, for whatever only has the title and folder of an item.
+    pub fn path_title(&self, folder_id: Option<i32>, title: &str) -> String {
+        let mut names = self.path(folder_id);
+        names.push(title.to_string());
+        names.join(&SEPARATOR.to_string())
+    }
+
+    /// Whether `folder_id` is `ancestor_id` or somewhere inside it.
+    pub fn is_inside(&self, folder_id: i32, ancestor_id: i32) -> bool {
+        let mut next = Some(folder_id);
+        let mut depth = 0;
+        while let Some(id) = next.filter(|_| depth <= self.folders.len()) {
+            if id == ancestor_id {
+                return true;
+            }
+            next = self.folders.get(&id).and_then(|f| f.parent_id);
+            depth += 1;
+        }
+        false
+    }
+}
+
+pub fn full_title(item: &models::Item) -> Result<String, Box<dyn Error>> {
+    Ok(FolderTree::load()?.full_title(item))
+}
+
+/// The folder at a path of names, created along with any missing parents, `None` for the top level.
+pub fn find_or_create(names: &[String]) -> Result<Option<i32>, Box<dyn Error>> {
+    let mut parent_id = None;
+    for name in names.iter() {
+        parent_id = Some(match folder_actions::find_child(parent_id, name)? {
+            Some(folder) => folder.id,
+            None => folder_actions::insert(&models::NewFolder::new(parent_id, name.clone()))?.id,
+        });
+    }
+    Ok(parent_id)
+}
+
+/// Looks an item up by its path, the trash included.
+pub fn find_item(path: &str) -> Result<Option<models::Item>, Box<dyn Error>> {
+    let (names, title) = split_path(path);
+    let mut folder_id = None;
+    for name in names.iter() {
+        match folder_actions::find_child(folder_id, name)? {
+            Some(folder) => folder_id = Some(folder.id),
+            // a top level title with the separator in it, as imports and renames keep them
+            None => return Ok(item_actions::find_by_folder_and_title(None, path)?),
+        }
+    }
+    Ok(item_actions::find_by_folder_and_title(folder_id, &title)?)
+}
+
+/// Looks an item up by the names of the folders it is in and its title, which may itself contain the separator.
+pub fn find_item_in(names: &[String], title: &str) -> Result<Option<models::Item>, Box<dyn Error>> {
+    let mut folder_id = None;
+    for name in names.iter() {
+        match folder_actions::find_child(folder_id, name)? {
+            Some(folder) => folder_id = Some(folder.id),
+            None => return Ok(None),
+        }
+    }
+    Ok(item_actions::find_by_folder_and_title(folder_id, title)?)
+}
+
+/// Files a new item into the folder at `names`, creating the folders it needs. The title is kept as it is, separators and all.
+pub fn place(new_item: &mut models::NewItem, names: &[String]) -> Result<(), Box<dyn Error>> {
+    if new_item.title.trim().is_empty() {
+        return Err(format!("no title for an item in {}", names.join(&SEPARATOR.to_string())).into());
+    }
+    new_item.folder_id = find_or_create(names)?;
+    Ok(())
+}
+
+/// Picks the next free "name (n)" among the items, or folders, directly inside a folder.
+pub fn unique_name(folder_id: Option<i32>, name: &str, for_folder: bool) -> Result<String, Box<dyn Error>> {
+    let taken = |n: &str| -> Result<bool, Box<dyn Error>> {
+        Ok(match for_folder {
+            true => folder_actions::find_child(folder_id, n)?.is_some(),
+            false => item_actions::find_by_folder_and_title(folder_id, n)?.is_some(),
+        })
+    };
+    if !taken(name)? {
+        return Ok(name.to_string());
+    }
+    for n in 2.. {
+        let candidate = format!("{} ({})", name, n);
+        if !taken(&candidate)? {
+            return Ok(candidate);
+        }
+    }
+    unreachable!()
+}
+
+/// Files the items saved before folders existed, titled like `Work/AWS/root`, into folders. This happens once, for the items the upgrade to folders marked; a title with the separator in it saved since is left as it is.
+pub fn migrate_path_titles() -> Result<usize, Box<dyn Error>> {
+    let mut num_moved = 0;
+    for item_id in folder_actions::find_path_title_item_ids()? {
+        // the mark goes first, an item that can't be moved now stays where it is for good
+        folder_actions::delete_path_title_item(item_id)?;
+        let mut item = match item_actions::find_by_id(item_id)? {
+            Some(item) if item.folder_id.is_none() => item,
+            _ => continue,
+        };
+        let (names, title) = split_path(&item.title);
+        if names.is_empty() || title.is_empty() {
+            continue;
+        }
+        let folder_id = find_or_create(&names)?;
+        if item_actions::find_by_folder_and_title(folder_id, &title)?.is_some() {
+            warn!("not moving {} into a folder, {} is already there", item.title, title);
+            continue;
+        }
+        item.folder_id = folder_id;
+        item.title = title;
+        if !item_actions::update(&mut item)? {
+            warn!("not moving {} into a folder, it changed in the meantime", item.title);
+            continue;
+        }
+        num_moved += 1;
+    }
+    if num_moved > 0 {
+        info!("moved {} items with a path in their title into folders", num_moved);
+    }
+    Ok(num_moved)
+}
+
+/// Moves an item into a folder, or to the top level.
+pub fn move_item(item: &models::Item, folder_id: Option<i32>) -> Result<(), Box<dyn Error>> {
+    if item.folder_id == folder_id {
+        return Ok(());
+    }
+    if item_actions::find_by_folder_and_title(folder_id, &item.title)?.is_some() {
+        return Err(format!("an item titled {} is already there", item.title).into());
+    }
+    item_actions::move_to_folder(&item.id, folder_id)?;
+    Ok(())
+}
+
+/// Moves a folder into another one, or to the top level, but never inside itself.
+pub fn move_folder(folder: &models::Folder, parent_id: Option<i32>) -> Result<(), Box<dyn Error>> {
+    if folder.parent_id == parent_id {
+        return Ok(());
+    }
+    if let Some(parent_id) = parent_id {
+        if FolderTree::load()?.is_inside(parent_id, folder.id) {
+            return Err(format!("{} can't be moved inside itself", folder.name).into());
+        }
+    }
+    if folder_actions::find_child(parent_id, &folder.name)?.is_some() {
+        return Err(format!("a folder named {} is already there", folder.name).into());
+    }
+    let mut folder = folder.clone();
+    folder.parent_id = parent_id;
+    folder_actions::update(&folder)?;
+    Ok(())
+}
+
+/// Deletes an empty folder, items of it still in the trash go to the top level in case they are restored.
+pub fn remove_folder(folder: &models::Folder) -> Result<(), Box<dyn Error>> {
+    let items = item_actions::find_by_folder_id(folder.id)?;
+    if !folder_actions::find_children(folder.id)?.is_empty() || items.iter().any(|i| i.deleted_at.is_none()) {
+        return Err(format!("{} is not empty", folder.name).into());
+    }
+    for item in items.iter() {
+        let mut item = item.clone();
+        item.title = unique_name(None, &item.title, false)?;
+        item.folder_id = None;
+        if !item_actions::update(&mut item)? {
+            return Err(format!("{} changed while {} was being removed", item.title, folder.name).into());
+        }
+    }
+    folder_actions::delete(folder.id)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn split_paths() {
+        assert_eq!(split_path("work/ aws /root"), (vec!["work".to_string(), "aws".to_string()], "root".to_string()));
+        assert_eq!(split_path("/root/"), (vec![], "root".to_string()));
+        assert_eq!(split_path(""), (vec![], String::new()));
+    }
+
+    #[test]
+    fn unique_names() {
+        db::init_test_db();
+        let folder_id = find_or_create(&["unique_name test".to_string()]).unwrap();
+        assert_eq!(unique_name(folder_id, "item", false).unwrap(), "item");
+        for title in ["item", "item (2)"] {
+            let mut new_item = models::NewItem::new(title.into());
+            place(&mut new_item, &["unique_name test".to_string()]).unwrap();
+            item_actions::insert(&new_item).unwrap();
+        }
+        assert_eq!(unique_name(folder_id, "item", false).unwrap(), "item (3)");
+        // items and folders are named apart, as are folders
+        assert_eq!(unique_name(folder_id, "item", true).unwrap(), "item");
+        assert_eq!(unique_name(None, "unique_name test", true).unwrap(), "unique_name test (2)");
+        assert_eq!(unique_name(None, "unique_name test", false).unwrap(), "unique_name test");
+    }
+
+    #[test]
+    fn place_keeps_separators_in_titles() {
+        db::init_test_db();
+        let names = vec!["place test".to_string(), "web".to_string()];
+        let mut new_item = models::NewItem::new("example.com/login".into());
+        place(&mut new_item, &names).unwrap();
+        item_actions::insert(&new_item).unwrap();
+        let item = find_item_in(&names, "example.com/login").unwrap().unwrap();
+        assert_eq!(full_title(&item).unwrap(), "place test/web/example.com/login");
+        assert!(find_item_in(&names[..1], "example.com/login").unwrap().is_none());
+        assert!(place(&mut models::NewItem::new(" ".into()), &names).is_err());
+    }
+
+    #[test]
+    fn path_titles_are_filed_once() {
+        use diesel::prelude::*;
+        db::init_test_db();
+        for title in ["migrate test/old/root", "migrate test/a/b"] {
+            item_actions::insert(&models::NewItem::new(title.into())).unwrap();
+        }
+        // only what the upgrade to folders marked is filed, as it was for a vault saved before folders existed
+        let old = item_actions::find_by_folder_and_title(None, "migrate test/old/root").unwrap().unwrap();
+        let mut conn = db::DB_POOL.get().unwrap();
+        diesel::sql_query(format!("INSERT INTO path_title_items (item_id) VALUES ({})", old.id)).execute(&mut conn).unwrap();
+        drop(conn);
+
+        // every start runs the migration, a restart must not move a title kept whole
+        for _ in 0..2 {
+            migrate_path_titles().unwrap();
+            assert_eq!(full_title(&item_actions::find_by_id(old.id).unwrap().unwrap()).unwrap(), "migrate test/old/root");
+            assert!(find_item_in(&["migrate test".to_string(), "old".to_string()], "root").unwrap().is_some());
+            let kept = item_actions::find_by_folder_and_title(None, "migrate test/a/b").unwrap().unwrap();
+            assert_eq!(find_item("migrate test/a/b").unwrap().unwrap().id, kept.id);
+        }
+        assert!(folder_actions::find_path_title_item_ids().unwrap().is_empty());
+    }
+}
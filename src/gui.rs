@@ -1,29 +1,73 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::error::Error;
-use std::fs;
-use std::io;
-use std::io::prelude::*;
+use std::path;
+use std::rc::Rc;
+use chrono::prelude::*;
 use gtk::prelude::*;
 use magic_crypt::MagicCryptTrait;
 use passwords::analyzer;
 use passwords::scorer;
+use crate::attachment;
+use crate::attachment_actions;
+use crate::autosave;
+use crate::contact_actions;
+use crate::content_cache;
+use crate::csv_import;
+use crate::db;
+use crate::directory;
+use crate::export;
+use crate::folder_actions;
+use crate::folders;
+use crate::history;
+use crate::history_actions;
+use crate::import;
 use crate::item_actions;
+use crate::master_key;
+use crate::merge;
 use crate::models;
+use crate::otp;
+use crate::query;
+use crate::saved_search_actions;
+use crate::saved_searches;
+use crate::search;
+use crate::share;
+use crate::tags;
+use crate::trash;
+use crate::vault_lock;
 
 pub fn launch(application: &gtk::Application, builder: &gtk::Builder) -> Result<(), Box<dyn Error>> {
     let main_window: gtk::Window = builder.object("main_window").unwrap();
     let main_window_item_title_tree_view: gtk::TreeView = builder.object("main_window_item_title_tree_view").unwrap();
 
     let item_store = create_item_store()?;
+    let autosave = Autosave::new(
+        builder.object("main_window_item_content_text_view").unwrap(),
+        builder.object("main_window_item_fields_grid").unwrap(),
+        builder.object("main_window_save_status_label").unwrap(),
+    );
 
-    connect_items(builder, &item_store, &main_window_item_title_tree_view)?;
-    connect_menu_items(builder, &main_window, &item_store, &main_window_item_title_tree_view)?;
+    connect_items(builder, &item_store, &main_window_item_title_tree_view, &autosave)?;
+    connect_menu_items(builder, &main_window, &item_store, &main_window_item_title_tree_view, &autosave)?;
     connect_about_dialog(builder)?;
-    connect_change_master_key_dialog(builder)?;
+    connect_change_master_key_dialog(builder, &autosave)?;
     connect_generate_password_dialog(&builder)?;
 
     main_window.set_application(Some(application));
 
-    main_window.connect_delete_event(glib::clone!(@weak main_window => @default-return Inhibit(false), move |_, _| {
+    if db::is_read_only() {
+        let read_only_info_bar: gtk::InfoBar = builder.object("main_window_read_only_info_bar").unwrap();
+        let read_only_info_bar_label: gtk::Label = builder.object("main_window_read_only_info_bar_label").unwrap();
+        let holder = env::var("SENORU_DB").ok().and_then(|db_path| vault_lock::holder(path::Path::new(&db_path)));
+        let by = holder.map(|pid| format!(" (process {})", pid)).unwrap_or_default();
+        read_only_info_bar_label.set_text(format!("Another senoru window has this vault open{}, so it is read-only here. Close that window and open the vault again to make changes.", by).as_str());
+        read_only_info_bar.show_all();
+    }
+
+    main_window.connect_delete_event(glib::clone!(@weak main_window, @strong autosave => @default-return Inhibit(false), move |_, _| {
+        autosave.flush_and_wait();
+        attachment::remove_drag_files();
         main_window.close();
         Inhibit(false)
     }));
@@ -32,57 +76,769 @@ pub fn launch(application: &gtk::Application, builder: &gtk::Builder) -> Result<
     Ok(())
 }
 
-fn create_item_store() -> Result<gtk::ListStore, Box<dyn Error>> {
-    let store = gtk::ListStore::new(&[glib::Type::STRING]);
-    let items = item_actions::find_all(None).expect("failed to get Items");
-    for item in items.iter() {
-        debug!("item: {:?}", item);
-        let value = glib::value::Value::from(&item.title);
-        store.set_value(&store.append(), 0u32, &value);
+/// Edits of the text view and the fields not saved yet. They are saved once typing pauses, when another row is selected and when the window closes, on the saving thread.
+struct Autosave {
+    saver: autosave::Saver,
+    text_view: gtk::TextView,
+    fields_grid: gtk::Grid,
+    status_label: gtk::Label,
+    /// The item the text view shows, `None` while it is being filled.
+    item_id: Cell<Option<i32>>,
+    dirty: Cell<bool>,
+    fields_dirty: Cell<bool>,
+    /// How many saves the saving thread hasn't reported back yet.
+    num_saving: Cell<usize>,
+    /// The newest edits of each item handed over and not reported back yet, shown when the item is selected again before they are written.
+    pending: RefCell<HashMap<i32, Pending>>,
+    timeout: RefCell<Option<glib::SourceId>>,
+}
+
+#[derive(Default)]
+struct Pending {
+    num_saving: usize,
+    contents: Option<String>,
+    fields: Option<Vec<models::Field>>,
+}
+
+impl Autosave {
+    fn new(text_view: gtk::TextView, fields_grid: gtk::Grid, status_label: gtk::Label) -> Rc<Autosave> {
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let saver = autosave::Saver::start(move |outcome| {
+            // the content cache is only touched on the main thread, see `saved`
+            if let autosave::Outcome::Saved(item_id) = outcome {
+                update_search_index(item_id);
+            }
+            let _ = sender.send(outcome);
+        });
+        let autosave = Rc::new(Autosave {
+            saver,
+            text_view,
+            fields_grid,
+            status_label,
+            item_id: Cell::new(None),
+            dirty: Cell::new(false),
+            fields_dirty: Cell::new(false),
+            num_saving: Cell::new(0),
+            pending: RefCell::new(HashMap::new()),
+            timeout: RefCell::new(None),
+        });
+        receiver.attach(None, glib::clone!(@weak autosave => @default-return glib::Continue(false), move |outcome| {
+            autosave.saved(outcome);
+            glib::Continue(true)
+        }));
+        let buffer = autosave.text_view.buffer().expect("Couldn't get buffer");
+        buffer.connect_changed(glib::clone!(@weak autosave => move |_| {
+            autosave.changed();
+        }));
+        autosave
+    }
+
+    /// Shows an item's contents, or nothing, without taking it for an edit. Edits of the item shown until now are handed over first.
+    fn load(&self, item: Option<&models::Item>, contents: &str) {
+        self.flush();
+        self.item_id.set(None);
+        self.text_view.buffer().expect("Couldn't get buffer").set_text(contents);
+        if let Some(item) = item {
+            self.saver.seen(item);
+        }
+        self.item_id.set(item.map(|item| item.id));
+        if self.num_saving.get() == 0 {
+            self.status_label.set_text("");
+        }
+    }
+
+    /// An item as read, with the edits of it that are still on their way to the database.
+    fn with_pending(&self, mut decrypted: content_cache::DecryptedItem) -> content_cache::DecryptedItem {
+        if let Some(pending) = self.pending.borrow().get(&decrypted.item.id) {
+            if let Some(contents) = pending.contents.as_ref() {
+                decrypted.contents = contents.clone();
+            }
+            if let Some(fields) = pending.fields.as_ref() {
+                decrypted.fields = fields.clone();
+            }
+        }
+        decrypted
+    }
+
+    fn changed(self: &Rc<Self>) {
+        if self.item_id.get().is_none() {
+            return;
+        }
+        self.dirty.set(true);
+        self.wait_for_pause();
+    }
+
+    fn fields_changed(self: &Rc<Self>) {
+        // a one-time password counter still moves on in a read-only vault, it just isn't kept
+        if self.item_id.get().is_none() || db::is_read_only() {
+            return;
+        }
+        self.fields_dirty.set(true);
+        self.wait_for_pause();
+    }
+
+    /// Starts the wait for a pause in typing over.
+    fn wait_for_pause(self: &Rc<Self>) {
+        self.status_label.set_text("Unsaved");
+        if let Some(source_id) = self.timeout.take() {
+            source_id.remove();
+        }
+        let source_id = glib::timeout_add_local(
+            std::time::Duration::from_millis(autosave::IDLE_MILLISECONDS),
+            glib::clone!(@weak self as autosave => @default-return glib::Continue(false), move || {
+                // the source ends with this call, so it mustn't be removed again
+                autosave.timeout.take();
+                autosave.flush();
+                glib::Continue(false)
+            }),
+        );
+        self.timeout.replace(Some(source_id));
+    }
+
+    /// Hands the unsaved edits over to the saving thread.
+    fn flush(&self) {
+        if let Some(source_id) = self.timeout.take() {
+            source_id.remove();
+        }
+        let item_id = match self.item_id.get() {
+            Some(item_id) => item_id,
+            None => return,
+        };
+        if self.dirty.replace(false) {
+            let buffer = self.text_view.buffer().expect("Couldn't get buffer");
+            let contents = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false).expect("failed to get content").to_string();
+            self.hand_over(item_id, autosave::Edit::Contents(contents));
+        }
+        if self.fields_dirty.replace(false) {
+            self.hand_over(item_id, autosave::Edit::Fields(grid_fields(&self.fields_grid)));
+        }
+    }
+
+    fn hand_over(&self, item_id: i32, edit: autosave::Edit) {
+        let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone().expect("failed to get magic_crypt");
+        self.num_saving.set(self.num_saving.get() + 1);
+        self.status_label.set_text("Saving…");
+        let mut pending = self.pending.borrow_mut();
+        let pending = pending.entry(item_id).or_default();
+        pending.num_saving += 1;
+        match edit {
+            autosave::Edit::Contents(contents) => {
+                pending.contents = Some(contents.clone());
+                self.saver.save(item_id, contents, mc);
+            }
+            autosave::Edit::Fields(fields) => {
+                pending.fields = Some(fields.clone());
+                self.saver.save_fields(item_id, fields, mc);
+            }
+        }
+    }
+
+    /// Saves the unsaved edits and waits for every save to be written, before the window closes or anything else reads the items.
+    fn flush_and_wait(&self) {
+        self.flush();
+        self.saver.wait();
+    }
+
+    /// Takes a save reported back by the saving thread. Once the last edit of an item handed over is written, the item is read from the database again.
+    fn saved(self: &Rc<Self>, outcome: autosave::Outcome) {
+        let item_id = outcome.item_id();
+        crate::APP_CORE.content_cache.lock().unwrap().remove(item_id);
+        {
+            let mut pending = self.pending.borrow_mut();
+            if let Some(p) = pending.get_mut(&item_id) {
+                p.num_saving -= 1;
+                if p.num_saving == 0 {
+                    pending.remove(&item_id);
+                }
+            }
+        }
+        self.num_saving.set(self.num_saving.get().saturating_sub(1));
+        match outcome {
+            autosave::Outcome::Failed(_, e) => self.status_label.set_text(format!("Not saved: {}", e).as_str()),
+            autosave::Outcome::Conflict(item_id) => self.resolve_conflict(item_id),
+            autosave::Outcome::FieldsConflict(item_id) => self.resolve_fields_conflict(item_id),
+            // later edits are still unsaved, or on their way
+            _ if self.dirty.get() || self.fields_dirty.get() || self.num_saving.get() > 0 => {}
+            autosave::Outcome::Saved(_) => self.status_label.set_text("Saved"),
+        }
+    }
+
+    /// Asks what to do with an edit that wasn't saved because the item was written elsewhere since it was shown: keep either version, or merge the two. When the contents weren't changed there, the edit is saved on top without asking.
+    fn resolve_conflict(self: &Rc<Self>, item_id: i32) {
+        // the newest edit that ran into the change is the one to resolve
+        self.flush_and_wait();
+        let conflict = match self.saver.take_conflict(item_id) {
+            Some(conflict) => conflict,
+            None => return self.status_label.set_text("Not saved: the item was changed elsewhere"),
+        };
+        let item = match item_actions::find_by_id(item_id).expect("failed to find Item by id") {
+            Some(item) => item,
+            None => return self.status_label.set_text("Not saved: the item was removed elsewhere"),
+        };
+        let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+        let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+        let theirs = item.decrypt_contents(mc_ref).expect("failed to decrypt contents");
+        let window = self.text_view.toplevel().and_then(|w| w.downcast::<gtk::Window>().ok());
+        let yours = match conflict.edit {
+            autosave::Edit::Contents(contents) => contents,
+            autosave::Edit::Fields(_) => return,
+        };
+        let base = conflict.base.decrypt_contents(mc_ref).expect("failed to decrypt contents");
+        // a rename or a fields change isn't in the way of the contents
+        let resolution = match theirs == base {
+            true => Resolution::Yours,
+            false => conflict_dialog(window.as_ref(), &item.title, &yours, &theirs, true),
+        };
+        self.saver.seen(&item);
+        reindex_item(item_id);
+        if item.fields != conflict.base.fields {
+            self.show_fields_written_elsewhere(&item, mc_ref);
+        }
+        let contents = match resolution {
+            Resolution::Theirs => theirs,
+            Resolution::Yours => yours,
+            Resolution::Merge => {
+                let merged = merge::merge(&base, &yours, &theirs);
+                info!("merged the edits of item {}, {} conflicting", item_id, merged.conflicts);
+                merged.text
+            }
+        };
+        if self.item_id.get() == Some(item_id) {
+            self.item_id.set(None);
+            self.text_view.buffer().expect("Couldn't get buffer").set_text(&contents);
+            self.item_id.set(Some(item_id));
+        }
+        match resolution {
+            Resolution::Theirs => self.status_label.set_text("Kept the version saved elsewhere"),
+            _ => self.hand_over(item_id, autosave::Edit::Contents(contents)),
+        }
+    }
+
+    /// Asks whether to keep the fields as edited here or as they were changed and saved elsewhere since they were shown. When the fields weren't changed there, the edit is saved on top without asking.
+    fn resolve_fields_conflict(self: &Rc<Self>, item_id: i32) {
+        self.flush_and_wait();
+        let (base, fields) = match self.saver.take_fields_conflict(item_id) {
+            Some(autosave::Conflict { base, edit: autosave::Edit::Fields(fields) }) => (base, fields),
+            _ => return self.status_label.set_text("Not saved: the item was changed elsewhere"),
+        };
+        let item = match item_actions::find_by_id(item_id).expect("failed to find Item by id") {
+            Some(item) => item,
+            None => return self.status_label.set_text("Not saved: the item was removed elsewhere"),
+        };
+        let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+        let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+        let theirs = item.decrypt_fields(mc_ref).expect("failed to decrypt fields");
+        let window = self.fields_grid.toplevel().and_then(|w| w.downcast::<gtk::Window>().ok());
+        let resolution = match item.fields == base.fields {
+            true => Resolution::Yours,
+            false => conflict_dialog(window.as_ref(), &item.title, &models::format_fields(&fields), &models::format_fields(&theirs), false),
+        };
+        self.saver.seen(&item);
+        reindex_item(item_id);
+        if item.contents != base.contents {
+            self.show_contents_written_elsewhere(&item, mc_ref);
+        }
+        match resolution {
+            Resolution::Yours => self.hand_over(item_id, autosave::Edit::Fields(fields)),
+            _ => {
+                if self.item_id.get() == Some(item_id) {
+                    show_item_fields(&self.fields_grid, &item, &theirs, self);
+                }
+                self.status_label.set_text("Kept the version saved elsewhere");
+            }
+        }
+    }
+
+    /// Shows the contents written elsewhere alongside a fields edit, if the item is the one shown and no contents edit of it is on its way, which would run into them too.
+    fn show_contents_written_elsewhere(&self, item: &models::Item, mc: &magic_crypt::MagicCrypt256) {
+        if self.item_id.get() != Some(item.id) || self.pending.borrow().get(&item.id).is_some_and(|p| p.contents.is_some()) {
+            return;
+        }
+        let contents = item.decrypt_contents(mc).expect("failed to decrypt contents");
+        self.item_id.set(None);
+        self.text_view.buffer().expect("Couldn't get buffer").set_text(&contents);
+        self.item_id.set(Some(item.id));
+    }
+
+    /// Shows the fields written elsewhere alongside a contents edit, like [`Autosave::show_contents_written_elsewhere`] does the contents.
+    fn show_fields_written_elsewhere(self: &Rc<Self>, item: &models::Item, mc: &magic_crypt::MagicCrypt256) {
+        if self.item_id.get() != Some(item.id) || self.pending.borrow().get(&item.id).is_some_and(|p| p.fields.is_some()) {
+            return;
+        }
+        let fields = item.decrypt_fields(mc).expect("failed to decrypt fields");
+        show_item_fields(&self.fields_grid, item, &fields, self);
+    }
+}
+
+/// What to keep of an edit that ran into a change saved elsewhere.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Resolution {
+    Theirs,
+    Yours,
+    Merge,
+}
+
+/// Shows an edit and the version saved elsewhere side by side and asks which one to keep, or whether to merge them when `can_merge`.
+fn conflict_dialog(window: Option<&gtk::Window>, title: &str, yours: &str, theirs: &str, can_merge: bool) -> Resolution {
+    // closing the dialog would leave the edit neither saved nor dropped
+    let dialog = gtk::Dialog::builder()
+        .title(format!("{} Changed Elsewhere", title).as_str())
+        .modal(true)
+        .deletable(false)
+        .default_width(900)
+        .default_height(500)
+        .build();
+    dialog.set_transient_for(window);
+    if can_merge {
+        dialog.add_button("Merge", gtk::ResponseType::Other(2));
     }
+    dialog.add_button("Keep Yours", gtk::ResponseType::Other(1));
+    dialog.add_button("Keep Theirs", gtk::ResponseType::Other(0));
+
+    let columns = gtk::Box::builder().spacing(12).margin(12).homogeneous(true).vexpand(true).build();
+    for (heading, text) in [("Yours", yours), ("Saved elsewhere", theirs)] {
+        let text_view = gtk::TextView::builder().editable(false).monospace(true).build();
+        text_view.buffer().expect("Couldn't get buffer").set_text(text);
+        let column = gtk::Box::builder().orientation(gtk::Orientation::Vertical).spacing(6).build();
+        column.pack_start(&gtk::Label::builder().label(heading).xalign(0.0).build(), false, false, 0);
+        column.pack_start(&gtk::ScrolledWindow::builder().child(&text_view).build(), true, true, 0);
+        columns.add(&column);
+    }
+    let explanation = match can_merge {
+        true => "This item was changed and saved elsewhere while you edited it. Merging keeps the changes of both, lines changed on both sides are kept between <<<<<<< and >>>>>>> lines.",
+        false => "This item was changed and saved elsewhere while you edited it.",
+    };
+    dialog.content_area().add(&gtk::Label::builder().label(explanation).wrap(true).margin_top(12).build());
+    dialog.content_area().add(&columns);
+    dialog.show_all();
+
+    let response = dialog.run();
+    dialog.close();
+    match response {
+        gtk::ResponseType::Other(2) => Resolution::Merge,
+        gtk::ResponseType::Other(1) => Resolution::Yours,
+        _ => Resolution::Theirs,
+    }
+}
+
+// columns of the item tree
+const NAME_COLUMN: u32 = 0;
+const ID_COLUMN: u32 = 1;
+const IS_FOLDER_COLUMN: u32 = 2;
+const ICON_COLUMN: u32 = 3;
+const TAGS_COLUMN: u32 = 4;
+/// The saved search a row is, or lists the item of, 0 for the folders and items of the vault itself.
+const SAVED_SEARCH_COLUMN: u32 = 5;
+
+/// Drags within the item tree carry "folder:<id>" or "item:<id>".
+const ROW_TARGET: &str = "application/x-senoru-row";
+
+fn create_item_store() -> Result<gtk::TreeStore, Box<dyn Error>> {
+    let store = gtk::TreeStore::new(&[glib::Type::STRING, glib::Type::I32, glib::Type::BOOL, glib::Type::STRING, glib::Type::STRING, glib::Type::I32]);
+    // saved searches, then folders, then items, each by name
+    store.set_sort_func(gtk::SortColumn::Index(NAME_COLUMN), |model, a, b| {
+        let rank = |iter: &gtk::TreeIter| match (model.value(iter, IS_FOLDER_COLUMN as i32).get::<bool>().unwrap_or(false), is_saved_search_row(model, iter)) {
+            (true, true) => 0,
+            (true, false) => 1,
+            (false, _) => 2,
+        };
+        let name = |iter: &gtk::TreeIter| model.value(iter, NAME_COLUMN as i32).get::<String>().unwrap_or_default().to_lowercase();
+        rank(a).cmp(&rank(b)).then_with(|| name(a).cmp(&name(b)))
+    });
+    store.set_sort_column_id(gtk::SortColumn::Index(NAME_COLUMN), gtk::SortType::Ascending);
+    fill_item_store(&store)?;
     Ok(store)
 }
 
-fn connect_items(builder: &gtk::Builder, store: &gtk::ListStore, item_title_tree_view: &gtk::TreeView) -> Result<(), Box<dyn Error>> {
+/// Whether the row is a saved search or one of the items listed under it.
+fn is_saved_search_row(model: &impl IsA<gtk::TreeModel>, iter: &gtk::TreeIter) -> bool {
+    model.value(iter, SAVED_SEARCH_COLUMN as i32).get::<i32>().unwrap_or_default() != 0
+}
+
+/// Whether the list only shows the items matching the search or the tag filter.
+fn is_item_list_filtered() -> bool {
+    !crate::APP_CORE.tag_filter.lock().unwrap().is_empty() || !crate::APP_CORE.search_query.lock().unwrap().trim().is_empty()
+}
+
+/// Fills the tree with the saved searches, the folders and the items in them. While the list is searched or filtered by tags only the matching items, and the folders above them, are shown.
+fn fill_item_store(store: &gtk::TreeStore) -> Result<(), Box<dyn Error>> {
+    // sorting as rows go in makes filling a large vault quadratic, the rows are sorted once at the end
+    let sort_column = store.sort_column_id();
+    store.set_unsorted();
+    let filled = fill_item_rows(store);
+    if let Some((column, order)) = sort_column {
+        store.set_sort_column_id(column, order);
+    }
+    filled
+}
+
+fn fill_item_rows(store: &gtk::TreeStore) -> Result<(), Box<dyn Error>> {
+    store.clear();
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+    let tag_filter = crate::APP_CORE.tag_filter.lock().unwrap().clone();
+    let search_query = query::parse(&crate::APP_CORE.search_query.lock().unwrap())?;
+    // saved searches are left out while the list is filtered, what matters then is the filter
+    let saved_searches = match is_item_list_filtered() {
+        true => vec![],
+        false => saved_searches::list(mc_ref)?
+            .into_iter()
+            .filter_map(|saved| match query::parse(&saved.query) {
+                Ok(saved_query) => Some((saved.id, saved.name, saved_query)),
+                Err(e) => {
+                    warn!("skipping saved search {}: {}", saved.name, e);
+                    None
+                }
+            })
+            .collect(),
+    };
+    let mut queries = vec![&search_query];
+    queries.extend(saved_searches.iter().map(|(_, _, saved_query)| saved_query));
+    let mut search_index = crate::APP_CORE.search_index.lock().unwrap();
+    // decrypting every item for the index is left until the first search with words to look for
+    if !search_index.is_built() && queries.iter().any(|query| query.has_text()) {
+        *search_index = search::SearchIndex::build(mc_ref)?;
+    }
+    let context = query::Context::load(mc_ref, &search_index, &queries)?;
+    let tag_index = context.tag_index();
+    let folder_tree = context.folder_tree();
+    let hit_ids: Option<HashSet<i32>> = match search_query.is_empty() {
+        true => None,
+        false => Some(search_query.find_items(&context).into_iter().collect()),
+    };
+    let items: Vec<&models::ItemSummary> = context.items().iter().filter(|item| tag_index.matches(item.id, &tag_filter) && hit_ids.as_ref().is_none_or(|ids| ids.contains(&item.id))).collect();
+
+    let items_by_id: HashMap<i32, &models::ItemSummary> = context.items().iter().map(|item| (item.id, item)).collect();
+    for (saved_search_id, name, saved_query) in saved_searches.iter() {
+        let iter = store.insert_with_values(
+            None,
+            None,
+            &[(NAME_COLUMN, name), (ID_COLUMN, saved_search_id), (IS_FOLDER_COLUMN, &true), (ICON_COLUMN, &"folder-saved-search"), (SAVED_SEARCH_COLUMN, saved_search_id)],
+        );
+        for item in saved_query.find_items(&context).into_iter().filter_map(|id| items_by_id.get(&id)) {
+            store.insert_with_values(
+                Some(&iter),
+                None,
+                &[
+                    (NAME_COLUMN, &folder_tree.path_title(item.folder_id, &item.title)),
+                    (ID_COLUMN, &item.id),
+                    (IS_FOLDER_COLUMN, &false),
+                    (ICON_COLUMN, &"text-x-generic"),
+                    (TAGS_COLUMN, &tag_index.item_names(item.id).join(", ")),
+                    (SAVED_SEARCH_COLUMN, saved_search_id),
+                ],
+            );
+        }
+    }
+
+    let mut folders: Vec<&models::Folder> = folder_tree.folders().collect();
+    if is_item_list_filtered() {
+        let parent_ids: HashMap<i32, Option<i32>> = folders.iter().map(|f| (f.id, f.parent_id)).collect();
+        let mut shown_folder_ids = HashSet::new();
+        for item in items.iter() {
+            let mut next = item.folder_id;
+            while let Some(id) = next.filter(|id| shown_folder_ids.insert(*id)) {
+                next = parent_ids.get(&id).copied().flatten();
+            }
+        }
+        folders.retain(|f| shown_folder_ids.contains(&f.id));
+    }
+    // parents go in before their children
+    folders.sort_by_key(|f| folder_tree.path(Some(f.id)).len());
+    let mut folder_iters: HashMap<i32, gtk::TreeIter> = HashMap::new();
+    for folder in folders.into_iter() {
+        let parent = folder.parent_id.and_then(|id| folder_iters.get(&id));
+        let iter = store.insert_with_values(
+            parent,
+            None,
+            &[(NAME_COLUMN, &folder.name), (ID_COLUMN, &folder.id), (IS_FOLDER_COLUMN, &true), (ICON_COLUMN, &"folder"), (SAVED_SEARCH_COLUMN, &0)],
+        );
+        folder_iters.insert(folder.id, iter);
+    }
+    for item in items.iter() {
+        let parent = item.folder_id.and_then(|id| folder_iters.get(&id));
+        let item_tags = tag_index.item_names(item.id).join(", ");
+        store.insert_with_values(
+            parent,
+            None,
+            &[(NAME_COLUMN, &item.title), (ID_COLUMN, &item.id), (IS_FOLDER_COLUMN, &false), (ICON_COLUMN, &"text-x-generic"), (TAGS_COLUMN, &item_tags), (SAVED_SEARCH_COLUMN, &0)],
+        );
+    }
+    Ok(())
+}
+
+/// Refills the tree after folders or items moved, keeping the open folders and saved searches open.
+fn reload_item_store(store: &gtk::TreeStore, tree_view: &gtk::TreeView) {
+    let mut expanded_folder_ids = vec![];
+    let mut expanded_saved_search_ids = vec![];
+    tree_view.map_expanded_rows(|_, path| {
+        if let Some(iter) = store.iter(path) {
+            let id = store.value(&iter, ID_COLUMN as i32).get::<i32>().unwrap_or_default();
+            match is_saved_search_row(store, &iter) {
+                true => expanded_saved_search_ids.push(id),
+                false => expanded_folder_ids.push(id),
+            }
+        }
+    });
+    // a detached model fills without the tree view updating for every row
+    tree_view.set_model(None::<&gtk::TreeStore>);
+    fill_item_store(store).expect("failed to load the items");
+    tree_view.set_model(Some(store));
+    if is_item_list_filtered() {
+        tree_view.expand_all();
+        return;
+    }
+    for folder_id in expanded_folder_ids.into_iter() {
+        if let Some(path) = find_row(store, true, folder_id) {
+            tree_view.expand_row(&path, false);
+        }
+    }
+    for saved_search_id in expanded_saved_search_ids.into_iter() {
+        if let Some(path) = find_saved_search_row(store, saved_search_id) {
+            tree_view.expand_row(&path, false);
+        }
+    }
+}
+
+/// The row of a folder or an item where it is in the vault, not under a saved search.
+fn find_row(store: &gtk::TreeStore, is_folder: bool, id: i32) -> Option<gtk::TreePath> {
+    let mut found = None;
+    store.foreach(|model, path, iter| {
+        let matches = model.value(iter, IS_FOLDER_COLUMN as i32).get::<bool>() == Ok(is_folder)
+            && model.value(iter, ID_COLUMN as i32).get::<i32>() == Ok(id)
+            && !is_saved_search_row(model, iter);
+        if matches {
+            found = Some(path.clone());
+        }
+        matches
+    });
+    found
+}
+
+fn find_saved_search_row(store: &gtk::TreeStore, saved_search_id: i32) -> Option<gtk::TreePath> {
+    let mut found = None;
+    store.foreach(|model, path, iter| {
+        let matches = model.value(iter, IS_FOLDER_COLUMN as i32).get::<bool>() == Ok(true) && model.value(iter, SAVED_SEARCH_COLUMN as i32).get::<i32>() == Ok(saved_search_id);
+        if matches {
+            found = Some(path.clone());
+        }
+        matches
+    });
+    found
+}
+
+/// Opens the folders above a row and selects it.
+fn select_row(store: &gtk::TreeStore, tree_view: &gtk::TreeView, is_folder: bool, id: i32) -> Option<gtk::TreePath> {
+    let path = find_row(store, is_folder, id)?;
+    tree_view.expand_to_path(&path);
+    tree_view.selection().select_path(&path);
+    tree_view.scroll_to_cell(Some(&path), None::<&gtk::TreeViewColumn>, false, 0.0, 0.0);
+    Some(path)
+}
+
+/// Whether each selected row is a folder, with its id, leaving out the saved searches.
+fn selected_rows(tree_view: &gtk::TreeView) -> Vec<(bool, i32)> {
+    let (paths, model) = tree_view.selection().selected_rows();
+    paths
+        .iter()
+        .filter_map(|path| model.iter(path))
+        .filter(|iter| !(model.value(iter, IS_FOLDER_COLUMN as i32).get::<bool>().unwrap_or(false) && is_saved_search_row(&model, iter)))
+        .map(|iter| {
+            let is_folder = model.value(&iter, IS_FOLDER_COLUMN as i32).get::<bool>().unwrap_or(false);
+            (is_folder, model.value(&iter, ID_COLUMN as i32).get::<i32>().expect("failed to get selected id"))
+        })
+        .collect()
+}
+
+/// The ids of the selected items, leaving out folders.
+fn selected_item_ids(tree_view: &gtk::TreeView) -> Vec<i32> {
+    selected_rows(tree_view).into_iter().filter(|(is_folder, _)| !is_folder).map(|(_, id)| id).collect()
+}
+
+/// The item of the selected row, `None` when nothing, a folder or several rows are selected.
+fn selected_item(tree_view: &gtk::TreeView) -> Option<models::Item> {
+    match selected_rows(tree_view).as_slice() {
+        [(false, id)] => item_actions::find_by_id(*id).expect("failed to find Item by id"),
+        _ => None,
+    }
+}
+
+fn selected_folder(tree_view: &gtk::TreeView) -> Option<models::Folder> {
+    match selected_rows(tree_view).as_slice() {
+        [(true, id)] => folder_actions::find_by_id(*id).expect("failed to find Folder by id"),
+        _ => None,
+    }
+}
+
+/// The saved search of the selected row, `None` unless a single saved search is selected.
+fn selected_saved_search(tree_view: &gtk::TreeView) -> Option<models::SavedSearch> {
+    let (paths, model) = tree_view.selection().selected_rows();
+    match paths.as_slice() {
+        [path] => model
+            .iter(path)
+            .filter(|iter| model.value(iter, IS_FOLDER_COLUMN as i32).get::<bool>().unwrap_or(false) && is_saved_search_row(&model, iter))
+            .and_then(|iter| saved_search_actions::find_by_id(model.value(&iter, ID_COLUMN as i32).get::<i32>().unwrap_or_default()).expect("failed to find SavedSearch by id")),
+        _ => None,
+    }
+}
+
+/// Where new items and folders go: the selected folder, or the folder of the selected item.
+fn selected_folder_id(tree_view: &gtk::TreeView) -> Option<i32> {
+    match selected_folder(tree_view) {
+        Some(folder) => Some(folder.id),
+        None => selected_item(tree_view).and_then(|item| item.folder_id),
+    }
+}
+
+fn connect_items(builder: &gtk::Builder, store: &gtk::TreeStore, item_title_tree_view: &gtk::TreeView, autosave: &Rc<Autosave>) -> Result<(), Box<dyn Error>> {
     let item_content_text_view: gtk::TextView = builder.object("main_window_item_content_text_view").unwrap();
+    let item_fields_grid: gtk::Grid = builder.object("main_window_item_fields_grid").unwrap();
+    let item_attachments_box: gtk::Box = builder.object("main_window_item_attachments_box").unwrap();
     let item_title_search_entry: gtk::SearchEntry = builder.object("main_window_item_title_search_entry").unwrap();
+    let info_bar: gtk::InfoBar = builder.object("main_window_info_bar").unwrap();
+    let info_bar_label: gtk::Label = builder.object("main_window_info_bar_label").unwrap();
+    let tag_filter_flow_box: gtk::FlowBox = builder.object("main_window_tag_filter_flow_box").unwrap();
 
     item_title_tree_view.set_model(Some(store));
-    item_title_tree_view.set_search_entry(Some(&item_title_search_entry));
-    let item_title_tree_view_renderer = gtk::CellRendererText::builder().editable(true).build();
-    item_title_tree_view_renderer.connect_edited(glib::clone!(@strong item_title_tree_view, @strong store => move |_renderer, _path, new_title| {
-        tree_view_cell_renderer_edited(new_title, &item_title_tree_view, &store);
+    item_title_search_entry.set_placeholder_text(Some("Search, or filter with tag:aws type:login modified:<90d is:weak"));
+    item_title_search_entry.connect_search_changed(glib::clone!(@strong store, @weak item_title_tree_view, @weak item_content_text_view => move |entry| {
+        // the list keeps showing the last query that made sense until this one does
+        if let Err(e) = query::parse(entry.text().as_str()) {
+            entry.set_icon_from_icon_name(gtk::EntryIconPosition::Secondary, Some("dialog-warning-symbolic"));
+            entry.set_icon_tooltip_text(gtk::EntryIconPosition::Secondary, Some(e.to_string().as_str()));
+            return;
+        }
+        entry.set_icon_from_icon_name(gtk::EntryIconPosition::Secondary, None);
+        *crate::APP_CORE.search_query.lock().unwrap() = entry.text().to_string();
+        reload_item_store(&store, &item_title_tree_view);
+        highlight_search_matches(&item_content_text_view);
+    }));
+    item_content_text_view.set_editable(!db::is_read_only());
+    let item_title_tree_view_renderer = gtk::CellRendererText::builder().editable(!db::is_read_only()).build();
+    item_title_tree_view_renderer.connect_edited(glib::clone!(@strong store => move |_renderer, path, new_title| {
+        tree_view_cell_renderer_edited(&path, new_title, &store);
     }));
-    let column = gtk::TreeViewColumn::builder().title("Title").sort_column_id(0i32).build();
+    let icon_renderer = gtk::CellRendererPixbuf::new();
+    // fixed sizes let the tree only measure the rows on screen, which keeps large vaults quick to show and scroll
+    let column = gtk::TreeViewColumn::builder()
+        .title("Title")
+        .sort_column_id(NAME_COLUMN as i32)
+        .sizing(gtk::TreeViewColumnSizing::Fixed)
+        .fixed_width(260)
+        .resizable(true)
+        .build();
+    TreeViewColumnExt::pack_start(&column, &icon_renderer, false);
+    TreeViewColumnExt::add_attribute(&column, &icon_renderer, "icon-name", ICON_COLUMN as i32);
     TreeViewColumnExt::pack_start(&column, &item_title_tree_view_renderer, true);
-    TreeViewColumnExt::add_attribute(&column, &item_title_tree_view_renderer, "text", 0i32);
+    TreeViewColumnExt::add_attribute(&column, &item_title_tree_view_renderer, "text", NAME_COLUMN as i32);
     item_title_tree_view.append_column(&column);
+    let tags_renderer = gtk::CellRendererText::builder().foreground("gray").ellipsize(gtk::pango::EllipsizeMode::End).build();
+    let tags_column = gtk::TreeViewColumn::builder().title("Tags").sizing(gtk::TreeViewColumnSizing::Fixed).expand(true).build();
+    TreeViewColumnExt::pack_start(&tags_column, &tags_renderer, true);
+    TreeViewColumnExt::add_attribute(&tags_column, &tags_renderer, "text", TAGS_COLUMN as i32);
+    item_title_tree_view.append_column(&tags_column);
+    item_title_tree_view.set_search_column(NAME_COLUMN as i32);
+    item_title_tree_view.set_fixed_height_mode(true);
+    reload_tag_filter(&tag_filter_flow_box, store, item_title_tree_view);
+
+    // folders and items are moved by dragging them onto a folder, or onto empty space for the top level
+    let row_targets = [gtk::TargetEntry::new(ROW_TARGET, gtk::TargetFlags::SAME_WIDGET, 0)];
+    item_title_tree_view.drag_source_set(gdk::ModifierType::BUTTON1_MASK, &row_targets, gdk::DragAction::MOVE);
+    if !db::is_read_only() {
+        item_title_tree_view.drag_dest_set(gtk::DestDefaults::ALL, &row_targets, gdk::DragAction::MOVE);
+    }
+    item_title_tree_view.connect_drag_data_get(|tree_view, _, selection_data, _, _| {
+        let rows: Vec<String> = selected_rows(tree_view).iter().map(|(is_folder, id)| format!("{}:{}", if *is_folder { "folder" } else { "item" }, id)).collect();
+        selection_data.set(&gdk::Atom::intern(ROW_TARGET), 8, rows.join("\n").as_bytes());
+    });
+    let main_window: gtk::Window = builder.object("main_window").unwrap();
+    item_title_tree_view.connect_drag_data_received(glib::clone!(@weak main_window, @strong store => move |tree_view, _, x, y, selection_data, _, _| {
+        let data = String::from_utf8_lossy(&selection_data.data()).to_string();
+        tree_view_drag_data_received(&main_window, tree_view, &store, x, y, &data);
+    }));
+
+    // the id of the item most recently moved to the trash, for as long as it can be undone
+    let removed_item_id: std::rc::Rc<std::cell::Cell<Option<i32>>> = Default::default();
+    info_bar.add_button("Undo", gtk::ResponseType::Apply);
+    info_bar.connect_response(glib::clone!(@strong store, @weak item_title_tree_view, @strong removed_item_id => move |info_bar, response| {
+        if let (gtk::ResponseType::Apply, Some(id)) = (response, removed_item_id.take()) {
+            undo_remove_action(&store, &item_title_tree_view, id);
+        }
+        removed_item_id.set(None);
+        info_bar.hide();
+    }));
 
     // remove popup for item title treeview
     let remove_menu_item: gtk::MenuItem = gtk::MenuItem::builder().label("Remove").build();
     remove_menu_item.connect_activate(
-        glib::clone!(@strong store, @weak item_title_tree_view, @strong item_content_text_view => move |_menu_item| {
-            remove_menu_item_action(&store, &item_title_tree_view, &item_content_text_view);
+        glib::clone!(@weak main_window, @strong store, @weak item_title_tree_view, @strong autosave, @strong item_fields_grid, @strong item_attachments_box, @weak info_bar, @weak info_bar_label, @strong removed_item_id => move |_menu_item| {
+            if let Some(item) = remove_menu_item_action(&main_window, &store, &item_title_tree_view, &autosave, &item_fields_grid, &item_attachments_box) {
+                info_bar_label.set_text(format!("{} moved to the trash", item.title).as_str());
+                info_bar.show_all();
+                info_bar.show();
+                removed_item_id.set(Some(item.id));
+                glib::timeout_add_seconds_local(10, glib::clone!(@weak info_bar, @strong removed_item_id => @default-return glib::Continue(false), move || {
+                    // a later removal owns the bar now
+                    if removed_item_id.get() == Some(item.id) {
+                        removed_item_id.set(None);
+                        info_bar.hide();
+                    }
+                    glib::Continue(false)
+                }));
+            }
+        }),
+    );
+    let share_menu_item: gtk::MenuItem = gtk::MenuItem::builder().label("Share Item…").build();
+    share_menu_item.connect_activate(glib::clone!(@weak main_window, @weak item_title_tree_view => move |_menu_item| {
+        share_menu_item_action(&main_window, &item_title_tree_view);
+    }));
+    let attach_menu_item: gtk::MenuItem = gtk::MenuItem::builder().label("Attach File…").build();
+    attach_menu_item.connect_activate(glib::clone!(@weak main_window, @weak item_title_tree_view, @weak item_attachments_box => move |_menu_item| {
+        attach_menu_item_action(&main_window, &item_title_tree_view, &item_attachments_box);
+    }));
+    let tag_menu_item: gtk::MenuItem = gtk::MenuItem::builder().label("Tag…").build();
+    tag_menu_item.connect_activate(glib::clone!(@weak main_window, @strong store, @weak item_title_tree_view, @weak tag_filter_flow_box => move |_menu_item| {
+        tag_menu_item_action(&main_window, &item_title_tree_view);
+        reload_tag_filter(&tag_filter_flow_box, &store, &item_title_tree_view);
+    }));
+    let history_menu_item: gtk::MenuItem = gtk::MenuItem::builder().label("History…").build();
+    history_menu_item.connect_activate(
+        glib::clone!(@weak main_window, @weak item_title_tree_view, @strong autosave, @weak item_fields_grid, @weak item_attachments_box => move |_menu_item| {
+            history_menu_item_action(&main_window, &item_title_tree_view, &autosave, &item_fields_grid, &item_attachments_box);
         }),
     );
-    let popup_menu: gtk::Menu = gtk::Menu::builder().child(&remove_menu_item).build();
-    item_title_tree_view.connect_button_press_event(move |_tree_view, event| {
+    let popup_menu: gtk::Menu = match db::is_read_only() {
+        // sharing only reads the item
+        true => gtk::Menu::builder().child(&share_menu_item).build(),
+        false => gtk::Menu::builder()
+            .child(&remove_menu_item)
+            .child(&share_menu_item)
+            .child(&attach_menu_item)
+            .child(&tag_menu_item)
+            .child(&history_menu_item)
+            .build(),
+    };
+    item_title_tree_view.connect_button_press_event(move |tree_view, event| {
         if event.event_type() == gdk::EventType::ButtonPress && event.button() == 3 {
             debug!("event: {:?}", event);
+            // a right click on a selected row keeps the whole selection, so it can be tagged at once
+            let (x, y) = event.position();
+            if let Some((Some(path), _, _, _)) = tree_view.path_at_pos(x as i32, y as i32) {
+                if !tree_view.selection().path_is_selected(&path) {
+                    tree_view.selection().unselect_all();
+                    tree_view.selection().select_path(&path);
+                }
+            }
             popup_menu.popup_easy(event.button(), event.time());
             popup_menu.show_all();
+            return gtk::Inhibit(true);
         }
         gtk::Inhibit(false)
     });
 
     let tree_view_selection = item_title_tree_view.selection();
-    tree_view_selection.connect_changed(glib::clone!(@weak item_content_text_view => move |tree_selection| {
-        tree_view_selection_changed(tree_selection, &item_content_text_view);
-    }));
-
-    item_content_text_view.connect_key_release_event(glib::clone!(@weak item_title_tree_view => @default-return Inhibit(false), move |text_view, _| {
-        text_view_key_press_event_action(&item_title_tree_view, text_view);
-        Inhibit(false)
+    tree_view_selection.connect_changed(glib::clone!(@strong autosave, @weak item_fields_grid, @weak item_attachments_box => move |tree_selection| {
+        tree_view_selection_changed(tree_selection, &autosave, &item_fields_grid, &item_attachments_box);
     }));
     Ok(())
 }
@@ -100,7 +856,7 @@ fn connect_about_dialog(builder: &gtk::Builder) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn connect_change_master_key_dialog(builder: &gtk::Builder) -> Result<(), Box<dyn Error>> {
+fn connect_change_master_key_dialog(builder: &gtk::Builder, autosave: &Rc<Autosave>) -> Result<(), Box<dyn Error>> {
     let dialog: gtk::Dialog = builder.object("change_master_key_dialog").unwrap();
     let menu_item: gtk::MenuItem = builder.object("change_master_key_menu_item").unwrap();
     let current_key_entry: gtk::Entry = builder.object("change_master_key_dialog_current_key_entry").unwrap();
@@ -128,7 +884,7 @@ fn connect_change_master_key_dialog(builder: &gtk::Builder) -> Result<(), Box<dy
         dialog.show_all();
     }));
 
-    ok_button.connect_clicked(glib::clone!(@weak dialog, @weak new_key_entry, @strong error_dialog => move |_| {
+    ok_button.connect_clicked(glib::clone!(@weak dialog, @weak new_key_entry, @strong error_dialog, @strong autosave => move |_| {
         let new_master_key_text = new_key_entry.buffer().text();
         let new_master_key_score = scorer::score(&analyzer::analyze(&new_master_key_text));
         if new_master_key_score < 40_f64 {
@@ -136,18 +892,19 @@ fn connect_change_master_key_dialog(builder: &gtk::Builder) -> Result<(), Box<dy
             error_dialog.run();
             error_dialog.close();
         } else {
-            let mut all_items = item_actions::find_all(None).expect("failed to get items from db");
+            // edits on their way are encrypted with the current key
+            autosave.flush_and_wait();
             let new_magic_crypt = new_magic_crypt!(new_key_entry.buffer().text(), 256);
             let mut current_magic_crypt = crate::APP_CORE.magic_crypt.lock().unwrap();
             let old_magic_crypt = current_magic_crypt.as_ref().expect("failed to get magic_crypt");
-            for item in all_items.iter_mut() {
-                let contents = item
-                    .decrypt_contents(&old_magic_crypt)
-                    .expect("failed to decrypt item contents using current key");
-                item.contents = Some(new_magic_crypt.encrypt_str_to_base64(contents));
-                item_actions::update(&item).expect("failed to update item contents with new key");
+            if let Err(e) = master_key::change(old_magic_crypt, &new_magic_crypt) {
+                error_dialog.set_text(Some(format!("The key was not changed: {}", e).as_str()));
+                error_dialog.run();
+                error_dialog.close();
+                return;
             }
             *current_magic_crypt = Some(new_magic_crypt.clone());
+            crate::APP_CORE.content_cache.lock().unwrap().clear();
             dialog.hide();
         }
     }));
@@ -224,29 +981,137 @@ fn connect_generate_password_dialog(builder: &gtk::Builder) -> Result<(), Box<dy
     Ok(())
 }
 
-fn connect_menu_items(builder: &gtk::Builder, main_window: &gtk::Window, store: &gtk::ListStore, item_title_tree_view: &gtk::TreeView) -> Result<(), Box<dyn Error>> {
+/// The menu items that change the vault, off while it is read-only.
+const WRITING_MENU_ITEMS: [&str; 13] = [
+    "new_menu_item",
+    "new_login_menu_item",
+    "new_card_menu_item",
+    "new_identity_menu_item",
+    "new_ssh_key_menu_item",
+    "new_folder_menu_item",
+    "import_menu_item",
+    "import_folder_menu_item",
+    "receive_menu_item",
+    "trash_menu_item",
+    "tags_menu_item",
+    "save_search_menu_item",
+    "change_master_key_menu_item",
+];
+
+fn connect_menu_items(builder: &gtk::Builder, main_window: &gtk::Window, store: &gtk::TreeStore, item_title_tree_view: &gtk::TreeView, autosave: &Rc<Autosave>) -> Result<(), Box<dyn Error>> {
+    // imports, received items and the trash can bring tags along, so the filter is rebuilt after them
+    let tag_filter_flow_box: gtk::FlowBox = builder.object("main_window_tag_filter_flow_box").unwrap();
+
     let new_menu_item: gtk::MenuItem = builder.object("new_menu_item").unwrap();
     new_menu_item.connect_activate(glib::clone!(@strong store, @strong item_title_tree_view => move |_| {
-        new_menu_item_action(&store, &item_title_tree_view)
+        new_menu_item_action(&store, &item_title_tree_view, models::ItemType::Note)
+    }));
+
+    let new_login_menu_item: gtk::MenuItem = builder.object("new_login_menu_item").unwrap();
+    new_login_menu_item.connect_activate(glib::clone!(@strong store, @strong item_title_tree_view => move |_| {
+        new_menu_item_action(&store, &item_title_tree_view, models::ItemType::Login)
+    }));
+
+    let new_card_menu_item: gtk::MenuItem = builder.object("new_card_menu_item").unwrap();
+    new_card_menu_item.connect_activate(glib::clone!(@strong store, @strong item_title_tree_view => move |_| {
+        new_menu_item_action(&store, &item_title_tree_view, models::ItemType::Card)
+    }));
+
+    let new_identity_menu_item: gtk::MenuItem = builder.object("new_identity_menu_item").unwrap();
+    new_identity_menu_item.connect_activate(glib::clone!(@strong store, @strong item_title_tree_view => move |_| {
+        new_menu_item_action(&store, &item_title_tree_view, models::ItemType::Identity)
+    }));
+
+    let new_ssh_key_menu_item: gtk::MenuItem = builder.object("new_ssh_key_menu_item").unwrap();
+    new_ssh_key_menu_item.connect_activate(glib::clone!(@strong store, @strong item_title_tree_view => move |_| {
+        new_menu_item_action(&store, &item_title_tree_view, models::ItemType::SshKey)
+    }));
+
+    let new_folder_menu_item: gtk::MenuItem = builder.object("new_folder_menu_item").unwrap();
+    new_folder_menu_item.connect_activate(glib::clone!(@strong store, @strong item_title_tree_view => move |_| {
+        new_folder_menu_item_action(&store, &item_title_tree_view)
     }));
 
     let import_menu_item: gtk::MenuItem = builder.object("import_menu_item").unwrap();
-    import_menu_item.connect_activate(glib::clone!(@weak main_window, @weak store, @weak item_title_tree_view => move |_| {
-        import_menu_item_action(&main_window, &store, &item_title_tree_view);
+    import_menu_item.connect_activate(glib::clone!(@weak main_window, @weak store, @weak item_title_tree_view, @weak tag_filter_flow_box => move |_| {
+        import_menu_item_action(&main_window, &store, &item_title_tree_view, gtk::FileChooserAction::Open);
+        reload_tag_filter(&tag_filter_flow_box, &store, &item_title_tree_view);
+    }));
+
+    let import_folder_menu_item: gtk::MenuItem = builder.object("import_folder_menu_item").unwrap();
+    import_folder_menu_item.connect_activate(glib::clone!(@weak main_window, @weak store, @weak item_title_tree_view, @weak tag_filter_flow_box => move |_| {
+        import_menu_item_action(&main_window, &store, &item_title_tree_view, gtk::FileChooserAction::SelectFolder);
+        reload_tag_filter(&tag_filter_flow_box, &store, &item_title_tree_view);
+    }));
+
+    let receive_menu_item: gtk::MenuItem = builder.object("receive_menu_item").unwrap();
+    receive_menu_item.connect_activate(glib::clone!(@weak main_window, @weak store, @weak item_title_tree_view, @weak tag_filter_flow_box => move |_| {
+        receive_menu_item_action(&main_window, &store, &item_title_tree_view);
+        reload_tag_filter(&tag_filter_flow_box, &store, &item_title_tree_view);
     }));
 
     let export_menu_item: gtk::MenuItem = builder.object("export_menu_item").unwrap();
-    export_menu_item.connect_activate(glib::clone!(@weak main_window => move |_| {
-        export_menu_item_action();
+    export_menu_item.connect_activate(glib::clone!(@weak main_window, @weak item_title_tree_view => move |_| {
+        export_file_menu_item_action(&main_window, &item_title_tree_view, export::Format::Archive);
+    }));
+
+    let export_plaintext_menu_item: gtk::MenuItem = builder.object("export_plaintext_menu_item").unwrap();
+    export_plaintext_menu_item.connect_activate(glib::clone!(@weak main_window => move |_| {
+        export_plaintext_menu_item_action(&main_window);
+    }));
+
+    let export_kdbx_menu_item: gtk::MenuItem = builder.object("export_kdbx_menu_item").unwrap();
+    export_kdbx_menu_item.connect_activate(glib::clone!(@weak main_window, @weak item_title_tree_view => move |_| {
+        export_file_menu_item_action(&main_window, &item_title_tree_view, export::Format::Kdbx);
+    }));
+
+    let trash_menu_item: gtk::MenuItem = builder.object("trash_menu_item").unwrap();
+    trash_menu_item.connect_activate(glib::clone!(@weak main_window, @weak store, @weak item_title_tree_view, @weak tag_filter_flow_box => move |_| {
+        trash_menu_item_action(&main_window, &store, &item_title_tree_view);
+        reload_tag_filter(&tag_filter_flow_box, &store, &item_title_tree_view);
+    }));
+
+    let tags_menu_item: gtk::MenuItem = builder.object("tags_menu_item").unwrap();
+    tags_menu_item.connect_activate(glib::clone!(@weak main_window, @weak store, @weak item_title_tree_view, @weak tag_filter_flow_box => move |_| {
+        tags_menu_item_action(&main_window);
+        reload_tag_filter(&tag_filter_flow_box, &store, &item_title_tree_view);
+    }));
+
+    let save_search_menu_item: gtk::MenuItem = builder.object("save_search_menu_item").unwrap();
+    save_search_menu_item.connect_activate(glib::clone!(@weak main_window, @weak store, @weak item_title_tree_view => move |_| {
+        save_search_menu_item_action(&main_window, &store, &item_title_tree_view);
+    }));
+
+    let lock_menu_item: gtk::MenuItem = builder.object("lock_menu_item").unwrap();
+    lock_menu_item.connect_activate(glib::clone!(@weak main_window, @strong autosave => move |_| {
+        lock_menu_item_action(&main_window, &autosave);
     }));
 
     let quit_menu_item: gtk::MenuItem = builder.object("quit_menu_item").unwrap();
     quit_menu_item.connect_activate(glib::clone!(@weak main_window => move |_| {
         main_window.close();
     }));
+
+    if db::is_read_only() {
+        for id in WRITING_MENU_ITEMS.iter() {
+            builder.object::<gtk::MenuItem>(id).unwrap().set_sensitive(false);
+        }
+    }
     Ok(())
 }
 
+/// Saves pending edits, forgets the key and everything decrypted, and asks for the key again in a new window.
+fn lock_menu_item_action(main_window: &gtk::Window, autosave: &Autosave) {
+    autosave.flush_and_wait();
+    let application = main_window.application().expect("failed to get the application");
+    // the application would quit without a window while the key dialog is up
+    let _hold = application.hold();
+    main_window.hide();
+    main_window.close();
+    crate::lock();
+    crate::start_ui(&application);
+}
+
 fn generate_password_dialog_refresh_action(
     include_numbers_checkbox: &gtk::CheckButton,
     include_uppercase_checkbox: &gtk::CheckButton,
@@ -274,32 +1139,61 @@ fn generate_password_dialog_refresh_action(
     }
 }
 
-fn new_menu_item_action(store: &gtk::ListStore, tree_view: &gtk::TreeView) {
-    let mut new_item = models::NewItem::new("New".into());
-    let contents: String = "Enter text here".into();
+fn new_menu_item_action(store: &gtk::TreeStore, tree_view: &gtk::TreeView, item_type: models::ItemType) {
+    let (title, contents) = match item_type {
+        models::ItemType::Note => ("New", "Enter text here"),
+        models::ItemType::Login => ("New Login", ""),
+        models::ItemType::Card => ("New Card", ""),
+        models::ItemType::Identity => ("New Identity", ""),
+        models::ItemType::SshKey => ("New SSH Key", "Paste an unencrypted OpenSSH private key here"),
+    };
+    let folder_id = selected_folder_id(tree_view);
+    // a second "New" becomes "New (2)" instead of running into the unique title
+    let mut new_item = models::NewItem::new(folders::unique_name(folder_id, title, false).expect("failed to name the item"));
+    new_item.item_type = item_type.to_string();
+    new_item.folder_id = folder_id;
     let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
     let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
 
     new_item.contents = Some(mc_ref.encrypt_str_to_base64(contents));
+    new_item.encrypt_fields(mc_ref, &item_type.default_fields()).expect("failed to encrypt fields");
     match item_actions::insert(&new_item) {
         Ok(_) => {
-            let value = glib::value::Value::from(&new_item.title);
-            let iter = store.append();
-            store.set_value(&iter, 0u32, &value);
-            let path = store.path(&iter).expect("Couldn't get path");
-            tree_view.selection().select_path(&path);
+            reload_item_store(store, tree_view);
+            if let Ok(Some(item)) = item_actions::find_by_folder_and_title(new_item.folder_id, &new_item.title) {
+                reindex_item(item.id);
+                // straight into renaming it
+                if let Some(path) = select_row(store, tree_view, false, item.id) {
+                    tree_view.set_cursor(&path, tree_view.column(0).as_ref(), true);
+                }
+            }
+        }
+        Err(e) => warn!("failed to add {}: {}", new_item.title, e),
+    }
+}
+
+fn new_folder_menu_item_action(store: &gtk::TreeStore, tree_view: &gtk::TreeView) {
+    let parent_id = selected_folder_id(tree_view);
+    let name = folders::unique_name(parent_id, "New Folder", true).expect("failed to name the folder");
+    match folder_actions::insert(&models::NewFolder::new(parent_id, name)) {
+        Ok(folder) => {
+            reload_item_store(store, tree_view);
+            // straight into renaming it
+            if let Some(path) = select_row(store, tree_view, true, folder.id) {
+                tree_view.set_cursor(&path, tree_view.column(0).as_ref(), true);
+            }
         }
         Err(e) => warn!("{}", e),
     }
 }
 
-fn import_menu_item_action(main_window: &gtk::Window, store: &gtk::ListStore, tree_view: &gtk::TreeView) {
+fn import_menu_item_action(main_window: &gtk::Window, store: &gtk::TreeStore, tree_view: &gtk::TreeView, action: gtk::FileChooserAction) {
     let file_chooser_dialog = gtk::FileChooserDialog::builder()
-        .title("Choose a file to import")
+        .title(if action == gtk::FileChooserAction::SelectFolder { "Choose a folder or password store to import" } else { "Choose a file to import" })
         .show_hidden(true)
         .select_multiple(true)
         .transient_for(main_window)
-        .action(gtk::FileChooserAction::Open)
+        .action(action)
         .build();
 
     file_chooser_dialog.add_button("Open", gtk::ResponseType::Ok);
@@ -308,135 +1202,1346 @@ fn import_menu_item_action(main_window: &gtk::Window, store: &gtk::ListStore, tr
     let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
     let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
 
-    if file_chooser_dialog.run() == gtk::ResponseType::Ok {
-        let files = file_chooser_dialog.filenames();
+    let response = file_chooser_dialog.run();
+    let files = file_chooser_dialog.filenames();
+    file_chooser_dialog.close();
+
+    if response == gtk::ResponseType::Ok {
         files.iter().for_each(|z| info!("file: {}", z.to_string_lossy()));
+        let mut report = import::ImportReport::default();
         for path in files.iter() {
-            let item_title: String = path.file_name().unwrap().to_os_string().into_string().unwrap();
-            let mut new_item = models::NewItem::new(item_title);
-            let contents = std::fs::read_to_string(path.as_path()).unwrap();
-            new_item.contents = Some(mc_ref.encrypt_str_to_base64(contents));
-            match item_actions::insert(&new_item) {
-                Ok(_) => {
-                    let value = glib::value::Value::from(&new_item.title);
-                    let iter = store.append();
-                    store.set_value(&iter, 0u32, &value);
-                    let path = store.path(&iter).expect("Couldn't get path");
-                    tree_view.selection().select_path(&path);
+            let format = import::Format::from_path(path);
+            let mut options = import::ReadOptions::default();
+            match format {
+                import::Format::Kdbx => match key_dialog(main_window, path, true) {
+                    Some((password, keyfile)) => {
+                        options.password = Some(password).filter(|p| !p.is_empty());
+                        options.keyfile = keyfile;
+                    }
+                    None => continue,
+                },
+                import::Format::Archive => match key_dialog(main_window, path, false) {
+                    Some((password, _)) => options.password = Some(password),
+                    None => continue,
+                },
+                import::Format::Csv => match csv_mapping_dialog(main_window, path) {
+                    Some(csv_mapping) => options.csv_mapping = csv_mapping,
+                    None => continue,
+                },
+                import::Format::Directory => {
+                    let binary_files_dialog = gtk::MessageDialog::builder()
+                        .title("Import")
+                        .buttons(gtk::ButtonsType::YesNo)
+                        .message_type(gtk::MessageType::Question)
+                        .modal(true)
+                        .transient_for(main_window)
+                        .text("Import files that are not text, such as images, as attachments? Otherwise they are skipped.")
+                        .build();
+                    if binary_files_dialog.run() == gtk::ResponseType::Yes {
+                        options.binary_files = directory::BinaryFiles::Attach;
+                    }
+                    binary_files_dialog.close();
+                }
+                _ => {}
+            }
+            let (imported_items, errors) = match import::read(path, format, &options) {
+                Ok(i) => i,
+                Err(e) => {
+                    warn!("{}", e);
+                    report.errors.push((path.to_string_lossy().to_string(), e.to_string()));
+                    continue;
+                }
+            };
+            let on_conflict = match format {
+                import::Format::Text => import::OnConflict::Skip,
+                _ => match import_preview_dialog(main_window, path, &imported_items, &errors) {
+                    Some(on_conflict) => on_conflict,
+                    None => continue,
+                },
+            };
+            report.errors.extend(errors);
+            match import::import_items(&imported_items, on_conflict, mc_ref) {
+                Ok(r) => {
+                    append_imported_titles(store, tree_view, &r);
+                    report.created.extend(r.created);
+                    report.renamed.extend(r.renamed);
+                    report.overwritten.extend(r.overwritten);
+                    report.conflicts.extend(r.conflicts);
+                    report.errors.extend(r.errors);
+                    report.created_ids.extend(r.created_ids);
                 }
                 Err(e) => warn!("{}", e),
             }
         }
+
+        import_report_dialog(main_window, &report);
     }
+}
 
-    file_chooser_dialog.close();
+fn append_imported_titles(store: &gtk::TreeStore, tree_view: &gtk::TreeView, report: &import::ImportReport) {
+    reset_search_index();
+    reload_item_store(store, tree_view);
+    for item_id in report.created_ids.iter() {
+        select_row(store, tree_view, false, *item_id);
+    }
+}
+
+/// Shows the outcome of every imported file or entry, the list can be long so it scrolls.
+fn import_report_dialog(main_window: &gtk::Window, report: &import::ImportReport) {
+    let dialog = gtk::Dialog::builder()
+        .title("Import")
+        .transient_for(main_window)
+        .modal(true)
+        .default_width(600)
+        .default_height(400)
+        .build();
+    dialog.add_button("Ok", gtk::ResponseType::Ok);
+
+    let summary = report.summary();
+    let (headline, details) = summary.split_once('\n').unwrap_or((summary.as_str(), ""));
+    let text_view = gtk::TextView::builder().editable(false).monospace(true).build();
+    text_view.buffer().expect("Couldn't get buffer").set_text(details);
+    let scrolled_window = gtk::ScrolledWindow::builder().vexpand(true).child(&text_view).build();
+
+    let content_area = dialog.content_area();
+    content_area.set_spacing(6);
+    content_area.add(&gtk::Label::new(Some(headline)));
+    content_area.add(&scrolled_window);
+    dialog.show_all();
+    dialog.run();
+    dialog.close();
+}
+
+fn import_preview_dialog(main_window: &gtk::Window, path: &path::Path, imported_items: &[import::ImportedItem], errors: &[(String, String)]) -> Option<import::OnConflict> {
+    let dialog = gtk::Dialog::builder()
+        .title(format!("Import {}", path.file_name().unwrap_or_default().to_string_lossy()).as_str())
+        .transient_for(main_window)
+        .modal(true)
+        .default_width(600)
+        .default_height(400)
+        .build();
+    dialog.add_button("Import", gtk::ResponseType::Ok);
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+
+    let text_view = gtk::TextView::builder().editable(false).monospace(true).build();
+    let scrolled_window = gtk::ScrolledWindow::builder().vexpand(true).child(&text_view).build();
+    let on_conflict_combobox = gtk::ComboBoxText::new();
+    on_conflict_combobox.append(Some("skip"), "Skip items whose title already exists");
+    on_conflict_combobox.append(Some("rename"), "Rename items whose title already exists");
+    on_conflict_combobox.append(Some("overwrite"), "Overwrite items whose title already exists");
+    on_conflict_combobox.set_active_id(Some("skip"));
+    let preview = |on_conflict| match import::plan(imported_items, on_conflict) {
+        Ok(actions) => actions
+            .iter()
+            .map(|a| a.to_string())
+            .chain(errors.iter().map(|(line, e)| format!("error  {}: {}", line, e)))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        Err(e) => e.to_string(),
+    };
+    let on_conflicts = [import::OnConflict::Skip, import::OnConflict::Rename, import::OnConflict::Overwrite];
+    let previews: Vec<String> = on_conflicts.iter().map(|o| preview(*o)).collect();
+    text_view.buffer().expect("Couldn't get buffer").set_text(&previews[0]);
+    on_conflict_combobox.connect_changed(glib::clone!(@weak text_view => move |combobox| {
+        let preview = &previews[combobox.active().unwrap_or(0) as usize];
+        text_view.buffer().expect("Couldn't get buffer").set_text(preview);
+    }));
+
+    let content_area = dialog.content_area();
+    content_area.set_spacing(6);
+    content_area.add(&scrolled_window);
+    content_area.add(&on_conflict_combobox);
+    dialog.show_all();
+
+    let result = match dialog.run() {
+        gtk::ResponseType::Ok => Some(on_conflicts[on_conflict_combobox.active().unwrap_or(0) as usize]),
+        _ => None,
+    };
+    dialog.close();
+    result
 }
 
-fn export_menu_item_action() {
-    let project_dir = dirs::home_dir().unwrap().join(".senoru");
-    if !project_dir.as_path().exists() {
-        std::fs::create_dir_all(&project_dir).ok();
+/// Asks which column layout a CSV file uses, `Some(None)` means detect it from the header.
+fn csv_mapping_dialog(main_window: &gtk::Window, path: &path::Path) -> Option<Option<csv_import::Mapping>> {
+    let dialog = gtk::Dialog::builder()
+        .title(format!("Import {}", path.file_name().unwrap_or_default().to_string_lossy()).as_str())
+        .transient_for(main_window)
+        .modal(true)
+        .build();
+    dialog.add_button("Ok", gtk::ResponseType::Ok);
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.set_default_response(gtk::ResponseType::Ok);
+
+    let grid = gtk::Grid::builder().row_spacing(6).column_spacing(6).margin(12).build();
+    let layout_combobox = gtk::ComboBoxText::new();
+    layout_combobox.append(Some("detect"), "Detect from the header");
+    for preset in csv_import::Preset::ALL.iter() {
+        layout_combobox.append(Some(format!("{:?}", preset).as_str()), preset.name());
     }
-    let export_dir = project_dir.join("export");
-    if !export_dir.as_path().exists() {
-        std::fs::create_dir_all(&export_dir).ok();
+    layout_combobox.append(Some("custom"), "Custom mapping");
+    layout_combobox.set_active_id(Some("detect"));
+    let mapping_entry = gtk::Entry::builder()
+        .placeholder_text("title=Name,username=Login,password=Pass,url=Site")
+        .activates_default(true)
+        .sensitive(false)
+        .hexpand(true)
+        .build();
+    layout_combobox.connect_changed(glib::clone!(@weak mapping_entry => move |combobox| {
+        mapping_entry.set_sensitive(combobox.active_id().as_deref() == Some("custom"));
+    }));
+    grid.attach(&gtk::Label::new(Some("Layout")), 0, 0, 1, 1);
+    grid.attach(&layout_combobox, 1, 0, 1, 1);
+    grid.attach(&gtk::Label::new(Some("Mapping")), 0, 1, 1, 1);
+    grid.attach(&mapping_entry, 1, 1, 1, 1);
+    dialog.content_area().add(&grid);
+    dialog.show_all();
+
+    let mut result = None;
+    while dialog.run() == gtk::ResponseType::Ok {
+        let active_id = layout_combobox.active_id().map(|id| id.to_string()).unwrap_or_default();
+        let mapping = match active_id.as_str() {
+            "detect" => Ok(None),
+            "custom" => mapping_entry.text().parse::<csv_import::Mapping>().map(Some),
+            id => Ok(csv_import::Preset::ALL.iter().find(|p| format!("{:?}", p) == id).map(|p| csv_import::Mapping::from(*p))),
+        };
+        match mapping {
+            Ok(m) => {
+                result = Some(m);
+                break;
+            }
+            Err(e) => {
+                let error_dialog = gtk::MessageDialog::builder()
+                    .title("Import")
+                    .buttons(gtk::ButtonsType::Ok)
+                    .message_type(gtk::MessageType::Error)
+                    .modal(true)
+                    .transient_for(&dialog)
+                    .text(e.as_str())
+                    .build();
+                error_dialog.run();
+                error_dialog.close();
+            }
+        }
     }
+    dialog.close();
+    result
+}
 
-    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
-    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+/// Asks for the password of a KeePass database or an archive, and a keyfile if `with_keyfile` is set.
+fn key_dialog(main_window: &gtk::Window, path: &path::Path, with_keyfile: bool) -> Option<(String, Option<path::PathBuf>)> {
+    let dialog = gtk::Dialog::builder()
+        .title(format!("Unlock {}", path.file_name().unwrap_or_default().to_string_lossy()).as_str())
+        .transient_for(main_window)
+        .modal(true)
+        .build();
+    dialog.add_button("Ok", gtk::ResponseType::Ok);
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.set_default_response(gtk::ResponseType::Ok);
 
-    let items = item_actions::find_all(None).expect("failed to get Items");
-    for item in items.iter().cloned() {
-        let output_file = export_dir.join(&item.title);
-        let mut bw = io::BufWriter::new(fs::File::create(output_file.as_path()).unwrap());
-        let contents = item.decrypt_contents(&mc_ref).expect("failed to decrypt item");
-        bw.write_all(contents.as_bytes()).expect("Unable to write data");
+    let grid = gtk::Grid::builder().row_spacing(6).column_spacing(6).margin(12).build();
+    let password_entry = gtk::Entry::builder().visibility(false).activates_default(true).hexpand(true).build();
+    let keyfile_button = gtk::FileChooserButton::new("Choose a keyfile", gtk::FileChooserAction::Open);
+    grid.attach(&gtk::Label::new(Some("Password")), 0, 0, 1, 1);
+    grid.attach(&password_entry, 1, 0, 1, 1);
+    if with_keyfile {
+        grid.attach(&gtk::Label::new(Some("Keyfile")), 0, 1, 1, 1);
+        grid.attach(&keyfile_button, 1, 1, 1, 1);
     }
+    dialog.content_area().add(&grid);
+    dialog.show_all();
 
+    let result = match dialog.run() {
+        gtk::ResponseType::Ok => Some((password_entry.text().to_string(), keyfile_button.filename())),
+        _ => None,
+    };
+    dialog.close();
+    result
+}
+
+fn export_plaintext_menu_item_action(main_window: &gtk::Window) {
+    let confirm_dialog = gtk::MessageDialog::builder()
+        .title("Export as Plain Text")
+        .buttons(gtk::ButtonsType::OkCancel)
+        .message_type(gtk::MessageType::Warning)
+        .modal(true)
+        .transient_for(main_window)
+        .text("Every item will be written decrypted, one file per item. Anyone who can read those files will see your secrets.\n\nUse Export Encrypted Archive unless you really need plain text.")
+        .build();
+    let response = confirm_dialog.run();
+    confirm_dialog.close();
+    if response != gtk::ResponseType::Ok {
+        return;
+    }
+
+    let file_chooser_dialog = gtk::FileChooserDialog::builder()
+        .title("Choose an empty folder for the plain text files")
+        .transient_for(main_window)
+        .action(gtk::FileChooserAction::CreateFolder)
+        .build();
+    file_chooser_dialog.add_button("Export", gtk::ResponseType::Ok);
+    file_chooser_dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    let response = file_chooser_dialog.run();
+    let export_dir = file_chooser_dialog.filename();
+    file_chooser_dialog.close();
+    let export_dir = match (response, export_dir) {
+        (gtk::ResponseType::Ok, Some(d)) => d,
+        _ => return,
+    };
+
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+    let (message_type, message) = match export::find_items(&[]).and_then(|items| export::write(&export_dir, export::Format::Plaintext, &items, mc_ref, &Default::default()).map(|_| items.len())) {
+        Ok(count) => (gtk::MessageType::Info, format!("{} items were written to: {}", count, export_dir.to_string_lossy())),
+        Err(e) => (gtk::MessageType::Error, format!("Export failed: {}", e)),
+    };
     let info_dialog = gtk::MessageDialog::builder()
         .title("Export")
         .buttons(gtk::ButtonsType::Ok)
-        .message_type(gtk::MessageType::Info)
+        .message_type(message_type)
         .modal(true)
-        .text(format!("Items were written to: {}", export_dir.to_string_lossy()).as_str())
+        .transient_for(main_window)
+        .text(message.as_str())
         .build();
     info_dialog.run();
     info_dialog.close();
 }
 
-fn remove_menu_item_action(store: &gtk::ListStore, tree_view: &gtk::TreeView, text_view: &gtk::TextView) {
-    let selection = tree_view.selection();
-    let (model, iter) = selection.selected().expect("Couldn't get selected");
-    let selected_title = model.value(&iter, 0).get::<String>().expect("failed to get selected title");
-    if let Some(item) = item_actions::find_by_title(&selected_title).expect("Could not find by title") {
-        item_actions::delete(&item.id).expect("failed to delete item");
-        store.remove(&iter);
+fn export_file_menu_item_action(main_window: &gtk::Window, tree_view: &gtk::TreeView, format: export::Format) {
+    let title = match format {
+        export::Format::Kdbx => "Export to KeePass",
+        _ => "Export Encrypted Archive",
+    };
+    let dialog = gtk::Dialog::builder().title(title).transient_for(main_window).modal(true).build();
+    dialog.add_button("Export", gtk::ResponseType::Ok);
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.set_default_response(gtk::ResponseType::Ok);
+
+    let grid = gtk::Grid::builder().row_spacing(6).column_spacing(6).margin(12).build();
+    let path_entry = gtk::Entry::builder().text(format.default_file_name()).hexpand(true).build();
+    let folder_button = gtk::FileChooserButton::new("Choose a folder", gtk::FileChooserAction::SelectFolder);
+    let password_entry = gtk::Entry::builder().visibility(false).build();
+    let repeat_password_entry = gtk::Entry::builder().visibility(false).activates_default(true).build();
+    let selected_only_checkbox = gtk::CheckButton::with_label("Only the selected item");
+    let selected_title = selected_item(tree_view).map(|item| folders::full_title(&item).expect("failed to get the item path"));
+    selected_only_checkbox.set_sensitive(selected_title.is_some());
+    if let Some(home_dir) = dirs::home_dir() {
+        folder_button.set_current_folder(home_dir);
+    }
+    grid.attach(&gtk::Label::new(Some("File name")), 0, 0, 1, 1);
+    grid.attach(&path_entry, 1, 0, 1, 1);
+    grid.attach(&gtk::Label::new(Some("Folder")), 0, 1, 1, 1);
+    grid.attach(&folder_button, 1, 1, 1, 1);
+    grid.attach(&gtk::Label::new(Some("Password")), 0, 2, 1, 1);
+    grid.attach(&password_entry, 1, 2, 1, 1);
+    grid.attach(&gtk::Label::new(Some("Repeat password")), 0, 3, 1, 1);
+    grid.attach(&repeat_password_entry, 1, 3, 1, 1);
+    grid.attach(&selected_only_checkbox, 1, 4, 1, 1);
+    dialog.content_area().add(&grid);
+    dialog.show_all();
+
+    while dialog.run() == gtk::ResponseType::Ok {
+        let options = export::WriteOptions {
+            password: password_entry.text().to_string(),
+            ..Default::default()
+        };
+        let message = if options.password.is_empty() || options.password != repeat_password_entry.text().as_str() {
+            "The passwords are empty or do not match".to_string()
+        } else {
+            let titles = match (selected_only_checkbox.is_active(), &selected_title) {
+                (true, Some(title)) => vec![title.clone()],
+                _ => vec![],
+            };
+            let path = folder_button.filename().unwrap_or_default().join(path_entry.text().as_str());
+            let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+            let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+            match export::find_items(&titles).and_then(|items| export::write(&path, format, &items, mc_ref, &options).map(|_| items.len())) {
+                Ok(count) => {
+                    let info_dialog = gtk::MessageDialog::builder()
+                        .title("Export")
+                        .buttons(gtk::ButtonsType::Ok)
+                        .message_type(gtk::MessageType::Info)
+                        .modal(true)
+                        .transient_for(main_window)
+                        .text(format!("{} items were written to: {}", count, path.to_string_lossy()).as_str())
+                        .build();
+                    info_dialog.run();
+                    info_dialog.close();
+                    break;
+                }
+                Err(e) => format!("Export failed: {}", e),
+            }
+        };
+        let error_dialog = gtk::MessageDialog::builder()
+            .title("Export")
+            .buttons(gtk::ButtonsType::Ok)
+            .message_type(gtk::MessageType::Error)
+            .modal(true)
+            .transient_for(&dialog)
+            .text(message.as_str())
+            .build();
+        error_dialog.run();
+        error_dialog.close();
+    }
+    dialog.close();
+}
+
+fn share_menu_item_action(main_window: &gtk::Window, tree_view: &gtk::TreeView) {
+    let title = match selected_item(tree_view) {
+        Some(item) => folders::full_title(&item).expect("failed to get the item path"),
+        None => return,
+    };
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+
+    let dialog = gtk::Dialog::builder()
+        .title(format!("Share {}", title).as_str())
+        .transient_for(main_window)
+        .modal(true)
+        .default_width(600)
+        .default_height(500)
+        .build();
+    dialog.add_button("Encrypt", gtk::ResponseType::Apply);
+    dialog.add_button("Copy", gtk::ResponseType::Accept);
+    dialog.add_button("Close", gtk::ResponseType::Close);
+
+    let grid = gtk::Grid::builder().row_spacing(6).column_spacing(6).margin(12).build();
+    let public_key_entry = gtk::Entry::builder().editable(false).hexpand(true).build();
+    match share::public_key(mc_ref) {
+        Ok(public_key) => public_key_entry.set_text(&public_key),
+        Err(e) => warn!("{}", e),
+    }
+    let contacts_box = gtk::Box::new(gtk::Orientation::Vertical, 3);
+    let contact_checkboxes: std::rc::Rc<std::cell::RefCell<Vec<(gtk::CheckButton, models::Contact)>>> = Default::default();
+    for contact in contact_actions::find_all().expect("failed to get contacts").into_iter() {
+        let checkbox = gtk::CheckButton::builder().label(&contact.name).tooltip_text(&contact.public_key).build();
+        contacts_box.add(&checkbox);
+        contact_checkboxes.borrow_mut().push((checkbox, contact));
+    }
+    let name_entry = gtk::Entry::builder().placeholder_text("Name").build();
+    let new_public_key_entry = gtk::Entry::builder().placeholder_text("age1...").hexpand(true).build();
+    let add_button = gtk::Button::with_label("Add Contact");
+    let message_text_view = gtk::TextView::builder().editable(false).monospace(true).build();
+    let scrolled_window = gtk::ScrolledWindow::builder().vexpand(true).child(&message_text_view).build();
+
+    add_button.connect_clicked(glib::clone!(@weak dialog, @weak contacts_box, @weak name_entry, @weak new_public_key_entry, @strong contact_checkboxes => move |_| {
+        let name = name_entry.text().to_string();
+        let public_key = new_public_key_entry.text().trim().to_string();
+        let result = share::parse_public_key(&public_key)
+            .and_then(|_| Ok(contact_actions::insert(&models::NewContact::new(name.clone(), public_key))?))
+            .and_then(|_| contact_actions::find_by_name(&name)?.ok_or_else(|| "contact was not saved".into()));
+        match result {
+            Ok(contact) => {
+                let checkbox = gtk::CheckButton::builder().label(&contact.name).tooltip_text(&contact.public_key).active(true).build();
+                contacts_box.add(&checkbox);
+                checkbox.show();
+                contact_checkboxes.borrow_mut().push((checkbox, contact));
+                name_entry.set_text("");
+                new_public_key_entry.set_text("");
+            }
+            Err(e) => {
+                let error_dialog = gtk::MessageDialog::builder()
+                    .title("Share")
+                    .buttons(gtk::ButtonsType::Ok)
+                    .message_type(gtk::MessageType::Error)
+                    .modal(true)
+                    .transient_for(&dialog)
+                    .text(format!("Couldn't add the contact: {}", e).as_str())
+                    .build();
+                error_dialog.run();
+                error_dialog.close();
+            }
+        }
+    }));
+
+    grid.attach(&gtk::Label::new(Some("Your public key")), 0, 0, 1, 1);
+    grid.attach(&public_key_entry, 1, 0, 2, 1);
+    grid.attach(&gtk::Label::new(Some("Share with")), 0, 1, 1, 1);
+    grid.attach(&contacts_box, 1, 1, 2, 1);
+    grid.attach(&name_entry, 0, 2, 1, 1);
+    grid.attach(&new_public_key_entry, 1, 2, 1, 1);
+    grid.attach(&add_button, 2, 2, 1, 1);
+    grid.attach(&scrolled_window, 0, 3, 3, 1);
+    dialog.content_area().add(&grid);
+    dialog.show_all();
+
+    loop {
+        match dialog.run() {
+            gtk::ResponseType::Apply => {
+                let contacts: Vec<models::Contact> = contact_checkboxes.borrow().iter().filter(|(c, _)| c.is_active()).map(|(_, c)| c.clone()).collect();
+                let message = export::find_items(std::slice::from_ref(&title)).and_then(|items| share::share(&items, &contacts, mc_ref));
+                let text = match message {
+                    Ok(m) => m,
+                    Err(e) => format!("Sharing failed: {}", e),
+                };
+                message_text_view.buffer().expect("Couldn't get buffer").set_text(&text);
+            }
+            gtk::ResponseType::Accept => {
+                let buffer = message_text_view.buffer().expect("Couldn't get buffer");
+                let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false).unwrap_or_default();
+                gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&text);
+            }
+            _ => break,
+        }
+    }
+    dialog.close();
+}
+
+fn receive_menu_item_action(main_window: &gtk::Window, store: &gtk::TreeStore, tree_view: &gtk::TreeView) {
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+
+    let dialog = gtk::Dialog::builder()
+        .title("Receive Item")
+        .transient_for(main_window)
+        .modal(true)
+        .default_width(600)
+        .default_height(400)
+        .build();
+    dialog.add_button("Receive", gtk::ResponseType::Ok);
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+
+    let message_text_view = gtk::TextView::builder().monospace(true).build();
+    let scrolled_window = gtk::ScrolledWindow::builder().vexpand(true).child(&message_text_view).build();
+    let file_button = gtk::FileChooserButton::new("Open a shared message", gtk::FileChooserAction::Open);
+    file_button.connect_file_set(glib::clone!(@weak message_text_view => move |button| {
+        if let Some(message) = button.filename().and_then(|f| std::fs::read_to_string(f).ok()) {
+            message_text_view.buffer().expect("Couldn't get buffer").set_text(&message);
+        }
+    }));
+    let content_area = dialog.content_area();
+    content_area.set_spacing(6);
+    content_area.add(&gtk::Label::new(Some("Paste the message that was shared with you, or open it from a file")));
+    content_area.add(&scrolled_window);
+    content_area.add(&file_button);
+    dialog.show_all();
+
+    let mut imported_items = None;
+    while dialog.run() == gtk::ResponseType::Ok {
+        let buffer = message_text_view.buffer().expect("Couldn't get buffer");
+        let message = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false).unwrap_or_default();
+        match share::receive(&message, mc_ref) {
+            Ok(i) => {
+                imported_items = Some(i);
+                break;
+            }
+            Err(e) => {
+                let error_dialog = gtk::MessageDialog::builder()
+                    .title("Receive Item")
+                    .buttons(gtk::ButtonsType::Ok)
+                    .message_type(gtk::MessageType::Error)
+                    .modal(true)
+                    .transient_for(&dialog)
+                    .text(format!("Couldn't decrypt the message: {}", e).as_str())
+                    .build();
+                error_dialog.run();
+                error_dialog.close();
+            }
+        }
+    }
+    dialog.close();
+
+    let imported_items = match imported_items {
+        Some(i) => i,
+        None => return,
+    };
+    if let Some(on_conflict) = import_preview_dialog(main_window, path::Path::new("shared items"), &imported_items, &[]) {
+        match import::import_items(&imported_items, on_conflict, mc_ref) {
+            Ok(report) => {
+                append_imported_titles(store, tree_view, &report);
+                import_report_dialog(main_window, &report);
+            }
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
+
+/// Moves the selected item to the trash and returns it, so the removal can be undone. A selected folder is deleted when it is empty.
+fn remove_menu_item_action(
+    main_window: &gtk::Window,
+    store: &gtk::TreeStore,
+    tree_view: &gtk::TreeView,
+    autosave: &Autosave,
+    fields_grid: &gtk::Grid,
+    attachments_box: &gtk::Box,
+) -> Option<models::Item> {
+    if let Some(saved_search) = selected_saved_search(tree_view) {
+        saved_search_actions::delete(saved_search.id).expect("failed to delete saved search");
+        reload_item_store(store, tree_view);
+        return None;
+    }
+    if let Some(folder) = selected_folder(tree_view) {
+        match folders::remove_folder(&folder) {
+            Ok(_) => reload_item_store(store, tree_view),
+            Err(e) => error_dialog(main_window, "Remove Folder", format!("Couldn't remove the folder: {}", e).as_str()),
+        }
+        return None;
+    }
+    let item = selected_item(tree_view);
+    if let Some(item) = item.as_ref() {
+        item_actions::move_to_trash(&item.id).expect("failed to move item to the trash");
+        reindex_item(item.id);
+        if let Some(iter) = find_row(store, false, item.id).and_then(|path| store.iter(&path)) {
+            store.remove(&iter);
+        }
         match store.iter_first() {
             Some(_) => {}
             None => {
-                let text_view_buffer = text_view.buffer().expect("Couldn't get buffer");
-                text_view_buffer.set_text(&"");
+                autosave.load(None, "");
+                fields_grid.hide();
+                attachments_box.hide();
             }
         }
     }
+    item
+}
+
+fn undo_remove_action(store: &gtk::TreeStore, tree_view: &gtk::TreeView, id: i32) {
+    item_actions::restore_from_trash(&id).expect("failed to restore item from the trash");
+    reindex_item(id);
+    reload_item_store(store, tree_view);
+    select_row(store, tree_view, false, id);
 }
 
-fn tree_view_selection_changed(tree_selection: &gtk::TreeSelection, text_view: &gtk::TextView) {
+fn error_dialog(main_window: &gtk::Window, title: &str, text: &str) {
+    let dialog = gtk::MessageDialog::builder()
+        .title(title)
+        .buttons(gtk::ButtonsType::Ok)
+        .message_type(gtk::MessageType::Error)
+        .modal(true)
+        .transient_for(main_window)
+        .text(text)
+        .build();
+    dialog.run();
+    dialog.close();
+}
+
+/// Moves the dragged rows into the folder they were dropped on, or next to the row they were dropped by.
+fn tree_view_drag_data_received(main_window: &gtk::Window, tree_view: &gtk::TreeView, store: &gtk::TreeStore, x: i32, y: i32, data: &str) {
+    let rows: Vec<(bool, i32)> = data
+        .lines()
+        .filter_map(|row| row.split_once(':'))
+        .filter_map(|(kind, id)| id.parse::<i32>().ok().map(|id| (kind == "folder", id)))
+        .collect();
+    if rows.is_empty() {
+        return warn!("unexpected drag data: {}", data);
+    }
+    let folder_id = match tree_view.dest_row_at_pos(x, y) {
+        Some((Some(path), position)) => {
+            let iter = store.iter(&path).expect("Couldn't get iter");
+            if is_saved_search_row(store, &iter) {
+                return warn!("saved searches only list items, nothing can be moved into them");
+            }
+            let row_id = store.value(&iter, ID_COLUMN as i32).get::<i32>().expect("failed to get row id");
+            let into = matches!(position, gtk::TreeViewDropPosition::IntoOrBefore | gtk::TreeViewDropPosition::IntoOrAfter);
+            match store.value(&iter, IS_FOLDER_COLUMN as i32).get::<bool>().unwrap_or(false) {
+                true if into => Some(row_id),
+                true => folder_actions::find_by_id(row_id).expect("failed to find Folder by id").and_then(|f| f.parent_id),
+                false => item_actions::find_by_id(row_id).expect("failed to find Item by id").and_then(|i| i.folder_id),
+            }
+        }
+        _ => None,
+    };
+    let mut errors = vec![];
+    for (is_folder, id) in rows.iter() {
+        let moved = match is_folder {
+            true => match folder_actions::find_by_id(*id).expect("failed to find Folder by id") {
+                Some(folder) => folders::move_folder(&folder, folder_id),
+                None => continue,
+            },
+            false => match item_actions::find_by_id(*id).expect("failed to find Item by id") {
+                Some(item) => folders::move_item(&item, folder_id),
+                None => continue,
+            },
+        };
+        if let Err(e) = moved {
+            errors.push(e.to_string());
+        }
+    }
+    reset_search_index();
+    reload_item_store(store, tree_view);
+    rows.iter().for_each(|(is_folder, id)| {
+        select_row(store, tree_view, *is_folder, *id);
+    });
+    if !errors.is_empty() {
+        error_dialog(main_window, "Move", format!("Couldn't move everything: {}", errors.join(", ")).as_str());
+    }
+}
+
+/// Rebuilds the tag filter chips above the item list, then the list, dropping filters on tags that are gone.
+fn reload_tag_filter(flow_box: &gtk::FlowBox, store: &gtk::TreeStore, tree_view: &gtk::TreeView) {
     let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
     let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
-    match tree_selection.selected() {
-        Some((model, iter)) => {
-            let selected_title = model.value(&iter, 0).get::<String>().expect("failed to get selected title");
-            let text_view_buffer = text_view.buffer().expect("Couldn't get buffer");
-            // match selected_title {
-            //     Some(title) => {
-            let item = item_actions::find_by_title(&selected_title).expect("failed to find Item by title");
-            match item {
-                Some(i) => {
-                    text_view_buffer.set_text(&i.decrypt_contents(&mc_ref).unwrap());
+    let names = tags::TagIndex::load(mc_ref).expect("failed to load the tags").names();
+    let tag_filter = {
+        let mut tag_filter = crate::APP_CORE.tag_filter.lock().unwrap();
+        tag_filter.retain(|name| names.iter().any(|(n, _)| n == name));
+        tag_filter.clone()
+    };
+    flow_box.children().iter().for_each(|child| flow_box.remove(child));
+    for (name, count) in names.iter() {
+        let chip = gtk::ToggleButton::builder().label(format!("{} {}", name, count).as_str()).active(tag_filter.contains(name)).build();
+        chip.connect_toggled(glib::clone!(@weak store, @weak tree_view, @strong name => move |chip| {
+            {
+                let mut tag_filter = crate::APP_CORE.tag_filter.lock().unwrap();
+                tag_filter.retain(|n| n != &name);
+                if chip.is_active() {
+                    tag_filter.push(name.clone());
                 }
-                None => text_view_buffer.set_text(&""),
             }
-            //     }
-            //     _ => text_view_buffer.set_text(&""),
-            // }
+            reload_item_store(&store, &tree_view);
+        }));
+        flow_box.add(&chip);
+    }
+    flow_box.show_all();
+    flow_box.set_visible(!names.is_empty());
+    reload_item_store(store, tree_view);
+}
+
+/// Adds a tag to, or takes it off, every selected item.
+fn tag_menu_item_action(main_window: &gtk::Window, tree_view: &gtk::TreeView) {
+    let item_ids = selected_item_ids(tree_view);
+    if item_ids.is_empty() {
+        return;
+    }
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+
+    let dialog = gtk::Dialog::builder().title("Tag").transient_for(main_window).modal(true).build();
+    dialog.add_button("Add Tag", gtk::ResponseType::Apply);
+    dialog.add_button("Remove Tag", gtk::ResponseType::Reject);
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.set_default_response(gtk::ResponseType::Apply);
+
+    let tag_names = gtk::ListStore::new(&[glib::Type::STRING]);
+    for (name, _) in tags::TagIndex::load(mc_ref).expect("failed to load the tags").names().iter() {
+        tag_names.insert_with_values(None, &[(0, name)]);
+    }
+    let completion = gtk::EntryCompletion::builder().model(&tag_names).text_column(0).inline_completion(true).build();
+    let tag_entry = gtk::Entry::builder().completion(&completion).activates_default(true).placeholder_text("aws, work").build();
+    let content_area = dialog.content_area();
+    content_area.set_spacing(6);
+    content_area.set_margin(12);
+    content_area.add(&gtk::Label::new(Some(format!("Tags for the {} selected items, separated by commas", item_ids.len()).as_str())));
+    content_area.add(&tag_entry);
+    dialog.show_all();
+
+    let response = dialog.run();
+    let names = tags::parse_list(tag_entry.text().as_str());
+    dialog.close();
+    for name in names.iter() {
+        let tagged = match response {
+            gtk::ResponseType::Apply => tags::tag_items(&item_ids, name, mc_ref),
+            gtk::ResponseType::Reject => tags::untag_items(&item_ids, name, mc_ref),
+            _ => return,
+        };
+        if let Err(e) = tagged {
+            warn!("{}", e);
         }
-        None => {}
     }
 }
 
-fn tree_view_cell_renderer_edited(new_title: &str, tree_view: &gtk::TreeView, store: &gtk::ListStore) {
-    let selection = tree_view.selection();
-    let (model, iter) = selection.selected().expect("Couldn't get selected");
-    let selected_title = model.value(&iter, 0).get::<String>().expect("failed to get selected title");
-    let item = item_actions::find_by_title(&selected_title).expect("failed to find Item by title");
-    match item {
-        Some(mut i) => {
-            i.title = new_title.to_string();
-            item_actions::update(&i).expect("failed to update item");
-            let value = glib::value::Value::from(&i.title);
-            store.set_value(&iter, 0u32, &value);
+/// Saves the query in the search box, or a new one, under a name, listing its items as a folder at the top of the tree.
+fn save_search_menu_item_action(main_window: &gtk::Window, store: &gtk::TreeStore, tree_view: &gtk::TreeView) {
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+
+    let dialog = gtk::Dialog::builder().title("Save Search").transient_for(main_window).modal(true).default_width(400).build();
+    dialog.add_button("Save", gtk::ResponseType::Apply);
+    dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    dialog.set_default_response(gtk::ResponseType::Apply);
+
+    let name_entry = gtk::Entry::builder().activates_default(true).placeholder_text("weak passwords").build();
+    let query_entry = gtk::Entry::builder().activates_default(true).placeholder_text("is:weak").text(crate::APP_CORE.search_query.lock().unwrap().as_str()).build();
+    let content_area = dialog.content_area();
+    content_area.set_spacing(6);
+    content_area.set_margin(12);
+    content_area.add(&gtk::Label::builder().label("Name, saving under an existing name replaces its query").xalign(0.0).build());
+    content_area.add(&name_entry);
+    content_area.add(&gtk::Label::builder().label("Words and filters such as tag:aws type:login folder:work modified:<90d is:weak is:reused has:otp").xalign(0.0).wrap(true).build());
+    content_area.add(&query_entry);
+    dialog.show_all();
+
+    while dialog.run() == gtk::ResponseType::Apply {
+        match saved_searches::save(name_entry.text().as_str(), query_entry.text().as_str(), mc_ref) {
+            Ok(saved_search) => {
+                reload_item_store(store, tree_view);
+                if let Some(path) = find_saved_search_row(store, saved_search.id) {
+                    tree_view.expand_row(&path, false);
+                }
+                break;
+            }
+            Err(e) => error_dialog(main_window, "Save Search", e.to_string().as_str()),
         }
-        None => {}
     }
+    dialog.close();
 }
 
-fn text_view_key_press_event_action(tree_view: &gtk::TreeView, text_view: &gtk::TextView) {
+/// Lists the tags with how many items carry each, from where they can be renamed or merged.
+fn tags_menu_item_action(main_window: &gtk::Window) {
     let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
     let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
-    let selection = tree_view.selection();
-    let (model, iter) = selection.selected().expect("Couldn't get selected");
-    let selected_title = model.value(&iter, 0).get::<String>().expect("failed to get selected title");
-    let item = item_actions::find_by_title(&selected_title).expect("failed to find Item by title");
+
+    let dialog = gtk::Dialog::builder().title("Tags").transient_for(main_window).modal(true).default_width(400).default_height(400).build();
+    dialog.add_button("Rename", gtk::ResponseType::Apply);
+    dialog.add_button("Merge", gtk::ResponseType::Other(1));
+    dialog.add_button("Close", gtk::ResponseType::Close);
+
+    let list_box = gtk::ListBox::builder().selection_mode(gtk::SelectionMode::Multiple).build();
+    list_box.set_placeholder(Some(&gtk::Label::builder().label("There are no tags yet").margin(6).build()));
+    let scrolled_window = gtk::ScrolledWindow::builder().vexpand(true).child(&list_box).build();
+    let name_entry = gtk::Entry::builder().placeholder_text("New name").build();
+    let content_area = dialog.content_area();
+    content_area.set_spacing(6);
+    content_area.set_margin(12);
+    content_area.add(&gtk::Label::new(Some("Select a tag to rename it, or several to merge them into the new name")));
+    content_area.add(&scrolled_window);
+    content_area.add(&name_entry);
+
+    loop {
+        list_box.children().iter().for_each(|child| list_box.remove(child));
+        let names = tags::TagIndex::load(mc_ref).expect("failed to load the tags").names();
+        for (name, count) in names.iter() {
+            let row = gtk::Box::builder().spacing(12).margin(6).build();
+            row.pack_start(&gtk::Label::builder().label(name.as_str()).xalign(0.0).build(), true, true, 0);
+            row.pack_start(&gtk::Label::new(Some(count.to_string().as_str())), false, false, 0);
+            list_box.add(&row);
+        }
+        dialog.show_all();
+
+        let response = dialog.run();
+        let selected: Vec<String> = list_box.selected_rows().iter().filter_map(|r| names.get(r.index() as usize)).map(|(name, _)| name.clone()).collect();
+        let new_name = name_entry.text().trim().to_string();
+        let changed = match response {
+            gtk::ResponseType::Apply if selected.len() == 1 && !new_name.is_empty() => tags::rename(&selected[0], &new_name, mc_ref),
+            gtk::ResponseType::Other(1) if selected.len() > 1 => {
+                // without a new name the tags are merged into the first one
+                let into = if new_name.is_empty() { selected[0].clone() } else { new_name };
+                tags::merge(&selected, &into, mc_ref).map(|_| ())
+            }
+            gtk::ResponseType::Apply | gtk::ResponseType::Other(1) => continue,
+            _ => break,
+        };
+        match changed {
+            Ok(_) => name_entry.set_text(""),
+            Err(e) => error_dialog(main_window, "Tags", e.to_string().as_str()),
+        }
+    }
+    dialog.close();
+}
+
+/// Lists the items in the trash, from where they can be put back into the vault or deleted for good.
+fn trash_menu_item_action(main_window: &gtk::Window, store: &gtk::TreeStore, tree_view: &gtk::TreeView) {
+    let dialog = gtk::Dialog::builder().title("Trash").transient_for(main_window).modal(true).default_width(500).default_height(400).build();
+    dialog.add_button("Restore", gtk::ResponseType::Apply);
+    dialog.add_button("Delete", gtk::ResponseType::Reject);
+    dialog.add_button("Empty Trash", gtk::ResponseType::Other(1));
+    dialog.add_button("Close", gtk::ResponseType::Close);
+
+    let list_box = gtk::ListBox::builder().selection_mode(gtk::SelectionMode::Multiple).build();
+    list_box.set_placeholder(Some(&gtk::Label::builder().label("The trash is empty").margin(6).build()));
+    let scrolled_window = gtk::ScrolledWindow::builder().vexpand(true).margin(12).child(&list_box).build();
+    let purge_label = match trash::purge_days() {
+        Some(days) => format!("Items are deleted for good after {} days in the trash.", days),
+        None => "Items stay in the trash until it is emptied.".to_string(),
+    };
+    dialog.content_area().add(&gtk::Label::builder().label(purge_label.as_str()).margin_top(12).build());
+    dialog.content_area().add(&scrolled_window);
+
+    let confirm = |text: &str| {
+        let confirm_dialog = gtk::MessageDialog::builder()
+            .title("Trash")
+            .buttons(gtk::ButtonsType::YesNo)
+            .message_type(gtk::MessageType::Question)
+            .modal(true)
+            .transient_for(&dialog)
+            .text(text)
+            .build();
+        let response = confirm_dialog.run();
+        confirm_dialog.close();
+        response == gtk::ResponseType::Yes
+    };
+
+    loop {
+        list_box.children().iter().for_each(|child| list_box.remove(child));
+        let items = item_actions::find_trash().expect("failed to find the trash");
+        let folder_tree = folders::FolderTree::load().expect("failed to load the folders");
+        for item in items.iter() {
+            let deleted_at = item.deleted_at.map(|d| Local.from_utc_datetime(&d).format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+            let row = gtk::Box::builder().spacing(12).margin(6).build();
+            row.pack_start(&gtk::Label::builder().label(folder_tree.full_title(item).as_str()).xalign(0.0).build(), true, true, 0);
+            row.pack_start(&gtk::Label::new(Some(deleted_at.as_str())), false, false, 0);
+            list_box.add(&row);
+        }
+        dialog.show_all();
+
+        match dialog.run() {
+            gtk::ResponseType::Apply => {
+                for row in list_box.selected_rows().iter() {
+                    if let Some(item) = items.get(row.index() as usize) {
+                        undo_remove_action(store, tree_view, item.id);
+                    }
+                }
+            }
+            gtk::ResponseType::Reject => {
+                let selected: Vec<&models::Item> = list_box.selected_rows().iter().filter_map(|r| items.get(r.index() as usize)).collect();
+                if !selected.is_empty() && confirm(format!("Delete {} items for good? This can't be undone.", selected.len()).as_str()) {
+                    for item in selected.iter() {
+                        item_actions::delete(&item.id).expect("failed to delete item");
+                    }
+                }
+            }
+            gtk::ResponseType::Other(1) => {
+                if !items.is_empty() && confirm(format!("Delete all {} items in the trash for good? This can't be undone.", items.len()).as_str()) {
+                    trash::empty().expect("failed to empty the trash");
+                }
+            }
+            _ => break,
+        }
+    }
+    dialog.close();
+}
+
+fn tree_view_selection_changed(tree_selection: &gtk::TreeSelection, autosave: &Rc<Autosave>, fields_grid: &gtk::Grid, attachments_box: &gtk::Box) {
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+    if tree_selection.count_selected_rows() == 0 {
+        return;
+    }
+    let text_view = &autosave.text_view;
+    // edits of the item shown until now are handed over first, so they are pending if it is selected again
+    autosave.flush();
+    let tree_view = tree_selection.tree_view().expect("Couldn't get tree view");
+    // a folder, or several rows, have nothing to show
+    let item = match selected_rows(&tree_view).as_slice() {
+        [(false, id)] => crate::APP_CORE.content_cache.lock().unwrap().get(*id, mc_ref).expect("failed to read the item"),
+        _ => None,
+    };
+    let item = item.map(|decrypted| autosave.with_pending(decrypted));
+    text_view.set_sensitive(item.is_some());
     match item {
-        Some(mut i) => {
-            let buffer = text_view.buffer().expect("Couldn't get buffer");
-            let contents = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false)
-                .expect("failed to get content")
-                .to_string();
-            i.contents = Some(mc_ref.encrypt_str_to_base64(contents));
-            item_actions::update(&i).expect("failed to update item");
-        }
-        None => {}
+        Some(decrypted) => {
+            autosave.load(Some(&decrypted.item), &decrypted.contents);
+            highlight_search_matches(text_view);
+            show_item_fields(fields_grid, &decrypted.item, &decrypted.fields, autosave);
+            show_item_attachments(attachments_box, &decrypted.item);
+        }
+        None => {
+            autosave.load(None, "");
+            fields_grid.hide();
+            attachments_box.hide();
+        }
+    }
+}
+
+/// Fills the form with the fields of a structured item, plain notes only get the text view.
+fn show_item_fields(grid: &gtk::Grid, item: &models::Item, fields: &[models::Field], autosave: &Rc<Autosave>) {
+    grid.children().iter().for_each(|child| grid.remove(child));
+    if !item.item_type().is_structured() && fields.is_empty() {
+        grid.hide();
+        return;
+    }
+    for (row, field) in fields.iter().enumerate() {
+        attach_field_row(grid, row as i32, field, autosave);
+    }
+    let add_button = gtk::Button::builder().label("Add Field").halign(gtk::Align::Start).sensitive(!db::is_read_only()).build();
+    add_button.connect_clicked(glib::clone!(@weak grid, @weak autosave => move |button| {
+        let row = grid.cell_top_attach(button);
+        grid.insert_row(row);
+        attach_field_row(&grid, row, &models::Field::new(models::FieldKind::Text, "Field", ""), &autosave);
+        grid.children().iter().for_each(|child| child.show_all());
+        autosave.fields_changed();
+    }));
+    grid.attach(&add_button, 0, fields.len() as i32, 1, 1);
+    grid.children().iter().for_each(|child| child.show_all());
+    grid.show();
+}
+
+/// One row of the form: the field name, its value (hidden for secrets) and a remove button, the kind is kept as the name entry's widget name.
+fn attach_field_row(grid: &gtk::Grid, row: i32, field: &models::Field, autosave: &Rc<Autosave>) {
+    let name_entry = gtk::Entry::builder().text(&field.name).width_chars(14).editable(!db::is_read_only()).build();
+    name_entry.set_widget_name(field.kind.as_str());
+    let value_entry = gtk::Entry::builder().text(&field.value).hexpand(true).visibility(!field.kind.is_secret()).editable(!db::is_read_only()).build();
+    if field.kind.is_secret() {
+        value_entry.set_icon_from_icon_name(gtk::EntryIconPosition::Secondary, Some("view-reveal-symbolic"));
+        value_entry.connect_icon_press(|entry, _, _| entry.set_visibility(!EntryExt::is_visible(entry)));
+    }
+    let remove_button = gtk::Button::from_icon_name(Some("list-remove-symbolic"), gtk::IconSize::Button);
+    remove_button.set_sensitive(!db::is_read_only());
+    remove_button.connect_clicked(glib::clone!(@weak grid, @weak autosave => move |button| {
+        grid.remove_row(grid.cell_top_attach(button));
+        autosave.fields_changed();
+    }));
+    for entry in [&name_entry, &value_entry] {
+        entry.connect_changed(glib::clone!(@weak autosave => move |_| {
+            autosave.fields_changed();
+        }));
+    }
+    grid.attach(&name_entry, 0, row, 1, 1);
+    grid.attach(&value_entry, 1, row, 1, 1);
+    grid.attach(&remove_button, 2, row, 1, 1);
+    if field.kind == models::FieldKind::Totp {
+        grid.attach(&otp_box(&value_entry), 3, row, 1, 1);
+    }
+}
+
+/// The current code of a TOTP field and a countdown of its validity, refreshed every second while the item is shown.
+fn otp_box(value_entry: &gtk::Entry) -> gtk::Box {
+    let code_label = gtk::Label::builder().selectable(true).width_chars(10).build();
+    let countdown_bar = gtk::ProgressBar::builder().valign(gtk::Align::Center).width_request(60).no_show_all(true).build();
+    let copy_button = gtk::Button::from_icon_name(Some("edit-copy-symbolic"), gtk::IconSize::Button);
+    copy_button.set_tooltip_text(Some("Copy code"));
+    copy_button.connect_clicked(glib::clone!(@weak value_entry, @weak code_label => move |_| {
+        match value_entry.text().parse::<otp::Otp>() {
+            Ok(otp) => {
+                let (code, _) = otp.now();
+                gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&code);
+                if let otp::Kind::Hotp { .. } = otp.kind {
+                    // a counter based code is only good once, the entry's changed handler saves the next counter
+                    code_label.set_text(&code);
+                    match otp::increment_counter(&value_entry.text()) {
+                        Ok(value) => value_entry.set_text(&value),
+                        Err(e) => warn!("{}", e),
+                    }
+                }
+            }
+            Err(e) => warn!("invalid OTP secret: {}", e),
+        }
+    }));
+
+    let refresh = glib::clone!(@weak value_entry, @weak code_label, @weak countdown_bar => @default-return glib::Continue(false), move || {
+        match value_entry.text().parse::<otp::Otp>().map(|otp| (otp.kind, otp.now())) {
+            Ok((otp::Kind::Totp { period }, (code, Some(remaining)))) => {
+                code_label.set_text(&code);
+                countdown_bar.set_fraction(remaining as f64 / period as f64);
+                countdown_bar.show();
+            }
+            Ok(_) => countdown_bar.hide(),
+            Err(_) => {
+                code_label.set_text("");
+                countdown_bar.hide();
+            }
+        }
+        glib::Continue(true)
+    });
+    refresh();
+    glib::timeout_add_seconds_local(1, refresh);
+
+    let otp_box = gtk::Box::builder().spacing(6).build();
+    otp_box.pack_start(&code_label, false, false, 0);
+    otp_box.pack_start(&countdown_bar, false, false, 0);
+    otp_box.pack_start(&copy_button, false, false, 0);
+    otp_box
+}
+
+/// The fields as the form shows them.
+fn grid_fields(grid: &gtk::Grid) -> Vec<models::Field> {
+    let mut fields = vec![];
+    let entry_at = |column: i32, row: i32| grid.child_at(column, row).and_then(|w| w.downcast::<gtk::Entry>().ok());
+    let mut row = 0;
+    while let (Some(name_entry), Some(value_entry)) = (entry_at(0, row), entry_at(1, row)) {
+        let kind = name_entry.widget_name().parse::<models::FieldKind>().unwrap_or(models::FieldKind::Text);
+        fields.push(models::Field::new(kind, &name_entry.text(), &value_entry.text()));
+        row += 1;
+    }
+    fields
+}
+
+fn attach_menu_item_action(main_window: &gtk::Window, tree_view: &gtk::TreeView, attachments_box: &gtk::Box) {
+    let item = selected_item(tree_view);
+    let item = match item {
+        Some(item) => item,
+        None => return,
+    };
+
+    let file_chooser_dialog = gtk::FileChooserDialog::builder()
+        .title("Choose files to attach")
+        .select_multiple(true)
+        .transient_for(main_window)
+        .action(gtk::FileChooserAction::Open)
+        .build();
+    file_chooser_dialog.add_button("Attach", gtk::ResponseType::Ok);
+    file_chooser_dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    let response = file_chooser_dialog.run();
+    let files = file_chooser_dialog.filenames();
+    file_chooser_dialog.close();
+    if response != gtk::ResponseType::Ok {
+        return;
+    }
+
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+    let errors: Vec<String> = files
+        .iter()
+        .filter_map(|file| attachment::attach(&item, file, mc_ref).err().map(|e| format!("{}: {}", file.to_string_lossy(), e)))
+        .collect();
+    if !errors.is_empty() {
+        let error_dialog = gtk::MessageDialog::builder()
+            .title("Attach File")
+            .buttons(gtk::ButtonsType::Ok)
+            .message_type(gtk::MessageType::Error)
+            .modal(true)
+            .transient_for(main_window)
+            .text(errors.join("\n").as_str())
+            .build();
+        error_dialog.run();
+        error_dialog.close();
+    }
+    show_item_attachments(attachments_box, &item);
+}
+
+/// Lists the item's attachments below its fields, each one can be saved, dragged to another application or removed.
+fn show_item_attachments(attachments_box: &gtk::Box, item: &models::Item) {
+    attachments_box.children().iter().for_each(|child| attachments_box.remove(child));
+    let attachments = attachment_actions::find_by_item_id(item.id).expect("failed to find attachments");
+    if attachments.is_empty() {
+        attachments_box.hide();
+        return;
+    }
+    for attachment in attachments.into_iter() {
+        let label = gtk::Label::builder()
+            .label(format!("{} ({} bytes, {})", attachment.file_name, attachment.size, attachment.mime_type).as_str())
+            .xalign(0.0)
+            .build();
+        let drag_area = gtk::EventBox::builder().child(&label).hexpand(true).tooltip_text("Drag to another application to copy the file").build();
+        drag_area.drag_source_set(gdk::ModifierType::BUTTON1_MASK, &[gtk::TargetEntry::new("text/uri-list", gtk::TargetFlags::OTHER_APP, 0)], gdk::DragAction::COPY);
+        drag_area.connect_drag_data_get(glib::clone!(@strong attachment => move |_, _, selection_data, _, _| {
+            let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+            let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+            match attachment::drag_file(&attachment, mc_ref).and_then(|file| Ok(glib::filename_to_uri(file, None)?)) {
+                Ok(uri) => {
+                    selection_data.set_uris(&[uri.as_str()]);
+                }
+                Err(e) => warn!("failed to drag {}: {}", attachment.file_name, e),
+            }
+        }));
+
+        let save_button = gtk::Button::from_icon_name(Some("document-save-as-symbolic"), gtk::IconSize::Button);
+        save_button.set_tooltip_text(Some("Save As…"));
+        save_button.connect_clicked(glib::clone!(@strong attachment => move |button| {
+            save_attachment_action(button, &attachment);
+        }));
+        let remove_button = gtk::Button::from_icon_name(Some("list-remove-symbolic"), gtk::IconSize::Button);
+        remove_button.set_tooltip_text(Some("Remove"));
+        remove_button.set_sensitive(!db::is_read_only());
+        remove_button.connect_clicked(glib::clone!(@weak attachments_box, @strong item, @strong attachment => move |_| {
+            attachment_actions::delete(attachment.id).expect("failed to delete attachment");
+            show_item_attachments(&attachments_box, &item);
+        }));
+
+        let row = gtk::Box::builder().spacing(6).build();
+        row.pack_start(&drag_area, true, true, 0);
+        row.pack_start(&save_button, false, false, 0);
+        row.pack_start(&remove_button, false, false, 0);
+        attachments_box.pack_start(&row, false, false, 0);
+    }
+    attachments_box.children().iter().for_each(|child| child.show_all());
+    attachments_box.show();
+}
+
+fn save_attachment_action(button: &gtk::Button, attachment: &models::Attachment) {
+    let window = button.toplevel().and_then(|w| w.downcast::<gtk::Window>().ok());
+    let file_chooser_dialog = gtk::FileChooserDialog::builder()
+        .title("Save Attachment")
+        .action(gtk::FileChooserAction::Save)
+        .do_overwrite_confirmation(true)
+        .build();
+    file_chooser_dialog.set_transient_for(window.as_ref());
+    file_chooser_dialog.set_current_name(&attachment::safe_file_name(&attachment.file_name).unwrap_or_default());
+    file_chooser_dialog.add_button("Save", gtk::ResponseType::Ok);
+    file_chooser_dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+    let response = file_chooser_dialog.run();
+    let file = file_chooser_dialog.filename();
+    file_chooser_dialog.close();
+
+    if let (gtk::ResponseType::Ok, Some(file)) = (response, file) {
+        let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+        let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+        if let Err(e) = attachment::save(attachment, &file, mc_ref) {
+            let error_dialog = gtk::MessageDialog::builder()
+                .title("Save Attachment")
+                .buttons(gtk::ButtonsType::Ok)
+                .message_type(gtk::MessageType::Error)
+                .modal(true)
+                .text(format!("Saving {} failed: {}", attachment.file_name, e).as_str())
+                .build();
+            error_dialog.set_transient_for(window.as_ref());
+            error_dialog.run();
+            error_dialog.close();
+        }
+    }
+}
+
+/// Lists the kept versions of the selected item, shows how each differs from the current one and restores the chosen one.
+fn history_menu_item_action(main_window: &gtk::Window, tree_view: &gtk::TreeView, autosave: &Rc<Autosave>, fields_grid: &gtk::Grid, attachments_box: &gtk::Box) {
+    // the history and the current version have to include the latest edits
+    autosave.flush_and_wait();
+    let item = selected_item(tree_view);
+    let item = match item {
+        Some(item) => item,
+        None => return,
+    };
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+    let versions = history_actions::find_by_item_id(item.id).expect("failed to find item history");
+    let current_text = history::text(
+        &item.decrypt_contents(mc_ref).expect("failed to decrypt contents"),
+        &item.decrypt_fields(mc_ref).expect("failed to decrypt fields"),
+    );
+
+    let dialog = gtk::Dialog::builder()
+        .title(format!("History of {}", item.title).as_str())
+        .transient_for(main_window)
+        .modal(true)
+        .default_width(700)
+        .default_height(500)
+        .build();
+    dialog.add_button("Restore", gtk::ResponseType::Apply);
+    dialog.add_button("Close", gtk::ResponseType::Close);
+    dialog.set_response_sensitive(gtk::ResponseType::Apply, false);
+
+    let list_box = gtk::ListBox::new();
+    for version in versions.iter() {
+        list_box.add(&gtk::Label::builder().label(history::format_date_saved(version).as_str()).xalign(0.0).margin(6).build());
+    }
+    if versions.is_empty() {
+        list_box.set_placeholder(Some(&gtk::Label::builder().label("No earlier versions").margin(6).build()));
+    }
+    let diff_text_view = gtk::TextView::builder().editable(false).monospace(true).build();
+    let diff_buffer = diff_text_view.buffer().expect("Couldn't get buffer");
+    let tag_table = diff_buffer.tag_table().expect("Couldn't get tag table");
+    tag_table.add(&gtk::TextTag::builder().name("removed").background("#f8d7da").build());
+    tag_table.add(&gtk::TextTag::builder().name("added").background("#d4edda").build());
+
+    list_box.connect_row_selected(glib::clone!(@weak dialog, @weak diff_buffer, @strong versions, @strong current_text => move |_, row| {
+        diff_buffer.set_text("");
+        let version = match row.and_then(|r| versions.get(r.index() as usize)) {
+            Some(version) => version,
+            None => return,
+        };
+        dialog.set_response_sensitive(gtk::ResponseType::Apply, true);
+        let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+        let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+        let version_text = version.decrypt_contents(mc_ref).and_then(|contents| Ok(history::text(&contents, &version.decrypt_fields(mc_ref)?)));
+        match version_text {
+            Ok(version_text) => {
+                for (tag, line) in history::diff(&version_text, &current_text).iter() {
+                    let start = diff_buffer.char_count();
+                    diff_buffer.insert(&mut diff_buffer.end_iter(), format!("{}{}\n", tag, line).as_str());
+                    match tag {
+                        similar::ChangeTag::Delete => diff_buffer.apply_tag_by_name("removed", &diff_buffer.iter_at_offset(start), &diff_buffer.end_iter()),
+                        similar::ChangeTag::Insert => diff_buffer.apply_tag_by_name("added", &diff_buffer.iter_at_offset(start), &diff_buffer.end_iter()),
+                        similar::ChangeTag::Equal => {}
+                    }
+                }
+            }
+            Err(e) => diff_buffer.set_text(format!("Couldn't decrypt this version: {}", e).as_str()),
+        }
+    }));
+
+    let split_pane = gtk::Paned::builder().orientation(gtk::Orientation::Horizontal).margin(12).vexpand(true).build();
+    split_pane.pack1(&gtk::ScrolledWindow::builder().child(&list_box).width_request(180).build(), false, false);
+    split_pane.pack2(&gtk::ScrolledWindow::builder().child(&diff_text_view).build(), true, false);
+    dialog.content_area().add(&gtk::Label::builder().label("Lines starting with - are only in the selected version, + only in the current one.").margin_top(12).build());
+    dialog.content_area().add(&split_pane);
+    dialog.show_all();
+
+    if dialog.run() == gtk::ResponseType::Apply {
+        let version = list_box.selected_row().and_then(|r| versions.get(r.index() as usize));
+        if let Some(version) = version {
+            history::restore(&item, version).expect("failed to restore item");
+            reindex_item(item.id);
+            tree_view_selection_changed(&tree_view.selection(), autosave, fields_grid, attachments_box);
+        }
+    }
+    dialog.close();
+}
+
+/// Renames the folder or item of the edited row, names must be unique within their folder and can't contain the path separator.
+fn tree_view_cell_renderer_edited(path: &gtk::TreePath, new_name: &str, store: &gtk::TreeStore) {
+    let iter = store.iter(path).expect("Couldn't get iter");
+    let id = store.value(&iter, ID_COLUMN as i32).get::<i32>().expect("failed to get row id");
+    if is_saved_search_row(store, &iter) {
+        return rename_saved_search(store, &iter, new_name);
+    }
+    let new_name = new_name.trim();
+    if new_name.is_empty() || new_name.contains(folders::SEPARATOR) {
+        return warn!("not renaming to {:?}, names can't be empty or contain {}", new_name, folders::SEPARATOR);
+    }
+    let renamed = match store.value(&iter, IS_FOLDER_COLUMN as i32).get::<bool>().unwrap_or(false) {
+        true => match folder_actions::find_by_id(id).expect("failed to find Folder by id") {
+            Some(mut f) if folder_actions::find_child(f.parent_id, new_name).expect("failed to find Folder").is_none() => {
+                f.name = new_name.to_string();
+                let renamed = folder_actions::update(&f).expect("failed to update folder");
+                // the paths of everything inside changed
+                reset_search_index();
+                renamed
+            }
+            _ => false,
+        },
+        false => match item_actions::find_by_id(id).expect("failed to find Item by id") {
+            Some(mut i) if item_actions::find_by_folder_and_title(i.folder_id, new_name).expect("failed to find Item").is_none() => {
+                i.title = new_name.to_string();
+                // written at the revision just read, a save in between fails it rather than being undone
+                let renamed = item_actions::update(&mut i).expect("failed to update item");
+                reindex_item(i.id);
+                renamed
+            }
+            _ => false,
+        },
+    };
+    match renamed {
+        true => store.set_value(&iter, NAME_COLUMN, &glib::value::Value::from(new_name)),
+        false => warn!("not renaming to {}, the name is already taken or it changed meanwhile", new_name),
+    }
+}
+
+/// Renames the saved search of the row, the items listed under it can't be renamed from there as they show their path.
+fn rename_saved_search(store: &gtk::TreeStore, iter: &gtk::TreeIter, new_name: &str) {
+    if !store.value(iter, IS_FOLDER_COLUMN as i32).get::<bool>().unwrap_or(false) {
+        return warn!("rename items where they are in their folder");
+    }
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+    let id = store.value(iter, ID_COLUMN as i32).get::<i32>().expect("failed to get row id");
+    if let Some(saved_search) = saved_search_actions::find_by_id(id).expect("failed to find SavedSearch by id") {
+        match saved_searches::rename(&saved_search, new_name, mc_ref) {
+            Ok(_) => store.set_value(iter, NAME_COLUMN, &glib::value::Value::from(new_name.trim())),
+            Err(e) => warn!("not renaming the saved search: {}", e),
+        }
+    }
+}
+
+/// Brings the search index up to date with an item after it was edited, renamed, moved, removed or restored.
+fn reindex_item(item_id: i32) {
+    crate::APP_CORE.content_cache.lock().unwrap().remove(item_id);
+    update_search_index(item_id);
+}
+
+fn update_search_index(item_id: i32) {
+    let mc = crate::APP_CORE.magic_crypt.lock().unwrap().clone();
+    let mc_ref = mc.as_ref().expect("failed to get magic_crypt");
+    if let Err(e) = crate::APP_CORE.search_index.lock().unwrap().update(item_id, mc_ref) {
+        warn!("failed to index item {}: {}", item_id, e);
+    }
+}
+
+/// Drops the index after changes to many items at once such as imports or folder renames, the next search builds it again.
+fn reset_search_index() {
+    crate::APP_CORE.content_cache.lock().unwrap().clear();
+    *crate::APP_CORE.search_index.lock().unwrap() = search::SearchIndex::default();
+}
+
+/// Marks the words of the text view matching the search.
+fn highlight_search_matches(text_view: &gtk::TextView) {
+    let buffer = text_view.buffer().expect("Couldn't get buffer");
+    let tag_table = buffer.tag_table().expect("Couldn't get tag table");
+    if tag_table.lookup("search-match").is_none() {
+        tag_table.add(&gtk::TextTag::builder().name("search-match").background("yellow").foreground("black").build());
+    }
+    buffer.remove_tag_by_name("search-match", &buffer.start_iter(), &buffer.end_iter());
+    let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false).unwrap_or_default();
+    let query = query::parse(&crate::APP_CORE.search_query.lock().unwrap()).map(|q| q.text().join(" ")).unwrap_or_default();
+    for (start, end) in search::highlights(&text, &query).into_iter() {
+        buffer.apply_tag_by_name("search-match", &buffer.iter_at_offset(start as i32), &buffer.iter_at_offset(end as i32));
     }
 }
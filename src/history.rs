@@ -0,0 +1,208 @@
+use std::env;
+use std::error::Error;
+use chrono::prelude::*;
+use diesel::SqliteConnection;
+use magic_crypt::MagicCrypt256;
+use magic_crypt::MagicCryptTrait;
+use similar::{ChangeTag, TextDiff};
+use crate::history_actions;
+use crate::item_actions;
+use crate::models;
+
+/// Edits are saved whenever typing pauses, so a new version is only kept once the last one is this old.
+pub const SNAPSHOT_INTERVAL_MINUTES: i64 = 5;
+
+pub const DEFAULT_MAX_VERSIONS: i64 = 50;
+
+/// How many versions of each item are kept, and for how long, set by `--history-versions` and `--history-days`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Retention {
+    pub max_versions: Option<i64>,
+    pub max_age_days: Option<i64>,
+}
+
+impl Retention {
+    /// Read from `SENORU_HISTORY_VERSIONS` and `SENORU_HISTORY_DAYS`, 0 means no limit.
+    pub fn from_env() -> Retention {
+        let limit = |name: &str, default: Option<i64>| match env::var(name).ok().and_then(|v| v.parse::<i64>().ok()) {
+            Some(0) => None,
+            Some(n) => Some(n),
+            None => default,
+        };
+        Retention {
+            max_versions: limit("SENORU_HISTORY_VERSIONS", Some(DEFAULT_MAX_VERSIONS)),
+            max_age_days: limit("SENORU_HISTORY_DAYS", None),
+        }
+    }
+}
+
+fn changed(stored: &models::Item, item: &models::Item) -> bool {
+    stored.contents != item.contents || stored.fields != item.fields || stored.item_type != item.item_type
+}
+
+/// A version is kept when the last one is older than the snapshot interval, or when the edit removes most of the text.
+fn should_keep(stored: &models::Item, item: &models::Item, mc: &MagicCrypt256) -> Result<bool, Box<dyn Error>> {
+    let keep_after = Utc::now().naive_utc() - chrono::Duration::minutes(SNAPSHOT_INTERVAL_MINUTES);
+    if history_actions::find_latest_date_saved(item.id)?.is_none_or(|date_saved| date_saved < keep_after) {
+        return Ok(true);
+    }
+    let stored_length = stored.decrypt_contents(mc)?.chars().count();
+    let length = item.decrypt_contents(mc)?.chars().count();
+    Ok(length < stored_length / 2)
+}
+
+fn keep(stored: &models::Item) -> Result<(), Box<dyn Error>> {
+    history_actions::insert(&models::NewItemVersion::from_item(stored))?;
+    let retention = Retention::from_env();
+    let saved_before = retention.max_age_days.map(|days| Utc::now().naive_utc() - chrono::Duration::days(days));
+    let num_deleted = history_actions::prune(stored.id, retention.max_versions, saved_before)?;
+    debug!("kept a version of item {}, pruned {}", stored.id, num_deleted);
+    Ok(())
+}
+
+/// Saves an edited item, keeping the stored version in the history first when it is due one. `false` when the item changed since it was read, then nothing is saved.
+pub fn update(item: &mut models::Item, mc: &MagicCrypt256) -> Result<bool, Box<dyn Error>> {
+    if let Some(stored) = item_actions::find_by_id(item.id)? {
+        if stored.revision != item.revision {
+            return Ok(false);
+        }
+        if changed(&stored, item) && should_keep(&stored, item, mc)? {
+            keep(&stored)?;
+        }
+    }
+    Ok(item_actions::update(item)?)
+}
+
+/// Brings back the contents, fields and type of a version, the current version is kept so the restore can be undone.
+pub fn restore(item: &models::Item, version: &models::ItemVersion) -> Result<models::Item, Box<dyn Error>> {
+    let mut restored = item.clone();
+    restored.contents = version.contents.clone();
+    restored.fields = version.fields.clone();
+    restored.item_type = version.item_type.clone();
+    restored.date_last_modified = Utc::now().naive_utc();
+    if changed(item, &restored) {
+        keep(item)?;
+    }
+    if !item_actions::update(&mut restored)? {
+        return Err(format!("{} changed since it was read, not restoring it", item.title).into());
+    }
+    info!("restored item {} to the version of {}", item.id, version.date_saved);
+    Ok(restored)
+}
+
+/// Re-encrypts every kept version after the master key changed.
+pub fn change_master_key(conn: &mut SqliteConnection, old_mc: &MagicCrypt256, new_mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    history_actions::reencrypt_all(conn, |version| {
+        let contents = version.decrypt_contents(old_mc)?;
+        version.contents = Some(new_mc.encrypt_str_to_base64(contents));
+        let fields = version.decrypt_fields(old_mc)?;
+        version.encrypt_fields(new_mc, &fields)
+    })
+}
+
+/// When a version was saved, in local time.
+pub fn format_date_saved(version: &models::ItemVersion) -> String {
+    Local.from_utc_datetime(&version.date_saved).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// The fields as `name: value` lines followed by the contents, the text versions are compared by.
+pub fn text(contents: &str, fields: &[models::Field]) -> String {
+    let fields = models::format_fields(fields);
+    if fields.is_empty() {
+        return contents.to_string();
+    }
+    format!("{}\n\n{}", fields, contents)
+}
+
+/// Line by line changes from `old` to `new`.
+pub fn diff(old: &str, new: &str) -> Vec<(ChangeTag, String)> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| (change.tag(), change.value().trim_end_matches('\n').to_string()))
+        .collect()
+}
+
+/// A unified style rendering of `diff`, removed lines start with `-` and added ones with `+`.
+pub fn format_diff(changes: &[(ChangeTag, String)]) -> String {
+    changes.iter().map(|(tag, line)| format!("{}{}", tag, line)).collect::<Vec<String>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn insert_item(title: &str, contents: &str, mc: &MagicCrypt256) -> models::Item {
+        let mut new_item = models::NewItem::new(title.into());
+        new_item.contents = Some(mc.encrypt_str_to_base64(contents));
+        item_actions::insert(&new_item).unwrap();
+        item_actions::find_by_folder_and_title(None, title).unwrap().unwrap()
+    }
+
+    fn version_contents(item_id: i32, mc: &MagicCrypt256) -> Vec<String> {
+        history_actions::find_by_item_id(item_id).unwrap().iter().map(|v| v.decrypt_contents(mc).unwrap()).collect()
+    }
+
+    #[test]
+    fn versions_are_kept_per_snapshot_interval() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let mut item = insert_item("history snapshot test", "a first version of the text", &mc);
+        let mut save = |contents: &str| {
+            item.contents = Some(mc.encrypt_str_to_base64(contents));
+            assert!(update(&mut item, &mc).unwrap());
+        };
+        // the first edit keeps what was there, the next ones are within the interval
+        save("a second version of the text");
+        save("a third version of the text");
+        // unless most of the text goes
+        save("short");
+        save("short");
+        assert_eq!(version_contents(item.id, &mc), vec!["a third version of the text", "a first version of the text"]);
+
+        let mut stale = item_actions::find_by_id(item.id).unwrap().unwrap();
+        stale.revision -= 1;
+        stale.contents = Some(mc.encrypt_str_to_base64("lost"));
+        assert!(!update(&mut stale, &mc).unwrap());
+        assert_eq!(item_actions::find_by_id(item.id).unwrap().unwrap().decrypt_contents(&mc).unwrap(), "short");
+    }
+
+    #[test]
+    fn prune_by_count_and_age() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let item = insert_item("history prune test", "text", &mc);
+        let now = Utc::now().naive_utc();
+        for days_ago in [40, 20, 10, 5, 1] {
+            history_actions::insert(&models::NewItemVersion {
+                contents: Some(mc.encrypt_str_to_base64(format!("{} days ago", days_ago))),
+                date_saved: now - chrono::Duration::days(days_ago),
+                ..models::NewItemVersion::from_item(&item)
+            })
+            .unwrap();
+        }
+        assert_eq!(history_actions::prune(item.id, None, Some(now - chrono::Duration::days(30))).unwrap(), 1);
+        assert_eq!(history_actions::prune(item.id, Some(3), None).unwrap(), 1);
+        assert_eq!(version_contents(item.id, &mc), vec!["1 days ago", "5 days ago", "10 days ago"]);
+        assert_eq!(history_actions::prune(item.id, Some(3), Some(now - chrono::Duration::days(30))).unwrap(), 0);
+    }
+
+    #[test]
+    fn retention_from_env() {
+        env::remove_var("SENORU_HISTORY_DAYS");
+        assert_eq!(Retention::from_env().max_age_days, None);
+        env::set_var("SENORU_HISTORY_DAYS", "30");
+        assert_eq!(Retention::from_env().max_age_days, Some(30));
+        env::set_var("SENORU_HISTORY_DAYS", "0");
+        assert_eq!(Retention::from_env().max_age_days, None);
+        env::remove_var("SENORU_HISTORY_DAYS");
+    }
+
+    #[test]
+    fn diffs() {
+        let changes = diff("a\nb\nc\n", "a\nB\nc\nd\n");
+        assert_eq!(format_diff(&changes), " a\n-b\n+B\n c\n+d");
+        assert_eq!(text("notes", &[models::Field::new(models::FieldKind::Username, "Username", "me")]), "Username: me\n\nnotes");
+        assert_eq!(text("notes", &[]), "notes");
+    }
+}
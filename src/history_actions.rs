@@ -0,0 +1,76 @@
+use std::error::Error;
+use chrono::NaiveDateTime;
+use diesel::debug_query;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+
+use crate::db;
+use crate::models;
+use crate::schema::item_history;
+
+/// The versions of an item, newest first.
+pub fn find_by_item_id(item_id: i32) -> Result<Vec<models::ItemVersion>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let versions = item_history::table
+        .filter(item_history::dsl::item_id.eq(item_id))
+        .order((item_history::dsl::date_saved.desc(), item_history::dsl::id.desc()));
+    debug!("{}", debug_query::<Sqlite, _>(&versions));
+    let results = versions.load::<models::ItemVersion>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn find_by_id(id: i32) -> Result<Option<models::ItemVersion>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = item_history::table.find(id).first::<models::ItemVersion>(&mut conn).optional()?;
+    Ok(results)
+}
+
+pub fn find_latest_date_saved(item_id: i32) -> Result<Option<NaiveDateTime>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = item_history::table
+        .filter(item_history::dsl::item_id.eq(item_id))
+        .select(diesel::dsl::max(item_history::dsl::date_saved))
+        .first::<Option<NaiveDateTime>>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn insert(new_version: &models::NewItemVersion) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let insert = diesel::insert_into(item_history::table).values(new_version);
+    debug!("{}", debug_query::<Sqlite, _>(&insert));
+    let num_inserted = insert.execute(&mut conn)?;
+    Ok(num_inserted == 1)
+}
+
+/// Rewrites every version in the vault with `reencrypt`, loading them one at a time, on the caller's connection so it can be part of a larger transaction.
+pub fn reencrypt_all(conn: &mut SqliteConnection, reencrypt: impl Fn(&mut models::ItemVersion) -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    let ids = item_history::table.order(item_history::dsl::id).select(item_history::dsl::id).load::<i32>(conn)?;
+    for id in ids {
+        let mut version = item_history::table.find(id).first::<models::ItemVersion>(conn)?;
+        reencrypt(&mut version)?;
+        diesel::update(item_history::table.find(id)).set(&version).execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Deletes the versions of an item beyond the newest `keep`, and those saved before `saved_before`.
+pub fn prune(item_id: i32, keep: Option<i64>, saved_before: Option<NaiveDateTime>) -> Result<usize, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    conn.immediate_transaction(|conn| {
+        let mut num_deleted = 0;
+        if let Some(saved_before) = saved_before {
+            num_deleted += diesel::delete(item_history::table.filter(item_history::dsl::item_id.eq(item_id)).filter(item_history::dsl::date_saved.lt(saved_before))).execute(conn)?;
+        }
+        if let Some(keep) = keep {
+            let kept_ids = item_history::table
+                .filter(item_history::dsl::item_id.eq(item_id))
+                .order((item_history::dsl::date_saved.desc(), item_history::dsl::id.desc()))
+                .select(item_history::dsl::id)
+                .limit(keep)
+                .load::<i32>(conn)?;
+            num_deleted += diesel::delete(item_history::table.filter(item_history::dsl::item_id.eq(item_id)).filter(item_history::dsl::id.ne_all(kept_ids))).execute(conn)?;
+        }
+        debug!("num_deleted: {}", num_deleted);
+        Ok(num_deleted)
+    })
+}
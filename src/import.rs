@@ -0,0 +1,403 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path;
+use chrono::prelude::*;
+use magic_crypt::MagicCrypt256;
+use magic_crypt::MagicCryptTrait;
+use crate::archive;
+use crate::attachment;
+use crate::attachment_actions;
+use crate::bitwarden;
+use crate::csv_import;
+use crate::directory;
+use crate::folders;
+use crate::history;
+use crate::item_actions;
+use crate::kdbx;
+use crate::models;
+use crate::onepassword;
+use crate::pass;
+use crate::tags;
+
+#[derive(clap::ValueEnum, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Format {
+    Text,
+    Archive,
+    Json,
+    Kdbx,
+    Bitwarden,
+    #[clap(name = "1pux")]
+    OnePassword,
+    Csv,
+    Pass,
+    Directory,
+}
+
+impl Format {
+    pub fn from_path(path: &path::Path) -> Format {
+        if path.is_dir() {
+            return if path.join(".gpg-id").exists() { Format::Pass } else { Format::Directory };
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("senoru") => Format::Archive,
+            Some("kdbx") => Format::Kdbx,
+            Some("json") if archive::is_json_document(path) => Format::Json,
+            Some("json") => Format::Bitwarden,
+            Some("1pux") => Format::OnePassword,
+            Some("csv") => Format::Csv,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// Extra input some formats need before the file can be read.
+#[derive(Debug, Default, Clone)]
+pub struct ReadOptions {
+    pub password: Option<String>,
+    pub keyfile: Option<path::PathBuf>,
+    pub csv_mapping: Option<csv_import::Mapping>,
+    pub binary_files: directory::BinaryFiles,
+}
+
+/// What to do with an imported item whose title is already used in the vault.
+#[derive(clap::ValueEnum, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum OnConflict {
+    Skip,
+    Rename,
+    /// Replace the contents, fields, type and dates of the existing item
+    Overwrite,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Action {
+    Create(String),
+    Skip(String),
+    Rename(String, String),
+    Overwrite(String),
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Create(title) => write!(f, "create {}", title),
+            Action::Skip(title) => write!(f, "skip   {} (title already exists)", title),
+            Action::Rename(title, new_title) => write!(f, "rename {} -> {}", title, new_title),
+            Action::Overwrite(title) => write!(f, "update {} (title already exists)", title),
+        }
+    }
+}
+
+/// An entry read from another password manager, before it is turned into a vault item.
+#[derive(Debug, Clone, Default)]
+pub struct ImportedItem {
+    pub path: Vec<String>,
+    pub title: String,
+    pub item_type: Option<models::ItemType>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub url: Option<String>,
+    pub notes: Option<String>,
+    pub fields: Vec<models::Field>,
+    pub tags: Vec<String>,
+    pub attachments: Vec<(String, Vec<u8>)>,
+    pub date_added: Option<NaiveDateTime>,
+    pub date_last_modified: Option<NaiveDateTime>,
+}
+
+impl ImportedItem {
+    /// The item title, prefixed with the group/folder path it was found under, for showing only: the title may contain the separator too.
+    pub fn full_title(&self) -> String {
+        path_title(&self.path, &self.title)
+    }
+
+    /// The explicit type, or a login when the entry has any credentials.
+    pub fn resolved_item_type(&self) -> models::ItemType {
+        match self.item_type {
+            Some(item_type) => item_type,
+            None if [&self.username, &self.password, &self.url].iter().any(|v| v.as_ref().is_some_and(|s| !s.is_empty())) => models::ItemType::Login,
+            None => models::ItemType::Note,
+        }
+    }
+
+    /// Username, password and URL first, then the other fields in the order they were read.
+    pub fn structured_fields(&self) -> Vec<models::Field> {
+        let mut fields = vec![];
+        let credentials = [
+            (models::FieldKind::Username, "Username", &self.username),
+            (models::FieldKind::Password, "Password", &self.password),
+            (models::FieldKind::Url, "URL", &self.url),
+        ];
+        for (kind, name, value) in credentials.iter() {
+            if let Some(value) = value.as_ref().filter(|s| !s.is_empty()) {
+                fields.push(models::Field::new(*kind, name, value));
+            }
+        }
+        fields.extend(self.fields.iter().cloned());
+        fields
+    }
+
+    /// The free text part of the item, attachments are stored on their own.
+    pub fn contents(&self) -> String {
+        self.notes.clone().unwrap_or_default()
+    }
+
+    pub fn to_new_item(&self, mc: &MagicCrypt256) -> Result<models::NewItem, Box<dyn Error>> {
+        let mut new_item = models::NewItem::new(self.title.clone());
+        new_item.contents = Some(mc.encrypt_str_to_base64(self.contents()));
+        new_item.item_type = self.resolved_item_type().to_string();
+        new_item.encrypt_fields(mc, &self.structured_fields())?;
+        if let Some(date_added) = self.date_added {
+            new_item.date_added = date_added;
+        }
+        if let Some(date_last_modified) = self.date_last_modified {
+            new_item.date_last_modified = date_last_modified;
+        }
+        Ok(new_item)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub created: Vec<String>,
+    pub renamed: Vec<(String, String)>,
+    pub overwritten: Vec<String>,
+    pub conflicts: Vec<String>,
+    pub errors: Vec<(String, String)>,
+    /// The items created, renamed ones included, titles alone can't tell them apart from items in a folder of the same name.
+    pub created_ids: Vec<i32>,
+}
+
+impl ImportReport {
+    /// A count line followed by the outcome of every item.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "{} items imported, {} skipped, {} not imported",
+            self.created.len() + self.renamed.len() + self.overwritten.len(),
+            self.conflicts.len(),
+            self.errors.len()
+        )];
+        self.created.iter().for_each(|t| lines.push(format!("  created  {}", t)));
+        self.renamed.iter().for_each(|(t, n)| lines.push(format!("  renamed  {} -> {}", t, n)));
+        self.overwritten.iter().for_each(|t| lines.push(format!("  updated  {}", t)));
+        self.conflicts.iter().for_each(|t| lines.push(format!("  skipped  {} (title already exists)", t)));
+        self.errors.iter().for_each(|(t, e)| lines.push(format!("  failed   {}: {}", t, e)));
+        lines.join("\n")
+    }
+}
+
+/// The items read from a file, along with the entries (e.g. malformed CSV rows) that had to be left out.
+pub type ReadResult = Result<(Vec<ImportedItem>, Vec<(String, String)>), Box<dyn Error>>;
+
+pub fn read(path: &path::Path, format: Format, options: &ReadOptions) -> ReadResult {
+    let imported_items = match format {
+        Format::Text => match directory::read_file(path, vec![], options.binary_files)? {
+            Some(imported_item) => vec![imported_item],
+            None => return Ok((vec![], vec![(path.to_string_lossy().to_string(), "binary file skipped".into())])),
+        },
+        Format::Archive => archive::read(path, options.password.as_deref().unwrap_or_default())?,
+        Format::Json => archive::read_json(path)?,
+        Format::Kdbx => kdbx::read(path, options.password.as_deref(), options.keyfile.as_deref())?,
+        Format::Bitwarden => bitwarden::read(path)?,
+        Format::OnePassword => onepassword::read(path)?,
+        Format::Csv => return csv_import::read(path, options.csv_mapping.as_ref()),
+        Format::Pass => return pass::read(path),
+        Format::Directory => return directory::read(path, options.binary_files),
+    };
+    Ok((imported_items, vec![]))
+}
+
+fn path_title(path: &[String], title: &str) -> String {
+    let mut parts = path.to_vec();
+    parts.push(title.to_string());
+    parts.join(&folders::SEPARATOR.to_string())
+}
+
+/// Picks the next free "title (n)" for a title that is already taken.
+pub fn unique_title(title: &str, taken: &HashSet<String>) -> String {
+    (2..).map(|n| format!("{} ({})", title, n)).find(|t| !taken.contains(t)).unwrap()
+}
+
+/// Works out, without writing anything, which items would be created, skipped or renamed because of the UNIQUE title.
+pub fn plan(imported_items: &[ImportedItem], on_conflict: OnConflict) -> Result<Vec<Action>, Box<dyn Error>> {
+    Ok(plan_titles(imported_items, on_conflict)?.into_iter().map(|(action, _)| action).collect())
+}
+
+/// What is done with each item, along with the title it gets in its folder.
+fn plan_titles(imported_items: &[ImportedItem], on_conflict: OnConflict) -> Result<Vec<(Action, String)>, Box<dyn Error>> {
+    // titles in the trash are still taken until it is emptied, folders and titles are kept apart as titles may contain the separator
+    let folder_tree = folders::FolderTree::load()?;
+    let mut taken: HashSet<(Vec<String>, String)> = item_actions::find_all_including_trash(None)?.into_iter().map(|i| (folder_tree.path(i.folder_id), i.title)).collect();
+    let mut actions = vec![];
+    for imported_item in imported_items.iter() {
+        let full_title = imported_item.full_title();
+        let key = (imported_item.path.clone(), imported_item.title.clone());
+        let (action, title) = if !taken.contains(&key) {
+            (Action::Create(full_title), imported_item.title.clone())
+        } else {
+            match on_conflict {
+                OnConflict::Skip => (Action::Skip(full_title), imported_item.title.clone()),
+                OnConflict::Rename => {
+                    let titles: HashSet<String> = taken.iter().filter(|(path, _)| *path == imported_item.path).map(|(_, title)| title.clone()).collect();
+                    let new_title = unique_title(&imported_item.title, &titles);
+                    (Action::Rename(full_title, path_title(&imported_item.path, &new_title)), new_title)
+                }
+                OnConflict::Overwrite => (Action::Overwrite(full_title), imported_item.title.clone()),
+            }
+        };
+        taken.insert((imported_item.path.clone(), title.clone()));
+        taken.insert(key);
+        actions.push((action, title));
+    }
+    Ok(actions)
+}
+
+/// Stores the imported attachments with the item, replacing any it already has under the same name.
+fn attach(item_id: i32, attachments: &[(String, Vec<u8>)], mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    for (file_name, data) in attachments.iter() {
+        if let Some(existing) = attachment_actions::find_by_file_name(item_id, file_name)? {
+            attachment_actions::delete(existing.id)?;
+        }
+        attachment::attach_bytes(item_id, file_name, data, mc)?;
+    }
+    Ok(())
+}
+
+/// Adds the imported tags to the item, the tags it already has are kept.
+fn tag(item_id: i32, names: &[String], mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    for name in names.iter() {
+        tags::tag_items(&[item_id], name, mc)?;
+    }
+    Ok(())
+}
+
+fn overwrite(imported_item: &ImportedItem, new_item: models::NewItem, mc: &MagicCrypt256) -> Result<i32, Box<dyn Error>> {
+    // an earlier item of the same import may have taken the title, so it is looked up again here
+    let title = imported_item.full_title();
+    let mut item = folders::find_item_in(&imported_item.path, &imported_item.title)?.ok_or_else(|| format!("no item titled: {}", title))?;
+    item.contents = new_item.contents;
+    item.item_type = new_item.item_type;
+    item.fields = new_item.fields;
+    item.date_last_modified = new_item.date_last_modified;
+    item.deleted_at = None;
+    if !history::update(&mut item, mc)? {
+        return Err(format!("{} changed while it was being overwritten", title).into());
+    }
+    Ok(item.id)
+}
+
+fn insert(imported_item: &ImportedItem, mut new_item: models::NewItem) -> Result<i32, Box<dyn Error>> {
+    folders::place(&mut new_item, &imported_item.path)?;
+    item_actions::insert(&new_item)?;
+    let item = item_actions::find_by_folder_and_title(new_item.folder_id, &new_item.title)?.ok_or_else(|| format!("no item titled: {}", imported_item.full_title()))?;
+    Ok(item.id)
+}
+
+pub fn import_items(imported_items: &[ImportedItem], on_conflict: OnConflict, mc: &MagicCrypt256) -> Result<ImportReport, Box<dyn Error>> {
+    let actions = plan_titles(imported_items, on_conflict)?;
+    let mut report = ImportReport::default();
+    for (imported_item, (action, title)) in imported_items.iter().zip(actions) {
+        let mut new_item = match imported_item.to_new_item(mc) {
+            Ok(new_item) => new_item,
+            Err(e) => {
+                report.errors.push((imported_item.full_title(), e.to_string()));
+                continue;
+            }
+        };
+        new_item.title = title;
+        let stored = match &action {
+            Action::Skip(title) => {
+                warn!("title already exists: {}", title);
+                report.conflicts.push(title.clone());
+                continue;
+            }
+            Action::Overwrite(_) => overwrite(imported_item, new_item, mc),
+            Action::Create(_) | Action::Rename(_, _) => insert(imported_item, new_item),
+        };
+        match stored.and_then(|item_id| attach(item_id, &imported_item.attachments, mc).and_then(|_| tag(item_id, &imported_item.tags, mc)).map(|_| item_id)) {
+            Ok(item_id) => match action {
+                Action::Create(title) => {
+                    report.created.push(title);
+                    report.created_ids.push(item_id);
+                }
+                Action::Rename(title, new_title) => {
+                    report.renamed.push((title, new_title));
+                    report.created_ids.push(item_id);
+                }
+                Action::Overwrite(title) => report.overwritten.push(title),
+                Action::Skip(_) => unreachable!(),
+            },
+            Err(e) => report.errors.push((imported_item.full_title(), e.to_string())),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::folder_actions;
+
+    fn imported(path: &[&str], title: &str) -> ImportedItem {
+        ImportedItem {
+            path: path.iter().map(|name| name.to_string()).collect(),
+            title: title.into(),
+            notes: Some(format!("{} notes", title)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn conflicts_are_planned_per_folder() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        import_items(&[imported(&["import plan test", "work"], "login")], OnConflict::Skip, &mc).unwrap();
+        let items = vec![
+            imported(&["import plan test", "work"], "login"),
+            imported(&["import plan test", "work"], "login"),
+            imported(&["import plan test", "home"], "login"),
+            imported(&["import plan test", "home"], "login"),
+            imported(&["import plan test"], "work/login"),
+        ];
+
+        // a dry run only plans, nothing is written
+        let root = folder_actions::find_child(None, "import plan test").unwrap().unwrap();
+        let work = folder_actions::find_child(Some(root.id), "work").unwrap().unwrap();
+        let planned = plan(&items, OnConflict::Rename).unwrap();
+        assert_eq!(
+            planned,
+            vec![
+                Action::Rename("import plan test/work/login".into(), "import plan test/work/login (2)".into()),
+                Action::Rename("import plan test/work/login".into(), "import plan test/work/login (3)".into()),
+                Action::Create("import plan test/home/login".into()),
+                Action::Rename("import plan test/home/login".into(), "import plan test/home/login (2)".into()),
+                // the same path written out, but a title in a folder of its own
+                Action::Create("import plan test/work/login".into()),
+            ]
+        );
+        assert_eq!(item_actions::find_by_folder_id(work.id).unwrap().len(), 1);
+        assert!(item_actions::find_by_folder_id(root.id).unwrap().is_empty());
+        assert!(folder_actions::find_child(Some(root.id), "home").unwrap().is_none());
+
+        assert_eq!(
+            plan(&items[..3], OnConflict::Skip).unwrap(),
+            vec![
+                Action::Skip("import plan test/work/login".into()),
+                Action::Skip("import plan test/work/login".into()),
+                Action::Create("import plan test/home/login".into()),
+            ]
+        );
+        assert_eq!(plan(&items[..1], OnConflict::Overwrite).unwrap(), vec![Action::Overwrite("import plan test/work/login".into())]);
+
+        // the import does what was planned
+        let report = import_items(&items, OnConflict::Rename, &mc).unwrap();
+        assert_eq!(report.created, vec!["import plan test/home/login", "import plan test/work/login"]);
+        assert_eq!(report.renamed.len(), 3);
+        assert!(report.errors.is_empty());
+        assert_eq!(item_actions::find_by_folder_id(work.id).unwrap().len(), 3);
+        assert_eq!(item_actions::find_by_folder_id(root.id).unwrap().len(), 1);
+        let copy = folders::find_item_in(&["import plan test".into(), "work".into()], "login (3)").unwrap().unwrap();
+        assert_eq!(copy.decrypt_contents(&mc).unwrap(), "login notes");
+        assert!(folders::find_item_in(&["import plan test".into()], "work/login").unwrap().is_some());
+    }
+}
@@ -1,12 +1,56 @@
+use std::error::Error;
+use chrono::prelude::*;
 use diesel::debug_query;
 use diesel::prelude::*;
 use diesel::sqlite::Sqlite;
 
 use crate::db;
 use crate::models;
-use crate::schema::items;
+use crate::schema::{attachment_chunks, attachments, item_history, item_tags, items};
 
+/// The items in the vault, leaving out the trash.
 pub fn find_all(limit: Option<i64>) -> Result<Vec<models::Item>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = match limit {
+        Some(l) => items::table
+            .filter(items::dsl::deleted_at.is_null())
+            .order(items::dsl::title)
+            .limit(l)
+            .load::<models::Item>(&mut conn)
+            .expect("failed to find all"),
+        None => items::table
+            .filter(items::dsl::deleted_at.is_null())
+            .order(items::dsl::title)
+            .load::<models::Item>(&mut conn)
+            .expect("failed to find all"),
+    };
+    Ok(results)
+}
+
+/// The titles and metadata of the items outside the trash, leaving the contents and fields in the database.
+pub fn find_all_summaries() -> Result<Vec<models::ItemSummary>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let query = items::table
+        .filter(items::dsl::deleted_at.is_null())
+        .order(items::dsl::title)
+        .select((items::dsl::id, items::dsl::title, items::dsl::item_type, items::dsl::folder_id, items::dsl::date_added, items::dsl::date_last_modified));
+    debug!("{}", debug_query::<Sqlite, _>(&query));
+    let results = query.load::<models::ItemSummary>(&mut conn)?;
+    Ok(results)
+}
+
+/// The encrypted fields of the items outside the trash, by item id.
+pub fn find_all_fields() -> Result<Vec<(i32, Option<String>)>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = items::table
+        .filter(items::dsl::deleted_at.is_null())
+        .select((items::dsl::id, items::dsl::fields))
+        .load::<(i32, Option<String>)>(&mut conn)?;
+    Ok(results)
+}
+
+/// Every item, the trash included, for whatever has to cover all of them: re-encrypting, checking the key or taken titles.
+pub fn find_all_including_trash(limit: Option<i64>) -> Result<Vec<models::Item>, diesel::result::Error> {
     let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
     let results = match limit {
         Some(l) => items::table
@@ -19,7 +63,6 @@ pub fn find_all(limit: Option<i64>) -> Result<Vec<models::Item>, diesel::result:
     Ok(results)
 }
 
-#[allow(dead_code)]
 pub fn find_by_id(gid: i32) -> Result<Option<models::Item>, diesel::result::Error> {
     let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
     let item = items::table.filter(items::dsl::id.eq(gid));
@@ -28,14 +71,76 @@ pub fn find_by_id(gid: i32) -> Result<Option<models::Item>, diesel::result::Erro
     Ok(results)
 }
 
-pub fn find_by_title(title: &String) -> Result<Option<models::Item>, diesel::result::Error> {
+/// The item titled `title` directly inside `folder_id`, or at the top level for `None`, the trash included.
+pub fn find_by_folder_and_title(folder_id: Option<i32>, title: &str) -> Result<Option<models::Item>, diesel::result::Error> {
     let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
-    let item = items::table.filter(items::dsl::title.eq(title));
-    debug!("{}", debug_query::<Sqlite, _>(&item).to_string());
-    let results = item.first::<models::Item>(&mut conn).optional()?;
+    let query = items::table.filter(items::dsl::title.eq(title)).into_boxed();
+    let query = match folder_id {
+        Some(folder_id) => query.filter(items::dsl::folder_id.eq(folder_id)),
+        None => query.filter(items::dsl::folder_id.is_null()),
+    };
+    debug!("{}", debug_query::<Sqlite, _>(&query));
+    let results = query.first::<models::Item>(&mut conn).optional()?;
     Ok(results)
 }
 
+/// The items directly inside a folder, the trash included.
+pub fn find_by_folder_id(folder_id: i32) -> Result<Vec<models::Item>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = items::table.filter(items::dsl::folder_id.eq(folder_id)).order(items::dsl::title).load::<models::Item>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn find_by_item_type(item_type: &models::ItemType) -> Result<Vec<models::Item>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let items = items::table
+        .filter(items::dsl::item_type.eq(item_type.as_str()))
+        .filter(items::dsl::deleted_at.is_null())
+        .order(items::dsl::title);
+    debug!("{}", debug_query::<Sqlite, _>(&items));
+    let results = items.load::<models::Item>(&mut conn)?;
+    Ok(results)
+}
+
+/// The items in the trash, most recently removed first.
+pub fn find_trash() -> Result<Vec<models::Item>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let items = items::table.filter(items::dsl::deleted_at.is_not_null()).order(items::dsl::deleted_at.desc());
+    debug!("{}", debug_query::<Sqlite, _>(&items));
+    let results = items.load::<models::Item>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn find_trashed_before(deleted_before: NaiveDateTime) -> Result<Vec<i32>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = items::table.filter(items::dsl::deleted_at.lt(deleted_before)).select(items::dsl::id).load::<i32>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn move_to_trash(gid: &i32) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let update = diesel::update(items::table.filter(items::dsl::id.eq(gid))).set((items::dsl::deleted_at.eq(Some(Utc::now().naive_utc())), items::dsl::revision.eq(items::dsl::revision + 1)));
+    debug!("{}", debug_query::<Sqlite, _>(&update));
+    let num_updated = update.execute(&mut conn)?;
+    Ok(num_updated == 1)
+}
+
+pub fn move_to_folder(gid: &i32, folder_id: Option<i32>) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let update = diesel::update(items::table.filter(items::dsl::id.eq(gid))).set((items::dsl::folder_id.eq(folder_id), items::dsl::revision.eq(items::dsl::revision + 1)));
+    debug!("{}", debug_query::<Sqlite, _>(&update));
+    let num_updated = update.execute(&mut conn)?;
+    Ok(num_updated == 1)
+}
+
+pub fn restore_from_trash(gid: &i32) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let update = diesel::update(items::table.filter(items::dsl::id.eq(gid))).set((items::dsl::deleted_at.eq(None::<NaiveDateTime>), items::dsl::revision.eq(items::dsl::revision + 1)));
+    debug!("{}", debug_query::<Sqlite, _>(&update));
+    let num_updated = update.execute(&mut conn)?;
+    Ok(num_updated == 1)
+}
+
 pub fn insert(new_item: &models::NewItem) -> Result<bool, diesel::result::Error> {
     let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
     let insert = diesel::insert_into(items::table).values(new_item);
@@ -45,20 +150,61 @@ pub fn insert(new_item: &models::NewItem) -> Result<bool, diesel::result::Error>
     Ok(num_inserted == 1)
 }
 
+/// Inserts the items in one transaction, for filling a large vault in the benchmark.
+#[cfg(test)]
+pub fn insert_all(new_items: &[models::NewItem]) -> Result<usize, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    conn.immediate_transaction(|conn| {
+        let mut num_inserted = 0;
+        for new_item in new_items.iter() {
+            num_inserted += diesel::insert_into(items::table).values(new_item).execute(conn)?;
+        }
+        debug!("num_inserted: {}", num_inserted);
+        Ok(num_inserted)
+    })
+}
+
+/// Deletes the item for good, along with its attachments and history.
 pub fn delete(gid: &i32) -> Result<bool, diesel::result::Error> {
     let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
-    let delete = diesel::delete(items::table.filter(items::dsl::id.eq(gid)));
-    debug!("{}", debug_query::<Sqlite, _>(&delete).to_string());
-    let num_deleted = delete.execute(&mut conn)?;
-    debug!("num_deleted: {}", num_deleted);
-    Ok(num_deleted == 1)
+    conn.immediate_transaction(|conn| {
+        let attachment_ids = attachments::table.filter(attachments::dsl::item_id.eq(gid)).select(attachments::dsl::id);
+        diesel::delete(attachment_chunks::table.filter(attachment_chunks::dsl::attachment_id.eq_any(attachment_ids))).execute(conn)?;
+        diesel::delete(attachments::table.filter(attachments::dsl::item_id.eq(gid))).execute(conn)?;
+        diesel::delete(item_history::table.filter(item_history::dsl::item_id.eq(gid))).execute(conn)?;
+        diesel::delete(item_tags::table.filter(item_tags::dsl::item_id.eq(gid))).execute(conn)?;
+        let delete = diesel::delete(items::table.filter(items::dsl::id.eq(gid)));
+        debug!("{}", debug_query::<Sqlite, _>(&delete).to_string());
+        let num_deleted = delete.execute(conn)?;
+        debug!("num_deleted: {}", num_deleted);
+        Ok(num_deleted == 1)
+    })
+}
+
+/// Rewrites every item, the trash included, with `reencrypt` and moves each on to its next revision, on the caller's connection so it can be part of a larger transaction.
+pub fn reencrypt_all(conn: &mut SqliteConnection, reencrypt: impl Fn(&mut models::Item) -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    let ids = items::table.order(items::dsl::id).select(items::dsl::id).load::<i32>(conn)?;
+    for id in ids {
+        let mut item = items::table.find(id).first::<models::Item>(conn)?;
+        reencrypt(&mut item)?;
+        item.revision += 1;
+        diesel::update(items::table.find(id)).set(&item).execute(conn)?;
+    }
+    Ok(())
 }
 
-pub fn update(item: &models::Item) -> Result<bool, diesel::result::Error> {
+/// Writes the item if it is still at the revision it was read at, and moves it on to the next one. `false` when something else wrote it in between, or deleted it, and nothing was written.
+pub fn update(item: &mut models::Item) -> Result<bool, diesel::result::Error> {
     let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
-    let update = diesel::update(items::table.filter(items::dsl::id.eq(item.id))).set(item);
+    let read_at = item.revision;
+    item.revision += 1;
+    let update = diesel::update(items::table.filter(items::dsl::id.eq(item.id)).filter(items::dsl::revision.eq(read_at))).set(&*item);
     debug!("{}", debug_query::<Sqlite, _>(&update).to_string());
     let num_updated = update.execute(&mut conn)?;
     debug!("num_updated: {}", num_updated);
+    if num_updated != 1 {
+        item.revision = read_at;
+        warn!("item {} changed since revision {}, not writing it", item.id, read_at);
+    }
     Ok(num_updated == 1)
 }
@@ -0,0 +1,293 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path;
+use keepass::config::DatabaseConfig;
+use keepass::db::{fields, Attachment, CustomDataItem, CustomDataValue, Entry, Group, History, Value};
+use keepass::{Database, DatabaseKey};
+use magic_crypt::MagicCrypt256;
+use serde::{Deserialize, Serialize};
+use crate::attachment;
+use crate::attachment_actions;
+use crate::history_actions;
+use crate::folders;
+use crate::import;
+use crate::import::ImportedItem;
+use crate::models;
+use crate::tags;
+
+/// Custom field used to carry the senoru item type through a KDBX or password-store export.
+pub const ITEM_TYPE_FIELD: &str = "senoru.item_type";
+
+/// Entry custom data holding the `FieldLayout` of the item fields as JSON.
+const FIELD_LAYOUT_KEY: &str = "senoru.fields";
+
+/// Where a field of the item went in the entry, KeePass has no place for the order and kind of fields.
+#[derive(Debug, Serialize, Deserialize)]
+struct FieldLayout {
+    key: String,
+    name: String,
+    kind: models::FieldKind,
+}
+
+fn database_key(password: Option<&str>, keyfile: Option<&path::Path>) -> Result<DatabaseKey, Box<dyn Error>> {
+    let mut key = DatabaseKey::new();
+    if let Some(password) = password {
+        key = key.with_password(password);
+    }
+    if let Some(keyfile) = keyfile {
+        key = key.with_keyfile(&mut fs::File::open(keyfile)?)?;
+    }
+    if key.is_empty() {
+        return Err("a password or a keyfile is required".into());
+    }
+    Ok(key)
+}
+
+/// Reads every entry of a KDBX database, groups below the root group become the item path.
+pub fn read(path: &path::Path, password: Option<&str>, keyfile: Option<&path::Path>) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+    let key = database_key(password, keyfile)?;
+    let database = Database::open(&mut fs::File::open(path)?, key)?;
+    let mut imported_items = vec![];
+    read_group(&database, &database.root, &mut vec![], &mut imported_items);
+    info!("read {} entries from {}", imported_items.len(), path.to_string_lossy());
+    Ok(imported_items)
+}
+
+fn read_group(database: &Database, group: &Group, group_path: &mut Vec<String>, imported_items: &mut Vec<ImportedItem>) {
+    for entry in group.entries.iter() {
+        imported_items.push(read_entry(entry, group_path));
+    }
+    for child in group.groups.iter() {
+        if database.meta.recyclebin_uuid == Some(child.uuid) {
+            debug!("skipping recycle bin: {}", child.name);
+            continue;
+        }
+        group_path.push(child.name.clone());
+        read_group(database, child, group_path, imported_items);
+        group_path.pop();
+    }
+}
+
+fn read_entry(entry: &Entry, group_path: &[String]) -> ImportedItem {
+    let layout = field_layout(entry).unwrap_or_default();
+    let in_layout = |key: &str| layout.iter().any(|l| l.key == key);
+    // the fields senoru wrote in their order, then any added in KeePass since
+    let mut custom_fields: Vec<models::Field> = entry
+        .fields
+        .iter()
+        .filter(|(k, _)| !fields::KNOWN_FIELDS.contains(&k.as_str()) && k.as_str() != ITEM_TYPE_FIELD && !in_layout(k))
+        .map(|(k, v)| models::Field::labelled(k.clone(), v.get().clone()))
+        .collect();
+    custom_fields.sort_by(|a, b| a.name.cmp(&b.name));
+    // KeePass leaves out fields without a value, the layout still has them
+    let mut item_fields: Vec<models::Field> = layout.iter().map(|l| models::Field::new(l.kind, &l.name, entry.get(&l.key).unwrap_or_default())).collect();
+    item_fields.extend(custom_fields);
+    let standard = |key: &str| entry.get(key).filter(|_| !in_layout(key)).map(String::from);
+
+    let mut attachments: Vec<(String, Vec<u8>)> = entry.attachments.iter().map(|(name, attachment)| (name.clone(), attachment.get().clone())).collect();
+    attachments.sort();
+
+    ImportedItem {
+        path: group_path.to_vec(),
+        title: entry.get_title().filter(|t| !t.is_empty()).unwrap_or("Untitled").to_string(),
+        item_type: entry.get(ITEM_TYPE_FIELD).and_then(|t| t.parse::<models::ItemType>().ok()),
+        username: standard(fields::USERNAME),
+        password: standard(fields::PASSWORD),
+        url: standard(fields::URL),
+        notes: entry.get(fields::NOTES).map(String::from),
+        fields: item_fields,
+        tags: entry.tags.clone(),
+        attachments,
+        date_added: entry.times.creation,
+        date_last_modified: entry.times.last_modification,
+    }
+}
+
+/// The field layout senoru wrote into the entry, `None` for entries from elsewhere.
+fn field_layout(entry: &Entry) -> Option<Vec<FieldLayout>> {
+    match entry.custom_data.get(FIELD_LAYOUT_KEY)?.value.as_ref()? {
+        CustomDataValue::String(json) => serde_json::from_str(json).map_err(|e| warn!("ignoring the field layout of {:?}: {}", entry.get_title(), e)).ok(),
+        CustomDataValue::Binary(_) => None,
+    }
+}
+
+/// Writes the items to a new KDBX 4 database, the folders of each item become its groups and its saved versions the entry history.
+pub fn write(path: &path::Path, items: &[models::Item], mc: &MagicCrypt256, password: &str) -> Result<(), Box<dyn Error>> {
+    let mut database = Database::new(DatabaseConfig::default());
+    database.meta.database_name = Some("senoru".into());
+    database.meta.generator = Some("senoru".into());
+
+    let folder_tree = folders::FolderTree::load()?;
+    for item in items.iter() {
+        let group_path = folder_tree.path(item.folder_id);
+        let mut entry = Entry::new();
+        set_contents(&mut entry, &item.title, &item.item_type, &item.decrypt_contents(mc)?, &item.decrypt_fields(mc)?)?;
+        let mut history = History::default();
+        // newest first, each one goes in front of the newer ones
+        for version in history_actions::find_by_item_id(item.id)?.iter() {
+            let mut old_entry = Entry::new();
+            old_entry.uuid = entry.uuid;
+            set_contents(&mut old_entry, &item.title, &version.item_type, &version.decrypt_contents(mc)?, &version.decrypt_fields(mc)?)?;
+            old_entry.times.creation = Some(item.date_added);
+            old_entry.times.last_modification = Some(version.date_saved);
+            history.add_entry(old_entry);
+        }
+        if !history.get_entries().is_empty() {
+            entry.history = Some(history);
+        }
+        entry.tags = tags::item_names(item.id, mc)?;
+        for attachment in attachment_actions::find_by_item_id(item.id)?.iter() {
+            let data = Value::protected(attachment::read(attachment, mc)?);
+            entry.attachments.insert(attachment.file_name.clone(), Attachment { data });
+        }
+        entry.times.creation = Some(item.date_added);
+        entry.times.last_modification = Some(item.date_last_modified);
+        group_for_path(&mut database.root, &group_path).entries.push(entry);
+    }
+
+    database.save(&mut fs::File::create(path)?, DatabaseKey::new().with_password(password))?;
+    info!("wrote {} entries to {}", items.len(), path.to_string_lossy());
+    Ok(())
+}
+
+/// Fills in the title, notes, fields and type of an entry, or of one in its history.
+fn set_contents(entry: &mut Entry, title: &str, item_type: &str, contents: &str, item_fields: &[models::Field]) -> Result<(), Box<dyn Error>> {
+    entry.set_unprotected(fields::TITLE, title);
+    entry.set_protected(fields::NOTES, contents);
+    let layout = set_fields(entry, item_fields);
+    if !layout.is_empty() {
+        let value = Some(CustomDataValue::String(serde_json::to_string(&layout)?));
+        entry.custom_data.insert(FIELD_LAYOUT_KEY.to_string(), CustomDataItem { value, last_modification_time: None });
+    }
+    if item_type.parse::<models::ItemType>().unwrap_or(models::ItemType::Note) != models::ItemType::Note {
+        entry.set_unprotected(ITEM_TYPE_FIELD, item_type);
+    }
+    Ok(())
+}
+
+/// The first username, password and URL go to the standard KeePass fields, everything else becomes a custom field.
+fn set_fields(entry: &mut Entry, item_fields: &[models::Field]) -> Vec<FieldLayout> {
+    let mut layout = vec![];
+    let mut taken: HashSet<String> = fields::KNOWN_FIELDS.iter().map(|f| f.to_string()).collect();
+    taken.insert(ITEM_TYPE_FIELD.to_string());
+    for field in item_fields.iter() {
+        let standard = match field.kind {
+            models::FieldKind::Username => Some(fields::USERNAME),
+            models::FieldKind::Password => Some(fields::PASSWORD),
+            models::FieldKind::Url => Some(fields::URL),
+            _ => None,
+        };
+        let name = match standard {
+            Some(name) if entry.get(name).is_none() => name.to_string(),
+            _ if taken.contains(&field.name) => import::unique_title(&field.name, &taken),
+            _ => field.name.clone(),
+        };
+        if field.kind.is_secret() {
+            entry.set_protected(&name, &field.value);
+        } else {
+            entry.set_unprotected(&name, &field.value);
+        }
+        taken.insert(name.clone());
+        layout.push(FieldLayout {
+            key: name,
+            name: field.name.clone(),
+            kind: field.kind,
+        });
+    }
+    layout
+}
+
+fn group_for_path<'a>(group: &'a mut Group, group_path: &[String]) -> &'a mut Group {
+    match group_path.split_first() {
+        None => group,
+        Some((name, rest)) => {
+            let index = match group.groups.iter().position(|g| g.name == *name) {
+                Some(i) => i,
+                None => {
+                    group.groups.push(Group::new(name));
+                    group.groups.len() - 1
+                }
+            };
+            group_for_path(&mut group.groups[index], rest)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use magic_crypt::MagicCryptTrait;
+    use crate::db;
+    use crate::history;
+    use crate::item_actions;
+
+    #[test]
+    fn write_then_read_keeps_folders_fields_and_history() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let folder_id = folders::find_or_create(&["kdbx test".to_string(), "cards".to_string()]).unwrap();
+        let mut new_item = models::NewItem::new("visa/debit".into());
+        new_item.folder_id = folder_id;
+        new_item.item_type = models::ItemType::Card.to_string();
+        new_item.contents = Some(mc.encrypt_str_to_base64("first"));
+        let item_fields = vec![
+            models::Field::new(models::FieldKind::CardNumber, "Number", "4111"),
+            models::Field::new(models::FieldKind::Password, "Security code", ""),
+            models::Field::new(models::FieldKind::Text, "Number", "again"),
+            models::Field::new(models::FieldKind::Username, "Username", "me"),
+        ];
+        new_item.encrypt_fields(&mc, &item_fields).unwrap();
+        item_actions::insert(&new_item).unwrap();
+        let mut item = item_actions::find_by_folder_and_title(folder_id, "visa/debit").unwrap().unwrap();
+        item.contents = Some(mc.encrypt_str_to_base64("second"));
+        assert!(history::update(&mut item, &mc).unwrap());
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("test.kdbx");
+        write(&file, &[item], &mc, "password").unwrap();
+
+        let database = Database::open(&mut fs::File::open(&file).unwrap(), DatabaseKey::new().with_password("password")).unwrap();
+        let entry = &database.root.groups[0].groups[0].entries[0];
+        let old_notes: Vec<&str> = entry.history.as_ref().unwrap().get_entries().iter().filter_map(|e| e.get(fields::NOTES)).collect();
+        assert_eq!(old_notes, vec!["first"]);
+
+        let imported_items = read(&file, Some("password"), None).unwrap();
+        assert_eq!(imported_items.len(), 1);
+        let imported_item = &imported_items[0];
+        assert_eq!(imported_item.path, vec!["kdbx test".to_string(), "cards".to_string()]);
+        assert_eq!(imported_item.title, "visa/debit");
+        assert_eq!(imported_item.item_type, Some(models::ItemType::Card));
+        assert_eq!(imported_item.contents(), "second");
+        assert_eq!(imported_item.structured_fields(), item_fields);
+    }
+
+    #[test]
+    fn read_guesses_fields_of_other_databases() {
+        let mut entry = Entry::new();
+        entry.set_unprotected(fields::TITLE, "mail");
+        entry.set_unprotected(fields::USERNAME, "me");
+        entry.set_protected(fields::PASSWORD, "secret");
+        entry.set_unprotected("website", "https://mail.example.com");
+        entry.set_unprotected("Answer", "42");
+        let mut database = Database::new(DatabaseConfig::default());
+        let mut group = Group::new("web");
+        group.entries.push(entry);
+        database.root.groups.push(group);
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("other.kdbx");
+        database.save(&mut fs::File::create(&file).unwrap(), DatabaseKey::new().with_password("password")).unwrap();
+
+        let imported_items = read(&file, Some("password"), None).unwrap();
+        let imported_item = &imported_items[0];
+        assert_eq!((imported_item.path.clone(), imported_item.title.as_str()), (vec!["web".to_string()], "mail"));
+        assert_eq!(imported_item.username.as_deref(), Some("me"));
+        assert_eq!(imported_item.password.as_deref(), Some("secret"));
+        assert_eq!(
+            imported_item.fields,
+            vec![models::Field::new(models::FieldKind::Text, "Answer", "42"), models::Field::new(models::FieldKind::Url, "website", "https://mail.example.com")]
+        );
+        assert_eq!(imported_item.resolved_item_type(), models::ItemType::Login);
+        assert!(read(&file, Some("wrong"), None).is_err());
+    }
+}
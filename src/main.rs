@@ -15,27 +15,73 @@ extern crate magic_crypt;
 
 use std::env;
 use std::error;
+use std::os::unix::fs::DirBuilderExt;
 use std::path;
 use std::sync::{Arc, Mutex};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use gio::prelude::*;
 use gtk::prelude::*;
 use passwords::analyzer;
 use passwords::scorer;
 
+mod archive;
+mod attachment;
+mod attachment_actions;
+mod autosave;
+#[cfg(test)]
+mod bench;
+mod bitwarden;
+mod cli;
+mod contact_actions;
+mod content_cache;
+mod csv_import;
 mod db;
+mod directory;
+mod export;
+mod folder_actions;
+mod folders;
 mod gui;
+mod history;
+mod history_actions;
+mod import;
 mod item_actions;
+mod kdbx;
+mod master_key;
+mod merge;
 mod models;
+mod onepassword;
+mod otp;
+mod pass;
+mod query;
+mod saved_search_actions;
+mod saved_searches;
 mod schema;
+mod search;
+mod share;
+mod ssh_agent;
+mod tag_actions;
+mod tags;
+mod trash;
+mod vault_lock;
 
 pub struct AppCore {
     pub magic_crypt: Arc<Mutex<Option<magic_crypt::MagicCrypt256>>>,
+    /// The tags the item list is filtered by, items must carry all of them.
+    pub tag_filter: Arc<Mutex<Vec<String>>>,
+    /// Built when the GUI is unlocked, dropped with the process.
+    pub search_index: Arc<Mutex<search::SearchIndex>>,
+    pub search_query: Arc<Mutex<String>>,
+    /// The items shown last, decrypted, cleared when the vault is locked.
+    pub content_cache: Arc<Mutex<content_cache::ContentCache>>,
 }
 
 lazy_static! {
     static ref APP_CORE: AppCore = AppCore {
-        magic_crypt: Arc::new(Mutex::new(None))
+        magic_crypt: Arc::new(Mutex::new(None)),
+        tag_filter: Arc::new(Mutex::new(vec![])),
+        search_index: Arc::new(Mutex::new(search::SearchIndex::default())),
+        search_query: Arc::new(Mutex::new(String::new())),
+        content_cache: Arc::new(Mutex::new(content_cache::ContentCache::default())),
     };
 }
 
@@ -44,6 +90,299 @@ lazy_static! {
 struct Options {
     #[clap(short, long)]
     database: Option<path::PathBuf>,
+
+    /// How many earlier versions of each item to keep, 0 for no limit
+    #[clap(long, default_value_t = history::DEFAULT_MAX_VERSIONS)]
+    history_versions: i64,
+
+    /// Drop earlier versions after this many days, kept for good when omitted
+    #[clap(long)]
+    history_days: Option<i64>,
+
+    /// Delete removed items after this many days in the trash, 0 to keep them until the trash is emptied
+    #[clap(long, default_value_t = trash::DEFAULT_PURGE_DAYS)]
+    trash_days: i64,
+
+    /// Read the master key from the first line of this file instead of asking for it, for scripts
+    #[clap(long)]
+    key_file: Option<path::PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, PartialEq, Debug)]
+enum Command {
+    /// Serve the SSH keys stored in the vault over the OpenSSH agent protocol
+    SshAgent {
+        /// Path of the agent socket, defaults to ~/.senoru/ssh-agent.sock
+        #[clap(short, long)]
+        socket: Option<path::PathBuf>,
+
+        /// Ask for confirmation through SSH_ASKPASS every time a key is used
+        #[clap(short, long)]
+        confirm: bool,
+    },
+
+    /// Import items from a text file, a senoru archive or JSON document, a KeePass database, a Bitwarden JSON export, a 1Password .1pux archive, a CSV file, a pass directory or a directory tree
+    Import {
+        path: path::PathBuf,
+
+        /// Format of the file, guessed from its extension when omitted
+        #[clap(short, long, value_enum)]
+        format: Option<import::Format>,
+
+        /// KeePass keyfile
+        #[clap(short, long)]
+        keyfile: Option<path::PathBuf>,
+
+        /// Column layout of a CSV file, detected from the header when omitted
+        #[clap(long, value_enum)]
+        csv_preset: Option<csv_import::Preset>,
+
+        /// Column mapping of a CSV file, e.g. "title=Name,username=Login,password=Pass,url=Site"
+        #[clap(long, conflicts_with = "csv_preset")]
+        csv_mapping: Option<csv_import::Mapping>,
+
+        /// What to do with files that are not UTF-8 text
+        #[clap(long, value_enum, default_value = "skip")]
+        binary_files: directory::BinaryFiles,
+
+        /// What to do with items whose title already exists in the vault
+        #[clap(long, value_enum, default_value = "skip")]
+        on_conflict: import::OnConflict,
+
+        /// Only list what would be created, skipped, renamed or updated
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Export the vault, or the given items, to an encrypted archive, a JSON document, a KeePass database or a pass directory
+    Export {
+        path: path::PathBuf,
+
+        #[clap(short, long, value_enum, default_value = "archive")]
+        format: export::Format,
+
+        /// Title of an item to export, can be repeated
+        #[clap(short, long)]
+        title: Vec<String>,
+
+        /// GPG key the pass entries are encrypted to, can be repeated, defaults to the store's .gpg-id
+        #[clap(short, long)]
+        recipient: Vec<String>,
+
+        /// Write json and plaintext exports without asking for confirmation
+        #[clap(short, long)]
+        yes: bool,
+    },
+
+    /// Encrypt items to one or more contacts, printing an armored message they can receive
+    Share {
+        /// Title of an item to share, can be repeated
+        #[clap(short, long, required = true)]
+        title: Vec<String>,
+
+        /// Name of the contact to share with, can be repeated
+        #[clap(long, required = true)]
+        to: Vec<String>,
+
+        /// Write the message to a file instead of stdout
+        #[clap(short, long)]
+        output: Option<path::PathBuf>,
+    },
+
+    /// Import items shared with this vault, read from a file or stdin
+    Receive {
+        path: Option<path::PathBuf>,
+
+        /// What to do with items whose title already exists in the vault
+        #[clap(long, value_enum, default_value = "rename")]
+        on_conflict: import::OnConflict,
+    },
+
+    /// List, add or remove the contacts items can be shared with
+    Contacts {
+        #[clap(subcommand)]
+        command: Option<ContactsCommand>,
+    },
+
+    /// Print the public key teammates need to share items with this vault
+    PublicKey,
+
+    /// Print the current one-time password of an item's TOTP field
+    Otp {
+        title: String,
+    },
+
+    /// Attach files to an item, or list its attachments when no file is given
+    Attach {
+        title: String,
+        files: Vec<path::PathBuf>,
+    },
+
+    /// Save an attachment of an item to a file and remove it from the item
+    Detach {
+        title: String,
+        file_name: String,
+
+        /// Where to save the attachment, its file name in the current directory by default
+        #[clap(short, long)]
+        output: Option<path::PathBuf>,
+
+        /// Keep the attachment in the vault, only save a copy
+        #[clap(long)]
+        keep: bool,
+    },
+
+    /// List the items in the vault by their folder path, e.g. work/aws/root
+    List {
+        /// List the items in the trash instead, with when they were removed
+        #[clap(long)]
+        trash: bool,
+
+        /// Only list the items with this tag, can be repeated to require several
+        #[clap(long, conflicts_with = "trash")]
+        tag: Vec<String>,
+
+        /// Only list the items matching this saved search
+        #[clap(long, conflicts_with = "trash")]
+        saved: Option<String>,
+    },
+
+    /// List the items whose title, contents or fields match the words of the text, closest matches first, typos are tolerated. Filters such as tag:aws, type:login, folder:work, modified:<90d, is:weak, is:reused, has:otp or has:attachment narrow it down, a leading - negates one
+    Search {
+        text: String,
+
+        /// Only list the items with this tag, can be repeated to require several
+        #[clap(long)]
+        tag: Vec<String>,
+    },
+
+    /// List the tags, tag or untag items, and rename or merge tags
+    Tags {
+        #[clap(subcommand)]
+        command: Option<TagsCommand>,
+    },
+
+    /// List, save, rename or remove the named searches `list --saved` and the GUI show
+    Searches {
+        #[clap(subcommand)]
+        command: Option<SearchesCommand>,
+    },
+
+    /// List the saved versions of an item, compare one with the current version or restore it
+    History {
+        title: String,
+
+        /// Show the changes from this version to the current one
+        #[clap(long)]
+        diff: Option<i32>,
+
+        /// Bring back the contents and fields of this version
+        #[clap(long, conflicts_with = "diff")]
+        restore: Option<i32>,
+    },
+
+    /// Move an item into a folder, e.g. work/aws, the folders are created when missing, "/" is the top level
+    Move {
+        title: String,
+        folder: String,
+    },
+}
+
+#[derive(Subcommand, PartialEq, Debug)]
+enum TagsCommand {
+    /// List the tags with how many items carry each
+    List,
+    /// Tag items
+    Add {
+        tag: String,
+        #[clap(required = true)]
+        titles: Vec<String>,
+    },
+    /// Take a tag off items
+    Remove {
+        tag: String,
+        #[clap(required = true)]
+        titles: Vec<String>,
+    },
+    /// Rename a tag, renaming it to an existing tag merges the two
+    Rename {
+        tag: String,
+        new_name: String,
+    },
+    /// Move the items of several tags onto one tag and delete the others
+    Merge {
+        #[clap(required = true)]
+        tags: Vec<String>,
+
+        /// The tag to keep, created when missing
+        #[clap(long)]
+        into: String,
+    },
+}
+
+#[derive(Subcommand, PartialEq, Debug)]
+enum SearchesCommand {
+    /// List the saved searches with their queries
+    List,
+    /// Save a query under a name, e.g. "weak passwords" "is:weak", replacing the query of a search by that name
+    Save {
+        name: String,
+        query: String,
+    },
+    /// Rename a saved search
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    /// Remove a saved search, the items it matched are left as they are
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Subcommand, PartialEq, Debug)]
+enum ContactsCommand {
+    List,
+    Add {
+        name: String,
+
+        /// age X25519 public key, age1...
+        public_key: String,
+    },
+    Remove {
+        name: String,
+    },
+}
+
+/// How a command uses the vault.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Access {
+    Read,
+    Write,
+    /// Writes only now and then, e.g. to move on the counter of a one-time password, and reads the vault as it is while another process has it open for writing.
+    WriteIfFree,
+}
+
+impl Command {
+    fn access(&self) -> Access {
+        let write_if = |writes: bool| if writes { Access::Write } else { Access::Read };
+        match self {
+            Command::Import { dry_run, .. } => write_if(!dry_run),
+            Command::Receive { .. } | Command::Move { .. } => Access::Write,
+            Command::Contacts { command } => write_if(!matches!(command, None | Some(ContactsCommand::List))),
+            Command::Tags { command } => write_if(!matches!(command, None | Some(TagsCommand::List))),
+            Command::Searches { command } => write_if(!matches!(command, None | Some(SearchesCommand::List))),
+            Command::Attach { files, .. } => write_if(!files.is_empty()),
+            Command::Detach { keep, .. } => write_if(!keep),
+            Command::History { restore, .. } => write_if(restore.is_some()),
+            // the vault's key pair is made the first time it is asked for
+            Command::Otp { .. } | Command::PublicKey => Access::WriteIfFree,
+            Command::SshAgent { .. } | Command::Export { .. } | Command::Share { .. } | Command::List { .. } | Command::Search { .. } => Access::Read,
+        }
+    }
 }
 
 fn main() -> Result<(), Box<dyn error::Error>> {
@@ -52,30 +391,149 @@ fn main() -> Result<(), Box<dyn error::Error>> {
     let options = Options::parse();
     debug!("{:?}", options);
 
+    let project_dir = dirs::home_dir().unwrap().join(".senoru");
+    if !project_dir.as_path().exists() {
+        // the vault, its lock and the agent socket live here, other users have no business listing them
+        std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&project_dir).ok();
+    }
+
     let db_path = match options.database {
         Some(p) => p,
-        None => {
-            let project_dir = dirs::home_dir().unwrap().join(".senoru");
-            if !project_dir.as_path().exists() {
-                std::fs::create_dir_all(&project_dir).ok();
-            }
-            project_dir.clone().join("senoru.db")
-        }
+        None => project_dir.clone().join("senoru.db"),
     };
     env::set_var("SENORU_DB", db_path.as_os_str());
+    env::set_var("SENORU_HISTORY_VERSIONS", options.history_versions.to_string());
+    if let Some(history_days) = options.history_days {
+        env::set_var("SENORU_HISTORY_DAYS", history_days.to_string());
+    }
+    env::set_var("SENORU_TRASH_DAYS", options.trash_days.to_string());
+    if let Some(key_file) = options.key_file.as_ref() {
+        env::set_var("SENORU_KEY_FILE", key_file.as_os_str());
+    }
+
+    if let Some(command) = options.command {
+        // commands changing the vault take the write lock, so they never save over what a window has on screen, the others read the vault as it is
+        let write_lock = match command.access() {
+            Access::Write => match vault_lock::acquire(&db_path, vault_lock::WAIT)? {
+                Some(write_lock) => Some(write_lock),
+                None => {
+                    let by = vault_lock::holder(&db_path).map(|pid| format!(" (process {})", pid)).unwrap_or_default();
+                    return Err(format!("another senoru process{} has the vault open for writing, close it and try again", by).into());
+                }
+            },
+            Access::WriteIfFree => vault_lock::try_acquire(&db_path)?,
+            Access::Read => None,
+        };
+        db::set_read_only(write_lock.is_none());
+        db::init_db()?;
+        if write_lock.is_some() {
+            folders::migrate_path_titles()?;
+            trash::purge_expired()?;
+        }
+        return match command {
+            Command::SshAgent { socket, confirm } => cli::ssh_agent(&socket.unwrap_or_else(|| project_dir.join("ssh-agent.sock")), confirm),
+            Command::Import {
+                path,
+                format,
+                keyfile,
+                csv_preset,
+                csv_mapping,
+                binary_files,
+                on_conflict,
+                dry_run,
+            } => {
+                let options = import::ReadOptions {
+                    keyfile,
+                    csv_mapping: csv_mapping.or(csv_preset.map(csv_import::Mapping::from)),
+                    binary_files,
+                    ..Default::default()
+                };
+                cli::import(&path, format, options, on_conflict, dry_run)
+            }
+            Command::Export {
+                path,
+                format,
+                title,
+                recipient,
+                yes,
+            } => cli::export(&path, format, &title, recipient, yes),
+            Command::Share { title, to, output } => cli::share(&title, &to, output.as_deref()),
+            Command::Receive { path, on_conflict } => cli::receive(path.as_deref(), on_conflict),
+            Command::Contacts { command } => match command.unwrap_or(ContactsCommand::List) {
+                ContactsCommand::List => cli::list_contacts(),
+                ContactsCommand::Add { name, public_key } => cli::add_contact(&name, &public_key),
+                ContactsCommand::Remove { name } => cli::remove_contact(&name),
+            },
+            Command::PublicKey => cli::public_key(),
+            Command::Otp { title } => cli::otp(&title),
+            Command::Attach { title, files } => cli::attach(&title, &files),
+            Command::Detach { title, file_name, output, keep } => cli::detach(&title, &file_name, output.as_deref(), keep),
+            Command::List { trash, tag, saved } => cli::list(trash, &tag, saved.as_deref()),
+            Command::Search { text, tag } => cli::search(&text, &tag),
+            Command::Tags { command } => match command.unwrap_or(TagsCommand::List) {
+                TagsCommand::List => cli::list_tags(),
+                TagsCommand::Add { tag, titles } => cli::tag_items(&tag, &titles),
+                TagsCommand::Remove { tag, titles } => cli::untag_items(&tag, &titles),
+                TagsCommand::Rename { tag, new_name } => cli::rename_tag(&tag, &new_name),
+                TagsCommand::Merge { tags, into } => cli::merge_tags(&tags, &into),
+            },
+            Command::Searches { command } => match command.unwrap_or(SearchesCommand::List) {
+                SearchesCommand::List => cli::list_saved_searches(),
+                SearchesCommand::Save { name, query } => cli::save_search(&name, &query),
+                SearchesCommand::Rename { name, new_name } => cli::rename_saved_search(&name, &new_name),
+                SearchesCommand::Remove { name } => cli::remove_saved_search(&name),
+            },
+            Command::History { title, diff, restore } => cli::history(&title, diff, restore),
+            Command::Move { title, folder } => cli::move_item(&title, &folder),
+        };
+    }
+
+    // one window changes the vault, any other opens it read-only
+    let write_lock = vault_lock::try_acquire(&db_path)?;
+    if write_lock.is_none() {
+        warn!("another process holds the write lock on {:?}, opening it read-only", db_path);
+        db::set_read_only(true);
+    }
 
-    let application = gtk::Application::builder().application_id("com.kiluet.senoru").build();
+    let application = gtk::Application::builder().application_id(vault_lock::application_id(&db_path)).build();
 
     application.connect_activate(move |app| {
-        start_ui(&app);
+        // launching senoru again on the same vault lands here, in the first process, which only brings its window to the front
+        match app.windows().into_iter().find(|window| window.is_visible()) {
+            Some(window) => window.present(),
+            None => start_ui(app),
+        }
     });
     let args: Vec<String> = vec![];
     application.run_with_args(&args);
+    drop(write_lock);
 
     Ok(())
 }
 
-fn start_ui(app: &gtk::Application) {
+/// Checks the key against the first item in the vault and, if it decrypts, makes it the active key.
+pub fn unlock(key: &str) -> Result<magic_crypt::MagicCrypt256, Box<dyn error::Error>> {
+    let items = item_actions::find_all_including_trash(Some(1i64))?;
+    let magic_crypt = new_magic_crypt!(key, 256);
+    if let Some(item) = items.first() {
+        item.decrypt_contents(&magic_crypt)?;
+    }
+    let mut mc = APP_CORE.magic_crypt.lock().unwrap();
+    *mc = Some(magic_crypt.clone());
+    Ok(magic_crypt)
+}
+
+/// Forgets the key and everything decrypted with it.
+pub fn lock() {
+    *APP_CORE.magic_crypt.lock().unwrap() = None;
+    *APP_CORE.search_index.lock().unwrap() = search::SearchIndex::default();
+    APP_CORE.search_query.lock().unwrap().clear();
+    APP_CORE.tag_filter.lock().unwrap().clear();
+    APP_CORE.content_cache.lock().unwrap().clear();
+    info!("locked the vault");
+}
+
+pub fn start_ui(app: &gtk::Application) {
     let builder: gtk::Builder = gtk::Builder::from_string(include_str!("senoru.glade"));
     let key_dialog: gtk::Dialog = builder.object("key_dialog").unwrap();
     let key_dialog_ok_button: gtk::Button = builder.object("key_dialog_ok_button").unwrap();
@@ -83,7 +541,23 @@ fn start_ui(app: &gtk::Application) {
     let key_dialog_entry: gtk::Entry = builder.object("key_dialog_entry").unwrap();
     let key_dialog_quality_score_label: gtk::Label = builder.object("key_dialog_quality_score_label").unwrap();
 
-    db::init_db().expect("failed to initialize the db");
+    key_dialog.set_application(Some(app));
+
+    let started = db::init_db().and_then(|_| match db::is_read_only() {
+        true => Ok(0),
+        false => folders::migrate_path_titles(),
+    });
+    if let Err(e) = started {
+        let error_dialog: gtk::MessageDialog = builder.object("error_dialog").unwrap();
+        error_dialog.set_text(Some(e.to_string().as_str()));
+        error_dialog.run();
+        std::process::exit(1);
+    }
+    if !db::is_read_only() {
+        if let Err(e) = trash::purge_expired() {
+            warn!("failed to purge the trash: {}", e);
+        }
+    }
 
     key_dialog_entry.connect_key_release_event(gtk::glib::clone!(@weak key_dialog_quality_score_label => @default-return Inhibit(false), move | entry, _ | {
         let key = entry.buffer().text();
@@ -106,29 +580,17 @@ fn start_ui(app: &gtk::Application) {
 }
 
 fn key_dialog_ok_button_clicked(app: &gtk::Application, builder: &gtk::Builder, key_dialog: &gtk::Dialog, key_dialog_entry: &gtk::Entry) {
-    let items = item_actions::find_all(Some(1i64)).expect("failed to get items from db");
-    let magic_crypt = new_magic_crypt!(key_dialog_entry.buffer().text(), 256);
-    // let app_core = AppCore::new(app.clone(), builder.clone(), magic_crypt.clone());
-    let mut mc = APP_CORE.magic_crypt.lock().unwrap();
-    *mc = Some(magic_crypt.clone());
-    let first_item = items.first();
-    match first_item {
-        Some(item) => match item.decrypt_contents(&magic_crypt) {
-            Ok(_) => {
-                gui::launch(app, builder).expect("failed to launch the gui");
-                key_dialog.close();
-            }
-            Err(e) => {
-                warn!("error message: {}", e.to_string().as_str());
-                let error_dialog: gtk::MessageDialog = builder.object("error_dialog").unwrap();
-                error_dialog.set_text("Invalid key".into());
-                error_dialog.run();
-                error_dialog.close();
-            }
-        },
-        None => {
+    match unlock(key_dialog_entry.buffer().text().as_str()) {
+        Ok(_) => {
             gui::launch(app, builder).expect("failed to launch the gui");
             key_dialog.close();
         }
+        Err(e) => {
+            warn!("error message: {}", e.to_string().as_str());
+            let error_dialog: gtk::MessageDialog = builder.object("error_dialog").unwrap();
+            error_dialog.set_text("Invalid key".into());
+            error_dialog.run();
+            error_dialog.close();
+        }
     }
 }
@@ -0,0 +1,60 @@
+use std::error::Error;
+use magic_crypt::MagicCrypt256;
+use magic_crypt::MagicCryptTrait;
+use crate::attachment;
+use crate::db;
+use crate::history;
+use crate::item_actions;
+use crate::saved_searches;
+use crate::share;
+use crate::tags;
+
+/// Re-encrypts the whole vault with the new key in one transaction, if any part fails nothing is written and the vault stays under the old key.
+pub fn change(old_mc: &MagicCrypt256, new_mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    let mut conn = db::DB_POOL.get()?;
+    conn.immediate_transaction::<_, Box<dyn Error>, _>(|conn| {
+        item_actions::reencrypt_all(conn, |item| {
+            if item.contents.is_some() {
+                item.contents = Some(new_mc.encrypt_str_to_base64(item.decrypt_contents(old_mc)?));
+            }
+            let fields = item.decrypt_fields(old_mc)?;
+            item.encrypt_fields(new_mc, &fields)
+        })?;
+        attachment::change_master_key(conn, old_mc, new_mc)?;
+        history::change_master_key(conn, old_mc, new_mc)?;
+        share::change_master_key(conn, old_mc, new_mc)?;
+        tags::change_master_key(conn, old_mc, new_mc)?;
+        saved_searches::change_master_key(conn, old_mc, new_mc)?;
+        Ok(())
+    })?;
+    info!("changed the master key");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachment_actions;
+    use crate::models;
+
+    #[test]
+    fn nothing_is_changed_when_a_part_fails() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let mut new_item = models::NewItem::new("master key rollback".into());
+        new_item.contents = Some(mc.encrypt_str_to_base64("kept"));
+        item_actions::insert(&new_item).unwrap();
+        let item = item_actions::find_by_folder_and_title(None, "master key rollback").unwrap().unwrap();
+        // a chunk the old key can't decrypt fails the attachments, after the items were already re-encrypted
+        let new_attachment = models::NewAttachment::new(item.id, "broken.bin".into(), 4);
+        let broken = attachment_actions::insert(&new_attachment, std::iter::once(Ok(vec![0u8; 4]))).unwrap();
+
+        assert!(change(&mc, &new_magic_crypt!("other", 256)).is_err());
+        let kept = item_actions::find_by_id(item.id).unwrap().unwrap();
+        assert_eq!(kept.revision, item.revision);
+        assert_eq!(kept.decrypt_contents(&mc).unwrap(), "kept");
+
+        attachment_actions::delete(broken.id).unwrap();
+        item_actions::delete(&item.id).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+
+/// A three-way merge of two edits of the same text.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Merged {
+    /// Both edits, with the lines changed differently on each side kept between conflict markers.
+    pub text: String,
+    pub conflicts: usize,
+}
+
+/// Lines of `base` replaced by other lines, `start..end` being empty for an insertion.
+struct Hunk<'a> {
+    start: usize,
+    end: usize,
+    lines: Vec<&'a str>,
+}
+
+/// The lines without their line breaks, so the last line matches whether a line break ends the text or not.
+fn lines(text: &str) -> Vec<&str> {
+    text.split('\n').collect()
+}
+
+fn hunks<'a>(base: &[&'a str], edited: &[&'a str]) -> Vec<Hunk<'a>> {
+    capture_diff_slices(Algorithm::Myers, base, edited)
+        .iter()
+        .map(|op| op.as_tag_tuple())
+        .filter(|(tag, _, _)| *tag != DiffTag::Equal)
+        .map(|(_, old, new)| Hunk {
+            start: old.start,
+            end: old.end,
+            lines: edited[new].to_vec(),
+        })
+        .collect()
+}
+
+/// The base lines `start..end` with the hunks, all within them, applied.
+fn apply<'a>(base: &[&'a str], start: usize, end: usize, hunks: &[Hunk<'a>]) -> Vec<&'a str> {
+    let mut lines = vec![];
+    let mut at = start;
+    for hunk in hunks.iter() {
+        lines.extend_from_slice(&base[at..hunk.start]);
+        lines.extend_from_slice(&hunk.lines);
+        at = hunk.end;
+    }
+    lines.extend_from_slice(&base[at..end]);
+    lines
+}
+
+/// Merges the edits made to `base` on both sides, line by line. Where both sides changed the same or neighbouring lines differently, both versions are kept between `<<<<<<<`, `=======` and `>>>>>>>` lines, as git does.
+pub fn merge(base: &str, mine: &str, theirs: &str) -> Merged {
+    let base_lines = lines(base);
+    let my_lines = lines(mine);
+    let their_lines = lines(theirs);
+    let my_hunks = hunks(&base_lines, &my_lines);
+    let their_hunks = hunks(&base_lines, &their_lines);
+
+    let mut merged = vec![];
+    let mut conflicts = 0;
+    let mut at = 0;
+    let (mut i, mut j) = (0, 0);
+    while i < my_hunks.len() || j < their_hunks.len() {
+        // the next hunk on either side, grown by every hunk touching it
+        let start = match (my_hunks.get(i), their_hunks.get(j)) {
+            (Some(mine), Some(theirs)) => mine.start.min(theirs.start),
+            (Some(mine), None) => mine.start,
+            (None, Some(theirs)) => theirs.start,
+            (None, None) => break,
+        };
+        let mut end = start;
+        let (first_mine, first_theirs) = (i, j);
+        loop {
+            if let Some(hunk) = my_hunks.get(i).filter(|h| h.start <= end) {
+                end = end.max(hunk.end);
+                i += 1;
+            } else if let Some(hunk) = their_hunks.get(j).filter(|h| h.start <= end) {
+                end = end.max(hunk.end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+        merged.extend_from_slice(&base_lines[at..start]);
+        let my_part = apply(&base_lines, start, end, &my_hunks[first_mine..i]);
+        let their_part = apply(&base_lines, start, end, &their_hunks[first_theirs..j]);
+        if first_theirs == j || my_part == their_part {
+            merged.extend(my_part);
+        } else if first_mine == i {
+            merged.extend(their_part);
+        } else {
+            conflicts += 1;
+            merged.push("<<<<<<< yours");
+            merged.extend(my_part);
+            merged.push("=======");
+            merged.extend(their_part);
+            merged.push(">>>>>>> saved elsewhere");
+        }
+        at = end;
+    }
+    merged.extend_from_slice(&base_lines[at..]);
+    Merged { text: merged.join("\n"), conflicts }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(text: &str) -> Merged {
+        Merged { text: text.to_string(), conflicts: 0 }
+    }
+
+    #[test]
+    fn unchanged_text_stays_as_it_is() {
+        assert_eq!(merge("one\ntwo\n", "one\ntwo\n", "one\ntwo\n"), clean("one\ntwo\n"));
+        assert_eq!(merge("", "", ""), clean(""));
+    }
+
+    #[test]
+    fn a_change_on_one_side_is_taken() {
+        assert_eq!(merge("one\ntwo\nthree", "one\n2\nthree", "one\ntwo\nthree"), clean("one\n2\nthree"));
+        assert_eq!(merge("one\ntwo\nthree", "one\ntwo\nthree", "one\nthree"), clean("one\nthree"));
+    }
+
+    #[test]
+    fn changes_of_different_lines_are_both_taken() {
+        assert_eq!(merge("one\ntwo\nthree\nfour", "1\ntwo\nthree\nfour", "one\ntwo\nthree\n4"), clean("1\ntwo\nthree\n4"));
+    }
+
+    #[test]
+    fn the_same_change_on_both_sides_is_taken_once() {
+        assert_eq!(merge("one\ntwo\nthree", "one\n2\nthree", "one\n2\nthree"), clean("one\n2\nthree"));
+    }
+
+    #[test]
+    fn different_changes_of_the_same_line_conflict() {
+        let merged = merge("one\ntwo\nthree", "one\nmine\nthree", "one\ntheirs\nthree");
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(merged.text, "one\n<<<<<<< yours\nmine\n=======\ntheirs\n>>>>>>> saved elsewhere\nthree");
+    }
+
+    #[test]
+    fn changes_of_neighbouring_lines_conflict() {
+        // nothing of the base is left between the two changes to tell them apart
+        let merged = merge("one\ntwo\nthree\nfour", "one\n2\nthree\nfour", "one\ntwo\n3\nfour");
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(merged.text, "one\n<<<<<<< yours\n2\nthree\n=======\ntwo\n3\n>>>>>>> saved elsewhere\nfour");
+    }
+
+    #[test]
+    fn lines_added_at_the_end() {
+        assert_eq!(merge("one\n", "one\ntwo\n", "one\n"), clean("one\ntwo\n"));
+        assert_eq!(merge("one", "one\ntwo", "zero\none"), clean("zero\none\ntwo"));
+        let merged = merge("one\n", "one\nmine\n", "one\ntheirs\n");
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(merged.text, "one\n<<<<<<< yours\nmine\n=======\ntheirs\n>>>>>>> saved elsewhere\n");
+    }
+}
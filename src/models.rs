@@ -5,6 +5,8 @@ use magic_crypt::MagicCrypt256;
 use magic_crypt::MagicCryptTrait;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize, Queryable, Identifiable, Insertable, AsChangeset)]
 #[diesel(table_name = items, treat_none_as_null = true)]
@@ -14,6 +16,15 @@ pub struct Item {
     pub contents: Option<String>,
     pub date_added: NaiveDateTime,
     pub date_last_modified: NaiveDateTime,
+    pub item_type: String,
+    pub fields: Option<String>,
+    /// When the item was moved to the trash, `None` for items in the vault.
+    pub deleted_at: Option<NaiveDateTime>,
+    /// `None` for items at the top level.
+    pub folder_id: Option<i32>,
+    /// Moves on with every write, an update only goes through on top of the revision it was read at.
+    #[serde(default)]
+    pub revision: i32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Insertable, AsChangeset)]
@@ -23,6 +34,84 @@ pub struct NewItem {
     pub contents: Option<String>,
     pub date_added: NaiveDateTime,
     pub date_last_modified: NaiveDateTime,
+    pub item_type: String,
+    pub fields: Option<String>,
+    pub folder_id: Option<i32>,
+}
+
+/// What lists need of an item, without the encrypted contents and fields.
+#[derive(PartialEq, Eq, Debug, Clone, Queryable)]
+pub struct ItemSummary {
+    pub id: i32,
+    pub title: String,
+    pub item_type: String,
+    pub folder_id: Option<i32>,
+    pub date_added: NaiveDateTime,
+    pub date_last_modified: NaiveDateTime,
+}
+
+impl ItemSummary {
+    pub fn item_type(&self) -> ItemType {
+        self.item_type.parse::<ItemType>().unwrap_or(ItemType::Note)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ItemType {
+    Note,
+    Login,
+    Card,
+    Identity,
+    SshKey,
+}
+
+impl ItemType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ItemType::Note => "note",
+            ItemType::Login => "login",
+            ItemType::Card => "card",
+            ItemType::Identity => "identity",
+            ItemType::SshKey => "ssh_key",
+        }
+    }
+
+    /// Types that are edited as a form of fields rather than as free text.
+    pub fn is_structured(&self) -> bool {
+        matches!(self, ItemType::Login | ItemType::Card | ItemType::Identity)
+    }
+
+    /// The empty fields a new item of this type starts with.
+    pub fn default_fields(&self) -> Vec<Field> {
+        let fields: &[(FieldKind, &str)] = match self {
+            ItemType::Login => &[(FieldKind::Username, "Username"), (FieldKind::Password, "Password"), (FieldKind::Url, "URL"), (FieldKind::Totp, "TOTP")],
+            ItemType::Card => &[(FieldKind::Text, "Cardholder"), (FieldKind::CardNumber, "Number"), (FieldKind::Expiry, "Expiry"), (FieldKind::Password, "Security code")],
+            ItemType::Identity => &[(FieldKind::Text, "Name"), (FieldKind::Text, "Email"), (FieldKind::Text, "Phone"), (FieldKind::Text, "Address")],
+            ItemType::Note | ItemType::SshKey => &[],
+        };
+        fields.iter().map(|(kind, name)| Field::new(*kind, name, "")).collect()
+    }
+}
+
+impl fmt::Display for ItemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ItemType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "note" => Ok(ItemType::Note),
+            "login" => Ok(ItemType::Login),
+            "card" => Ok(ItemType::Card),
+            "identity" => Ok(ItemType::Identity),
+            "ssh_key" => Ok(ItemType::SshKey),
+            _ => Err(format!("unknown item type: {}", s)),
+        }
+    }
 }
 
 impl NewItem {
@@ -32,13 +121,396 @@ impl NewItem {
             contents: None,
             date_added: Utc::now().naive_utc(),
             date_last_modified: Utc::now().naive_utc(),
+            item_type: ItemType::Note.to_string(),
+            fields: None,
+            folder_id: None,
         }
     }
+
+    pub fn encrypt_fields(&mut self, mc: &MagicCrypt256, fields: &[Field]) -> Result<(), Box<dyn Error>> {
+        self.fields = encrypt_fields(mc, fields)?;
+        Ok(())
+    }
 }
 
 impl Item {
+    /// The decrypted text of the item, empty when it has none stored.
     pub fn decrypt_contents(&self, mc: &MagicCrypt256) -> Result<String, Box<dyn Error>> {
-        let contents = mc.decrypt_base64_to_string(self.contents.as_ref().unwrap())?;
-        Ok(contents)
+        match self.contents.as_deref() {
+            Some(contents) => Ok(mc.decrypt_base64_to_string(contents)?),
+            None => Ok(String::new()),
+        }
+    }
+
+    pub fn item_type(&self) -> ItemType {
+        self.item_type.parse::<ItemType>().unwrap_or(ItemType::Note)
+    }
+
+    pub fn decrypt_fields(&self, mc: &MagicCrypt256) -> Result<Vec<Field>, Box<dyn Error>> {
+        decrypt_fields(mc, self.fields.as_deref())
+    }
+
+    pub fn encrypt_fields(&mut self, mc: &MagicCrypt256, fields: &[Field]) -> Result<(), Box<dyn Error>> {
+        self.fields = encrypt_fields(mc, fields)?;
+        Ok(())
+    }
+}
+
+/// The field list is stored as one JSON array encrypted with the master key, `None` when there are no fields.
+fn encrypt_fields(mc: &MagicCrypt256, fields: &[Field]) -> Result<Option<String>, Box<dyn Error>> {
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(mc.encrypt_str_to_base64(serde_json::to_string(fields)?)))
+}
+
+pub fn decrypt_fields(mc: &MagicCrypt256, fields: Option<&str>) -> Result<Vec<Field>, Box<dyn Error>> {
+    match fields {
+        Some(fields) => Ok(serde_json::from_str(&mc.decrypt_base64_to_string(fields)?)?),
+        None => Ok(vec![]),
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldKind {
+    Username,
+    Password,
+    Url,
+    Totp,
+    CardNumber,
+    Expiry,
+    Text,
+}
+
+impl FieldKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldKind::Username => "username",
+            FieldKind::Password => "password",
+            FieldKind::Url => "url",
+            FieldKind::Totp => "totp",
+            FieldKind::CardNumber => "card_number",
+            FieldKind::Expiry => "expiry",
+            FieldKind::Text => "text",
+        }
+    }
+
+    /// Guesses the kind of a field other tools only know by its label.
+    pub fn from_label(label: &str) -> FieldKind {
+        match label.to_lowercase().as_str() {
+            "username" | "user" | "login" => FieldKind::Username,
+            "password" | "pin" | "security code" | "cvv" => FieldKind::Password,
+            "url" | "website" => FieldKind::Url,
+            "totp" | "otp" | "one-time password" => FieldKind::Totp,
+            "number" | "card number" | "ccnum" => FieldKind::CardNumber,
+            "expiry" | "expiry date" | "expiration date" | "expires" => FieldKind::Expiry,
+            _ => FieldKind::Text,
+        }
+    }
+
+    /// Values that are hidden until asked for.
+    pub fn is_secret(&self) -> bool {
+        matches!(self, FieldKind::Password | FieldKind::Totp | FieldKind::CardNumber)
+    }
+}
+
+impl fmt::Display for FieldKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for FieldKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "username" => Ok(FieldKind::Username),
+            "password" => Ok(FieldKind::Password),
+            "url" => Ok(FieldKind::Url),
+            "totp" => Ok(FieldKind::Totp),
+            "card_number" => Ok(FieldKind::CardNumber),
+            "expiry" => Ok(FieldKind::Expiry),
+            "text" => Ok(FieldKind::Text),
+            _ => Err(format!("unknown field kind: {}", s)),
+        }
+    }
+}
+
+/// One named value of a structured item, the name is what the form shows and what exports use as a label.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct Field {
+    pub kind: FieldKind,
+    pub name: String,
+    pub value: String,
+}
+
+impl Field {
+    pub fn new(kind: FieldKind, name: &str, value: &str) -> Field {
+        Field {
+            kind,
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// A field read from another tool, its kind guessed from the label.
+    pub fn labelled(name: String, value: String) -> Field {
+        Field {
+            kind: FieldKind::from_label(&name),
+            name,
+            value,
+        }
+    }
+}
+
+/// Renders fields as `name: value` lines, the way they appear in plain text exports.
+pub fn format_fields(fields: &[Field]) -> String {
+    fields.iter().filter(|f| !f.value.is_empty()).map(|f| format!("{}: {}", f.name, f.value)).collect::<Vec<String>>().join("\n")
+}
+
+/// A teammate items can be shared with, `public_key` is an age X25519 recipient (`age1...`).
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize, Queryable, Identifiable)]
+#[diesel(table_name = contacts)]
+pub struct Contact {
+    pub id: i32,
+    pub name: String,
+    pub public_key: String,
+    pub date_added: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = contacts)]
+pub struct NewContact {
+    pub name: String,
+    pub public_key: String,
+    pub date_added: NaiveDateTime,
+}
+
+impl NewContact {
+    pub fn new(name: String, public_key: String) -> NewContact {
+        NewContact {
+            name,
+            public_key,
+            date_added: Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// The vault's own age X25519 key pair, the secret key is encrypted with the master key like item contents.
+#[derive(PartialEq, Eq, Debug, Clone, Queryable, Identifiable, AsChangeset)]
+#[diesel(table_name = identities)]
+pub struct Identity {
+    pub id: i32,
+    pub public_key: String,
+    pub secret_key: String,
+    pub date_added: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = identities)]
+pub struct NewIdentity {
+    pub public_key: String,
+    pub secret_key: String,
+    pub date_added: NaiveDateTime,
+}
+
+/// A folder of items, `parent_id` is `None` for top level folders.
+#[derive(PartialEq, Eq, Debug, Clone, Queryable, Identifiable, AsChangeset)]
+#[diesel(table_name = folders, treat_none_as_null = true)]
+pub struct Folder {
+    pub id: i32,
+    pub parent_id: Option<i32>,
+    pub name: String,
+    pub date_added: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = folders)]
+pub struct NewFolder {
+    pub parent_id: Option<i32>,
+    pub name: String,
+    pub date_added: NaiveDateTime,
+}
+
+impl NewFolder {
+    pub fn new(parent_id: Option<i32>, name: String) -> NewFolder {
+        NewFolder {
+            parent_id,
+            name,
+            date_added: Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// A file kept with an item, its contents live encrypted in `attachment_chunks`.
+#[derive(PartialEq, Eq, Debug, Clone, Queryable, Identifiable)]
+#[diesel(table_name = attachments)]
+pub struct Attachment {
+    pub id: i32,
+    pub item_id: i32,
+    pub file_name: String,
+    pub mime_type: String,
+    pub size: i64,
+    pub date_added: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = attachments)]
+pub struct NewAttachment {
+    pub item_id: i32,
+    pub file_name: String,
+    pub mime_type: String,
+    pub size: i64,
+    pub date_added: NaiveDateTime,
+}
+
+impl NewAttachment {
+    pub fn new(item_id: i32, file_name: String, size: i64) -> NewAttachment {
+        NewAttachment {
+            item_id,
+            mime_type: mime_guess::from_path(&file_name).first_or_octet_stream().to_string(),
+            file_name,
+            size,
+            date_added: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = attachment_chunks)]
+pub struct AttachmentChunk {
+    pub attachment_id: i32,
+    pub chunk_index: i32,
+    pub data: Vec<u8>,
+}
+
+/// A prior version of an item, encrypted with the master key like the item itself.
+#[derive(PartialEq, Eq, Debug, Clone, Queryable, Identifiable, AsChangeset)]
+#[diesel(table_name = item_history, treat_none_as_null = true)]
+pub struct ItemVersion {
+    pub id: i32,
+    pub item_id: i32,
+    pub contents: Option<String>,
+    pub fields: Option<String>,
+    pub item_type: String,
+    pub date_saved: NaiveDateTime,
+}
+
+impl ItemVersion {
+    pub fn decrypt_contents(&self, mc: &MagicCrypt256) -> Result<String, Box<dyn Error>> {
+        match self.contents.as_ref() {
+            Some(contents) => Ok(mc.decrypt_base64_to_string(contents)?),
+            None => Ok(String::new()),
+        }
+    }
+
+    pub fn decrypt_fields(&self, mc: &MagicCrypt256) -> Result<Vec<Field>, Box<dyn Error>> {
+        decrypt_fields(mc, self.fields.as_deref())
+    }
+
+    pub fn encrypt_fields(&mut self, mc: &MagicCrypt256, fields: &[Field]) -> Result<(), Box<dyn Error>> {
+        self.fields = encrypt_fields(mc, fields)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = item_history)]
+pub struct NewItemVersion {
+    pub item_id: i32,
+    pub contents: Option<String>,
+    pub fields: Option<String>,
+    pub item_type: String,
+    pub date_saved: NaiveDateTime,
+}
+
+impl NewItemVersion {
+    /// Keeps the item as it is now, still encrypted.
+    pub fn from_item(item: &Item) -> NewItemVersion {
+        NewItemVersion {
+            item_id: item.id,
+            contents: item.contents.clone(),
+            fields: item.fields.clone(),
+            item_type: item.item_type.clone(),
+            date_saved: Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// A tag, its name encrypted with the master key. The encryption is deterministic, so equal names still collide on the unique index.
+#[derive(PartialEq, Eq, Debug, Clone, Queryable, Identifiable, AsChangeset)]
+#[diesel(table_name = tags)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+    pub date_added: NaiveDateTime,
+}
+
+impl Tag {
+    pub fn decrypt_name(&self, mc: &MagicCrypt256) -> Result<String, Box<dyn Error>> {
+        Ok(mc.decrypt_base64_to_string(&self.name)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = tags)]
+pub struct NewTag {
+    pub name: String,
+    pub date_added: NaiveDateTime,
+}
+
+impl NewTag {
+    pub fn new(mc: &MagicCrypt256, name: &str) -> NewTag {
+        NewTag {
+            name: mc.encrypt_str_to_base64(name),
+            date_added: Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = item_tags)]
+pub struct ItemTag {
+    pub item_id: i32,
+    pub tag_id: i32,
+}
+
+/// A named query, both the name and the query encrypted with the master key.
+#[derive(PartialEq, Eq, Debug, Clone, Queryable, Identifiable, AsChangeset)]
+#[diesel(table_name = saved_searches)]
+pub struct SavedSearch {
+    pub id: i32,
+    pub name: String,
+    pub query: String,
+    pub date_added: NaiveDateTime,
+}
+
+impl SavedSearch {
+    pub fn decrypt_name(&self, mc: &MagicCrypt256) -> Result<String, Box<dyn Error>> {
+        Ok(mc.decrypt_base64_to_string(&self.name)?)
+    }
+
+    pub fn decrypt_query(&self, mc: &MagicCrypt256) -> Result<String, Box<dyn Error>> {
+        Ok(mc.decrypt_base64_to_string(&self.query)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Insertable)]
+#[diesel(table_name = saved_searches)]
+pub struct NewSavedSearch {
+    pub name: String,
+    pub query: String,
+    pub date_added: NaiveDateTime,
+}
+
+impl NewSavedSearch {
+    pub fn new(mc: &MagicCrypt256, name: &str, query: &str) -> NewSavedSearch {
+        NewSavedSearch {
+            name: mc.encrypt_str_to_base64(name),
+            query: mc.encrypt_str_to_base64(query),
+            date_added: Utc::now().naive_utc(),
+        }
     }
 }
@@ -0,0 +1,295 @@
+use std::error::Error;
+use std::fs;
+use std::io::prelude::*;
+use std::path;
+use chrono::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use crate::import::ImportedItem;
+use crate::models;
+
+const CREDIT_CARD: &str = "002";
+const IDENTITY: &str = "004";
+const PASSWORD: &str = "005";
+
+#[derive(Debug, Deserialize)]
+struct Export {
+    accounts: Vec<Account>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Account {
+    #[serde(default)]
+    vaults: Vec<Vault>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Vault {
+    attrs: VaultAttrs,
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VaultAttrs {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    created_at: Option<i64>,
+    updated_at: Option<i64>,
+    state: Option<String>,
+    category_uuid: String,
+    details: Details,
+    overview: Overview,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Details {
+    #[serde(default)]
+    login_fields: Vec<LoginField>,
+    notes_plain: Option<String>,
+    #[serde(default)]
+    sections: Vec<Section>,
+    password: Option<String>,
+    document_attributes: Option<DocumentAttributes>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginField {
+    value: Option<String>,
+    name: Option<String>,
+    designation: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Section {
+    #[serde(default)]
+    fields: Vec<SectionField>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SectionField {
+    title: Option<String>,
+    id: Option<String>,
+    value: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentAttributes {
+    file_name: String,
+    document_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Overview {
+    title: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    urls: Vec<OverviewUrl>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OverviewUrl {
+    url: Option<String>,
+}
+
+fn timestamp(seconds: Option<i64>) -> Option<NaiveDateTime> {
+    seconds.and_then(|s| DateTime::from_timestamp(s, 0)).map(|d| d.naive_utc())
+}
+
+/// Section field values are objects with a single key naming their kind, e.g. `{"concealed": "..."}`.
+fn field_value(value: &Value) -> (Option<&str>, String) {
+    let (kind, inner) = match value.as_object().and_then(|o| o.iter().next()) {
+        Some((kind, inner)) => (Some(kind.as_str()), inner),
+        None => (None, value),
+    };
+    let text = match (kind, inner) {
+        (_, Value::String(s)) => s.clone(),
+        (Some("date"), Value::Number(n)) => n.as_i64().and_then(|s| DateTime::from_timestamp(s, 0)).map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default(),
+        (Some("monthYear"), Value::Number(n)) => n.as_i64().map(|my| format!("{:02}/{}", my % 100, my / 100)).unwrap_or_default(),
+        (Some("sshKey"), Value::Object(o)) => o.get("privateKey").and_then(|k| k.as_str()).unwrap_or_default().to_string(),
+        (Some("email"), Value::Object(o)) => o.get("email_address").and_then(|k| k.as_str()).unwrap_or_default().to_string(),
+        (Some("address"), Value::Object(o)) => ["street", "city", "state", "zip", "country"]
+            .iter()
+            .filter_map(|k| o.get(*k).and_then(|v| v.as_str()).filter(|v| !v.is_empty()))
+            .collect::<Vec<&str>>()
+            .join(", "),
+        (_, Value::Null) => String::new(),
+        (_, v) => v.to_string(),
+    };
+    (kind, text)
+}
+
+/// Reads a 1Password `.1pux` archive, each vault becomes the path of its items.
+pub fn read(path: &path::Path) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+    let export: Export = serde_json::from_reader(archive.by_name("export.data")?)?;
+
+    let mut imported_items = vec![];
+    for vault in export.accounts.into_iter().flat_map(|a| a.vaults.into_iter()) {
+        for item in vault.items.into_iter() {
+            if item.state.as_deref() == Some("deleted") {
+                continue;
+            }
+            let mut imported_item = ImportedItem {
+                path: vec![vault.attrs.name.clone()],
+                title: item.overview.title.clone().filter(|t| !t.is_empty()).unwrap_or_else(|| "Untitled".into()),
+                notes: item.details.notes_plain.clone(),
+                tags: item.overview.tags.clone(),
+                date_added: timestamp(item.created_at),
+                date_last_modified: timestamp(item.updated_at),
+                ..Default::default()
+            };
+            if item.state.as_deref() == Some("archived") {
+                imported_item.tags.push("archived".into());
+            }
+
+            imported_item.url = item.overview.url.clone().filter(|u| !u.is_empty());
+            for url in item.overview.urls.iter().filter_map(|u| u.url.clone()) {
+                if imported_item.url.as_ref() != Some(&url) {
+                    imported_item.fields.push(models::Field::labelled("URL".into(), url));
+                }
+            }
+
+            for login_field in item.details.login_fields.iter() {
+                let value = login_field.value.clone().unwrap_or_default();
+                match login_field.designation.as_deref() {
+                    Some("username") => imported_item.username = Some(value),
+                    Some("password") => imported_item.password = Some(value),
+                    _ => imported_item.fields.push(models::Field::labelled(login_field.name.clone().unwrap_or_default(), value)),
+                }
+            }
+            match item.category_uuid.as_str() {
+                PASSWORD => imported_item.password = item.details.password.clone(),
+                CREDIT_CARD => imported_item.item_type = Some(models::ItemType::Card),
+                IDENTITY => imported_item.item_type = Some(models::ItemType::Identity),
+                _ => {}
+            }
+
+            for field in item.details.sections.iter().flat_map(|s| s.fields.iter()) {
+                let (kind, value) = field_value(&field.value);
+                if value.is_empty() {
+                    continue;
+                }
+                if kind == Some("sshKey") {
+                    imported_item.item_type = Some(models::ItemType::SshKey);
+                    imported_item.notes = Some(value);
+                    break;
+                }
+                let name = field.title.clone().filter(|t| !t.is_empty()).or_else(|| field.id.clone()).unwrap_or_default();
+                imported_item.fields.push(models::Field::labelled(name, value));
+            }
+
+            if let Some(document) = item.details.document_attributes.as_ref() {
+                let name = format!("files/{}__{}", document.document_id, document.file_name);
+                match archive.by_name(&name) {
+                    Ok(mut file) => {
+                        let mut data = vec![];
+                        file.read_to_end(&mut data)?;
+                        imported_item.attachments.push((document.file_name.clone(), data));
+                    }
+                    Err(e) => warn!("missing document {} for {}: {}", name, imported_item.title, e),
+                }
+            }
+            imported_items.push(imported_item);
+        }
+    }
+    info!("read {} items from {}", imported_items.len(), path.to_string_lossy());
+    Ok(imported_items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const EXPORT: &str = r#"{
+        "accounts": [{
+            "vaults": [{
+                "attrs": { "name": "Private" },
+                "items": [
+                    {
+                        "createdAt": 1767323045,
+                        "updatedAt": 1767323045,
+                        "state": "active",
+                        "categoryUuid": "001",
+                        "details": {
+                            "loginFields": [
+                                { "value": "admin", "name": "username", "designation": "username" },
+                                { "value": "hunter2", "name": "password", "designation": "password" }
+                            ],
+                            "notesPlain": "notes",
+                            "sections": [{ "fields": [
+                                { "title": "PIN", "id": "pin", "value": { "concealed": "1234" } },
+                                { "title": "Expires", "id": "expires", "value": { "monthYear": 203003 } },
+                                { "title": "", "id": "empty", "value": { "string": "" } }
+                            ] }],
+                            "documentAttributes": { "fileName": "scan.pdf", "documentId": "doc1" }
+                        },
+                        "overview": {
+                            "title": "bank",
+                            "url": "https://bank.example.com",
+                            "urls": [{ "url": "https://bank.example.com" }, { "url": "https://m.bank.example.com" }],
+                            "tags": ["money"]
+                        }
+                    },
+                    {
+                        "state": "archived",
+                        "categoryUuid": "005",
+                        "details": { "password": "old" },
+                        "overview": { "title": "" }
+                    },
+                    {
+                        "state": "deleted",
+                        "categoryUuid": "005",
+                        "details": {},
+                        "overview": { "title": "gone" }
+                    }
+                ]
+            }]
+        }]
+    }"#;
+
+    #[test]
+    fn read_1pux() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("export.1pux");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&file).unwrap());
+        zip.start_file("export.data", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(EXPORT.as_bytes()).unwrap();
+        zip.start_file("files/doc1__scan.pdf", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(b"%PDF").unwrap();
+        zip.finish().unwrap();
+
+        let imported_items = read(&file).unwrap();
+        assert_eq!(imported_items.len(), 2);
+        let bank = &imported_items[0];
+        assert_eq!((bank.path.clone(), bank.title.as_str()), (vec!["Private".to_string()], "bank"));
+        assert_eq!(bank.username.as_deref(), Some("admin"));
+        assert_eq!(bank.password.as_deref(), Some("hunter2"));
+        assert_eq!(bank.url.as_deref(), Some("https://bank.example.com"));
+        assert_eq!(
+            bank.fields,
+            vec![
+                models::Field::new(models::FieldKind::Url, "URL", "https://m.bank.example.com"),
+                models::Field::new(models::FieldKind::Password, "PIN", "1234"),
+                models::Field::new(models::FieldKind::Expiry, "Expires", "03/2030"),
+            ]
+        );
+        assert_eq!(bank.tags, vec!["money".to_string()]);
+        assert_eq!(bank.attachments, vec![("scan.pdf".to_string(), b"%PDF".to_vec())]);
+        assert_eq!(bank.date_added, DateTime::from_timestamp(1767323045, 0).map(|d| d.naive_utc()));
+
+        let archived = &imported_items[1];
+        assert_eq!(archived.title, "Untitled");
+        assert_eq!(archived.password.as_deref(), Some("old"));
+        assert_eq!(archived.tags, vec!["archived".to_string()]);
+    }
+}
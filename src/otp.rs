@@ -0,0 +1,218 @@
+use std::error::Error;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use hmac::{Hmac, Mac};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "SHA1" => Ok(Algorithm::Sha1),
+            "SHA256" => Ok(Algorithm::Sha256),
+            "SHA512" => Ok(Algorithm::Sha512),
+            _ => Err(format!("unsupported OTP algorithm: {}", s)),
+        }
+    }
+}
+
+/// Time based codes (RFC 6238) change every `period` seconds, counter based ones (RFC 4226) every time one is used.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Kind {
+    Totp { period: u64 },
+    Hotp { counter: u64 },
+}
+
+/// A one-time password generator, parsed from a bare base32 secret or an `otpauth://` URI.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Otp {
+    pub secret: Vec<u8>,
+    pub algorithm: Algorithm,
+    pub digits: u32,
+    pub kind: Kind,
+}
+
+impl Otp {
+    pub fn new(secret: Vec<u8>, algorithm: Algorithm, digits: u32, kind: Kind) -> Otp {
+        Otp {
+            secret,
+            algorithm,
+            digits,
+            kind,
+        }
+    }
+
+    /// The RFC 4226 code for a counter value, zero padded to `digits`.
+    pub fn hotp(&self, counter: u64) -> String {
+        let message = counter.to_be_bytes();
+        let hash = match self.algorithm {
+            Algorithm::Sha1 => Hmac::<sha1::Sha1>::new_from_slice(&self.secret).map(|m| m.chain_update(message).finalize().into_bytes().to_vec()),
+            Algorithm::Sha256 => Hmac::<sha2::Sha256>::new_from_slice(&self.secret).map(|m| m.chain_update(message).finalize().into_bytes().to_vec()),
+            Algorithm::Sha512 => Hmac::<sha2::Sha512>::new_from_slice(&self.secret).map(|m| m.chain_update(message).finalize().into_bytes().to_vec()),
+        }
+        .expect("HMAC accepts keys of any length");
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+        let code = binary as u64 % 10u64.pow(self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    /// The RFC 6238 code at a unix time, for counter based generators the code of the current counter.
+    pub fn code_at(&self, time: u64) -> String {
+        match self.kind {
+            Kind::Totp { period } => self.hotp(time / period),
+            Kind::Hotp { counter } => self.hotp(counter),
+        }
+    }
+
+    /// The current code and, for time based codes, the seconds it stays valid.
+    pub fn now(&self) -> (String, Option<u64>) {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let remaining = match self.kind {
+            Kind::Totp { period } => Some(period - time % period),
+            Kind::Hotp { .. } => None,
+        };
+        (self.code_at(time), remaining)
+    }
+}
+
+impl FromStr for Otp {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if !s.starts_with("otpauth://") {
+            return Ok(Otp::new(decode_base32(s)?, Algorithm::Sha1, 6, Kind::Totp { period: 30 }));
+        }
+        let uri = url::Url::parse(s)?;
+        let mut secret = None;
+        let mut algorithm = Algorithm::Sha1;
+        let mut digits = 6;
+        let mut period = 30;
+        let mut counter = None;
+        for (key, value) in uri.query_pairs() {
+            match key.as_ref() {
+                "secret" => secret = Some(decode_base32(&value)?),
+                "algorithm" => algorithm = value.parse::<Algorithm>()?,
+                "digits" => digits = value.parse::<u32>()?,
+                "period" => period = value.parse::<u64>()?,
+                "counter" => counter = Some(value.parse::<u64>()?),
+                _ => {}
+            }
+        }
+        if !(6..=10).contains(&digits) || period == 0 {
+            return Err(format!("invalid OTP parameters: digits={} period={}", digits, period).into());
+        }
+        let kind = match uri.host_str() {
+            Some("totp") => Kind::Totp { period },
+            Some("hotp") => Kind::Hotp { counter: counter.unwrap_or_default() },
+            t => return Err(format!("unsupported OTP type: {}", t.unwrap_or_default()).into()),
+        };
+        Ok(Otp::new(secret.ok_or("the otpauth URI has no secret")?, algorithm, digits, kind))
+    }
+}
+
+/// Advances the `counter` of an `otpauth://hotp/` URI once its code has been used.
+pub fn increment_counter(value: &str) -> Result<String, Box<dyn Error>> {
+    let mut uri = url::Url::parse(value.trim())?;
+    let mut pairs: Vec<(String, String)> = uri.query_pairs().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    match pairs.iter_mut().find(|(k, _)| k == "counter") {
+        Some((_, counter)) => *counter = (counter.parse::<u64>()? + 1).to_string(),
+        None => pairs.push(("counter".into(), "1".into())),
+    }
+    uri.query_pairs_mut().clear().extend_pairs(pairs);
+    Ok(uri.to_string())
+}
+
+/// RFC 4648 base32, case insensitive, with or without padding, spaces and dashes are ignored.
+fn decode_base32(s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes = vec![];
+    let mut buffer = 0u64;
+    let mut bits = 0;
+    for c in s.chars().filter(|c| !matches!(c, ' ' | '-' | '=')) {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => return Err(format!("invalid base32 character in OTP secret: {}", c).into()),
+        };
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bytes.is_empty() {
+        return Err("the OTP secret is empty".into());
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 4226 Appendix D.
+    #[test]
+    fn hotp_test_values() {
+        let otp = Otp::new(b"12345678901234567890".to_vec(), Algorithm::Sha1, 6, Kind::Hotp { counter: 0 });
+        let codes = ["755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583", "399871", "520489"];
+        for (counter, code) in codes.iter().enumerate() {
+            assert_eq!(otp.hotp(counter as u64), *code, "counter {}", counter);
+        }
+    }
+
+    /// RFC 6238 Appendix B, each algorithm has a seed of its own length.
+    #[test]
+    fn totp_test_values() {
+        let sha1 = Otp::new(b"12345678901234567890".to_vec(), Algorithm::Sha1, 8, Kind::Totp { period: 30 });
+        let sha256 = Otp::new(b"12345678901234567890123456789012".to_vec(), Algorithm::Sha256, 8, Kind::Totp { period: 30 });
+        let sha512 = Otp::new(b"1234567890123456789012345678901234567890123456789012345678901234".to_vec(), Algorithm::Sha512, 8, Kind::Totp { period: 30 });
+        let values = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (time, sha1_code, sha256_code, sha512_code) in values.iter() {
+            assert_eq!(sha1.code_at(*time), *sha1_code, "SHA1 at {}", time);
+            assert_eq!(sha256.code_at(*time), *sha256_code, "SHA256 at {}", time);
+            assert_eq!(sha512.code_at(*time), *sha512_code, "SHA512 at {}", time);
+        }
+    }
+
+    #[test]
+    fn parse() {
+        // base32 of the RFC 4226 secret
+        let secret = b"12345678901234567890".to_vec();
+        assert_eq!("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".parse::<Otp>().unwrap(), Otp::new(secret.clone(), Algorithm::Sha1, 6, Kind::Totp { period: 30 }));
+        assert_eq!("gezd gnbv-gy3t qojq gezd gnbv gy3t qojq".parse::<Otp>().unwrap().secret, secret);
+        assert_eq!(
+            "otpauth://totp/Example:me?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&algorithm=SHA256&digits=8&period=60".parse::<Otp>().unwrap(),
+            Otp::new(secret.clone(), Algorithm::Sha256, 8, Kind::Totp { period: 60 })
+        );
+        assert_eq!(
+            "otpauth://hotp/me?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=3".parse::<Otp>().unwrap().code_at(0),
+            "969429"
+        );
+        for invalid in ["", "not base32!", "otpauth://totp/me", "otpauth://totp/me?secret=GEZD&digits=5", "otpauth://totp/me?secret=GEZD&period=0", "otpauth://totp/me?secret=GEZD&algorithm=MD5", "otpauth://motp/me?secret=GEZD"] {
+            assert!(invalid.parse::<Otp>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn increment_counters() {
+        assert_eq!(increment_counter("otpauth://hotp/me?secret=GEZD&counter=9").unwrap(), "otpauth://hotp/me?secret=GEZD&counter=10");
+        assert_eq!(increment_counter("otpauth://hotp/me?secret=GEZD").unwrap(), "otpauth://hotp/me?secret=GEZD&counter=1");
+    }
+}
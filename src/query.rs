@@ -0,0 +1,371 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use chrono::prelude::*;
+use magic_crypt::MagicCrypt256;
+use passwords::analyzer;
+use passwords::scorer;
+use crate::attachment_actions;
+use crate::folders;
+use crate::item_actions;
+use crate::models;
+use crate::search;
+use crate::tags;
+
+/// Passwords scoring below this, out of the 100 the key dialogs show, are weak.
+const WEAK_PASSWORD_SCORE: f64 = 60.0;
+
+/// When an item was added or last modified, as in `modified:<90d` or `added:>2026-01-01`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Age {
+    /// `<90d`, less than that long ago, `90d` means the same
+    Within(chrono::Duration),
+    /// `>90d`
+    OlderThan(chrono::Duration),
+    /// `<2026-01-01`
+    Before(NaiveDate),
+    /// `>2026-01-01`
+    After(NaiveDate),
+    /// `2026-01-01`
+    On(NaiveDate),
+}
+
+impl Age {
+    fn parse(value: &str) -> Result<Age, Box<dyn Error>> {
+        let (op, rest) = match value.strip_prefix('<').or_else(|| value.strip_prefix('>')) {
+            Some(rest) => (value.chars().next(), rest),
+            None => (None, value),
+        };
+        if let Ok(day) = NaiveDate::parse_from_str(rest, "%Y-%m-%d") {
+            return Ok(match op {
+                Some('<') => Age::Before(day),
+                Some(_) => Age::After(day),
+                None => Age::On(day),
+            });
+        }
+        let unit_start = rest.char_indices().last().map_or(0, |(i, _)| i);
+        let (number, unit) = rest.split_at(unit_start);
+        let number = number.parse::<i64>().map_err(|_| format!("not a date or an age such as 90d: {}", value))?;
+        let days = match unit {
+            "d" => number,
+            "w" => number * 7,
+            "m" => number * 30,
+            "y" => number * 365,
+            _ => return Err(format!("unknown unit in {}, use d, w, m or y", value).into()),
+        };
+        Ok(match op {
+            Some('>') => Age::OlderThan(chrono::Duration::days(days)),
+            _ => Age::Within(chrono::Duration::days(days)),
+        })
+    }
+
+    fn matches(&self, date: NaiveDateTime, now: NaiveDateTime) -> bool {
+        match self {
+            Age::Within(duration) => date > now - *duration,
+            Age::OlderThan(duration) => date <= now - *duration,
+            Age::Before(day) => date.date() < *day,
+            Age::After(day) => date.date() > *day,
+            Age::On(day) => date.date() == *day,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum Filter {
+    /// A lower cased word or quoted phrase, looked up in the search index
+    Text(String),
+    Tag(String),
+    Type(models::ItemType),
+    /// The lower cased folder names, matching items in the folder or below it
+    Folder(Vec<String>),
+    Modified(Age),
+    Added(Age),
+    WeakPassword,
+    ReusedPassword,
+    Otp,
+    Attachment,
+}
+
+impl Filter {
+    fn parse(key: &str, value: &str) -> Result<Filter, Box<dyn Error>> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(format!("{}: needs a value", key).into());
+        }
+        let filter = match key {
+            "tag" => Filter::Tag(value.to_lowercase()),
+            "type" => Filter::Type(value.to_lowercase().parse::<models::ItemType>()?),
+            "folder" | "in" => Filter::Folder(value.split(folders::SEPARATOR).map(str::trim).filter(|n| !n.is_empty()).map(str::to_lowercase).collect()),
+            "modified" => Filter::Modified(Age::parse(value)?),
+            "added" | "created" => Filter::Added(Age::parse(value)?),
+            "is" => match value.to_lowercase().as_str() {
+                "weak" => Filter::WeakPassword,
+                "reused" => Filter::ReusedPassword,
+                _ => return Err(format!("unknown is:{}, use is:weak or is:reused", value).into()),
+            },
+            "has" => match value.to_lowercase().as_str() {
+                "otp" | "totp" => Filter::Otp,
+                "attachment" | "attachments" => Filter::Attachment,
+                _ => return Err(format!("unknown has:{}, use has:otp or has:attachment", value).into()),
+            },
+            _ => Filter::Text(format!("{}:{}", key, value).to_lowercase()),
+        };
+        Ok(filter)
+    }
+
+    fn needs_fields(&self) -> bool {
+        matches!(self, Filter::WeakPassword | Filter::ReusedPassword | Filter::Otp)
+    }
+
+    /// How well the item matches, non-text filters either match with 0 or don't.
+    fn score(&self, item: &models::ItemSummary, context: &Context) -> Option<u32> {
+        let matched = match self {
+            Filter::Text(term) => return context.search_index.score(item.id, term),
+            Filter::Tag(name) => context.tag_index.item_names(item.id).iter().any(|n| n.to_lowercase() == *name),
+            Filter::Type(item_type) => item.item_type() == *item_type,
+            Filter::Folder(names) => {
+                let path: Vec<String> = context.folder_tree.path(item.folder_id).iter().map(|n| n.to_lowercase()).collect();
+                path.starts_with(names)
+            }
+            Filter::Modified(age) => age.matches(item.date_last_modified, context.now),
+            Filter::Added(age) => age.matches(item.date_added, context.now),
+            Filter::WeakPassword => context.passwords(item.id).any(|p| scorer::score(&analyzer::analyze(p)) < WEAK_PASSWORD_SCORE),
+            Filter::ReusedPassword => context.passwords(item.id).any(|p| context.password_counts.get(p).is_some_and(|count| *count > 1)),
+            Filter::Otp => context.otp_item_ids.contains(&item.id),
+            Filter::Attachment => context.attachment_item_ids.contains(&item.id),
+        };
+        matched.then_some(0)
+    }
+}
+
+/// A parsed query: words to search for and filters such as `tag:aws modified:<90d type:login`, every one of which has to match. A leading `-` negates a word or a filter, and double quotes keep a phrase or a value with spaces together.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Query {
+    /// Each filter, with whether it is negated.
+    terms: Vec<(bool, Filter)>,
+}
+
+/// Keys that start a filter, any other `key:value` is searched for as text, such as a URL.
+const KEYS: [&str; 9] = ["tag", "type", "folder", "in", "modified", "added", "created", "is", "has"];
+
+pub fn parse(text: &str) -> Result<Query, Box<dyn Error>> {
+    let mut terms = vec![];
+    for token in tokenize(text).into_iter() {
+        let (negated, token) = match token.strip_prefix('-') {
+            Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (false, token.as_str()),
+        };
+        let filter = match token.split_once(':') {
+            Some((key, value)) if KEYS.contains(&key.to_lowercase().as_str()) => Filter::parse(&key.to_lowercase(), value)?,
+            _ => Filter::Text(token.to_lowercase()),
+        };
+        terms.push((negated, filter));
+    }
+    Ok(Query { terms })
+}
+
+/// Splits the query on whitespace outside double quotes, dropping the quotes. A quote left open runs to the end, so a query still parses while it is being typed.
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut started = false;
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    tokens.push(std::mem::take(&mut token));
+                    started = false;
+                }
+            }
+            c => {
+                token.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        tokens.push(token);
+    }
+    // "" on its own searches for nothing
+    tokens.retain(|t| !t.is_empty());
+    tokens
+}
+
+impl Query {
+    /// An empty query matches every item.
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Whether the query searches for words, which needs the search index.
+    pub fn has_text(&self) -> bool {
+        self.terms.iter().any(|(_, filter)| matches!(filter, Filter::Text(_)))
+    }
+
+    /// The words and phrases searched for, to highlight them.
+    pub fn text(&self) -> Vec<String> {
+        self.terms
+            .iter()
+            .filter_map(|(negated, filter)| match filter {
+                Filter::Text(term) if !negated => Some(term.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn needs_fields(&self) -> bool {
+        self.terms.iter().any(|(_, filter)| filter.needs_fields())
+    }
+
+    /// How well the item matches, `None` when it doesn't.
+    pub fn score(&self, item: &models::ItemSummary, context: &Context) -> Option<u32> {
+        let mut total = 0;
+        for (negated, filter) in self.terms.iter() {
+            match (filter.score(item, context), negated) {
+                (Some(_), true) | (None, false) => return None,
+                (Some(score), false) => total += score,
+                (None, true) => {}
+            }
+        }
+        Some(total)
+    }
+
+    /// The ids of the matching items, closest text matches first and otherwise by path.
+    pub fn find_items(&self, context: &Context) -> Vec<i32> {
+        let mut hits: Vec<(u32, String, i32)> = context
+            .items
+            .iter()
+            .filter_map(|item| self.score(item, context).map(|score| (score, context.folder_tree.path_title(item.folder_id, &item.title).to_lowercase(), item.id)))
+            .collect();
+        hits.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        hits.into_iter().map(|(_, _, id)| id).collect()
+    }
+}
+
+/// The items outside the trash and what queries look at, loaded once to evaluate any number of queries.
+pub struct Context<'a> {
+    items: Vec<models::ItemSummary>,
+    folder_tree: folders::FolderTree,
+    tag_index: tags::TagIndex,
+    search_index: &'a search::SearchIndex,
+    /// The decrypted password fields of each item, only when a query looks at them.
+    passwords: HashMap<i32, Vec<String>>,
+    /// On how many items each password is used.
+    password_counts: HashMap<String, usize>,
+    otp_item_ids: HashSet<i32>,
+    attachment_item_ids: HashSet<i32>,
+    now: NaiveDateTime,
+}
+
+impl<'a> Context<'a> {
+    /// Fields are only decrypted when one of the queries looks at passwords or one-time passwords.
+    pub fn load(mc: &MagicCrypt256, search_index: &'a search::SearchIndex, queries: &[&Query]) -> Result<Context<'a>, Box<dyn Error>> {
+        let mut passwords: HashMap<i32, Vec<String>> = HashMap::new();
+        let mut password_counts: HashMap<String, usize> = HashMap::new();
+        let mut otp_item_ids = HashSet::new();
+        if queries.iter().any(|query| query.needs_fields()) {
+            for (item_id, encrypted_fields) in item_actions::find_all_fields()?.into_iter() {
+                let fields = models::decrypt_fields(mc, encrypted_fields.as_deref())?;
+                let mut item_passwords: Vec<String> = fields.iter().filter(|f| f.kind == models::FieldKind::Password && !f.value.is_empty()).map(|f| f.value.clone()).collect();
+                item_passwords.sort();
+                item_passwords.dedup();
+                item_passwords.iter().for_each(|p| *password_counts.entry(p.clone()).or_default() += 1);
+                passwords.insert(item_id, item_passwords);
+                if fields.iter().any(|f| f.kind == models::FieldKind::Totp && !f.value.is_empty()) {
+                    otp_item_ids.insert(item_id);
+                }
+            }
+        }
+        Ok(Context {
+            items: item_actions::find_all_summaries()?,
+            folder_tree: folders::FolderTree::load()?,
+            tag_index: tags::TagIndex::load(mc)?,
+            search_index,
+            passwords,
+            password_counts,
+            otp_item_ids,
+            attachment_item_ids: attachment_actions::find_item_ids()?.into_iter().collect(),
+            now: Utc::now().naive_utc(),
+        })
+    }
+
+    pub fn items(&self) -> &[models::ItemSummary] {
+        &self.items
+    }
+
+    pub fn folder_tree(&self) -> &folders::FolderTree {
+        &self.folder_tree
+    }
+
+    pub fn tag_index(&self) -> &tags::TagIndex {
+        &self.tag_index
+    }
+
+    fn passwords(&self, item_id: i32) -> impl Iterator<Item = &String> {
+        self.passwords.get(&item_id).into_iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(text: &str) -> Vec<(bool, Filter)> {
+        parse(text).unwrap().terms
+    }
+
+    #[test]
+    fn tokenize_quotes() {
+        assert_eq!(tokenize(r#"  aws "root account"  -"old key" "#), vec!["aws", "root account", "-old key"]);
+        assert_eq!(tokenize(r#"tag:"two words" "open"#), vec!["tag:two words", "open"]);
+        assert!(tokenize(r#"  "" "#).is_empty());
+    }
+
+    #[test]
+    fn parse_filters() {
+        assert!(parse("").unwrap().is_empty());
+        assert_eq!(
+            terms(r#"AWS -tag:Prod type:login in:"Work / AWS" has:otp -is:reused https://x.com"#),
+            vec![
+                (false, Filter::Text("aws".into())),
+                (true, Filter::Tag("prod".into())),
+                (false, Filter::Type(models::ItemType::Login)),
+                (false, Filter::Folder(vec!["work".into(), "aws".into()])),
+                (false, Filter::Otp),
+                (true, Filter::ReusedPassword),
+                (false, Filter::Text("https://x.com".into())),
+            ]
+        );
+        assert_eq!(terms("-"), vec![(false, Filter::Text("-".into()))]);
+        assert_eq!(terms("Has:Attachments"), vec![(false, Filter::Attachment)]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for text in ["tag:", "type:car", "is:strong", "has:notes", "modified:soon", "added:3x"] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parse_ages() {
+        assert_eq!(Age::parse("90d").unwrap(), Age::Within(chrono::Duration::days(90)));
+        assert_eq!(Age::parse("<2w").unwrap(), Age::Within(chrono::Duration::days(14)));
+        assert_eq!(Age::parse(">1y").unwrap(), Age::OlderThan(chrono::Duration::days(365)));
+        let day = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap();
+        assert_eq!(Age::parse("<2026-01-01").unwrap(), Age::Before(day));
+        assert_eq!(Age::parse(">2026-01-01").unwrap(), Age::After(day));
+        assert_eq!(Age::parse("2026-01-01").unwrap(), Age::On(day));
+
+        let now = day.and_hms_opt(12, 0, 0).unwrap();
+        assert!(Age::parse("<7d").unwrap().matches(now - chrono::Duration::days(6), now));
+        assert!(!Age::parse("<7d").unwrap().matches(now - chrono::Duration::days(8), now));
+        assert!(Age::parse(">7d").unwrap().matches(now - chrono::Duration::days(7), now));
+        assert!(Age::parse("2026-01-01").unwrap().matches(now, now));
+        assert!(!Age::parse(">2026-01-01").unwrap().matches(now, now));
+    }
+}
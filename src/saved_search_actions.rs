@@ -0,0 +1,59 @@
+use std::error::Error;
+use diesel::debug_query;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+
+use crate::db;
+use crate::models;
+use crate::schema::saved_searches;
+
+pub fn find_all() -> Result<Vec<models::SavedSearch>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = saved_searches::table.order(saved_searches::dsl::id).load::<models::SavedSearch>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn find_by_id(id: i32) -> Result<Option<models::SavedSearch>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = saved_searches::table.find(id).first::<models::SavedSearch>(&mut conn).optional()?;
+    Ok(results)
+}
+
+/// Looks a saved search up by its encrypted name.
+pub fn find_by_name(name: &str) -> Result<Option<models::SavedSearch>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = saved_searches::table.filter(saved_searches::dsl::name.eq(name)).first::<models::SavedSearch>(&mut conn).optional()?;
+    Ok(results)
+}
+
+pub fn insert(new_saved_search: &models::NewSavedSearch) -> Result<models::SavedSearch, diesel::result::Error> {
+    {
+        let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+        let insert = diesel::insert_into(saved_searches::table).values(new_saved_search);
+        debug!("{}", debug_query::<Sqlite, _>(&insert));
+        insert.execute(&mut conn)?;
+    }
+    find_by_name(&new_saved_search.name)?.ok_or(diesel::result::Error::NotFound)
+}
+
+pub fn update(saved_search: &models::SavedSearch) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let num_updated = diesel::update(saved_searches::table.find(saved_search.id)).set(saved_search).execute(&mut conn)?;
+    Ok(num_updated == 1)
+}
+
+/// Rewrites every saved search with `reencrypt`, on the caller's connection so it can be part of a larger transaction.
+pub fn reencrypt_all(conn: &mut SqliteConnection, reencrypt: impl Fn(&mut models::SavedSearch) -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    for mut saved_search in saved_searches::table.order(saved_searches::dsl::id).load::<models::SavedSearch>(conn)? {
+        reencrypt(&mut saved_search)?;
+        diesel::update(saved_searches::table.find(saved_search.id)).set(&saved_search).execute(conn)?;
+    }
+    Ok(())
+}
+
+pub fn delete(id: i32) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let num_deleted = diesel::delete(saved_searches::table.find(id)).execute(&mut conn)?;
+    debug!("num_deleted: {}", num_deleted);
+    Ok(num_deleted == 1)
+}
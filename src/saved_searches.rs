@@ -0,0 +1,77 @@
+use std::error::Error;
+use diesel::SqliteConnection;
+use magic_crypt::MagicCrypt256;
+use magic_crypt::MagicCryptTrait;
+use crate::models;
+use crate::query;
+use crate::saved_search_actions;
+
+pub fn find(name: &str, mc: &MagicCrypt256) -> Result<Option<models::SavedSearch>, Box<dyn Error>> {
+    Ok(saved_search_actions::find_by_name(&mc.encrypt_str_to_base64(name.trim()))?)
+}
+
+/// A saved search with its name and query decrypted.
+pub struct SavedQuery {
+    pub id: i32,
+    pub name: String,
+    pub query: String,
+}
+
+/// The saved searches, by name.
+pub fn list(mc: &MagicCrypt256) -> Result<Vec<SavedQuery>, Box<dyn Error>> {
+    let mut saved_queries = vec![];
+    for saved_search in saved_search_actions::find_all()?.into_iter() {
+        let name = saved_search.decrypt_name(mc)?;
+        let query = saved_search.decrypt_query(mc)?;
+        saved_queries.push(SavedQuery { id: saved_search.id, name, query });
+    }
+    saved_queries.sort_by_key(|saved_query| saved_query.name.to_lowercase());
+    Ok(saved_queries)
+}
+
+/// Saves a query under a name, replacing the query of a saved search by that name. The query is parsed first, so one that can't be evaluated is never saved.
+pub fn save(name: &str, query_text: &str, mc: &MagicCrypt256) -> Result<models::SavedSearch, Box<dyn Error>> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("a saved search needs a name".into());
+    }
+    let query_text = query_text.trim();
+    query::parse(query_text)?;
+    match find(name, mc)? {
+        Some(mut saved_search) => {
+            saved_search.query = mc.encrypt_str_to_base64(query_text);
+            saved_search_actions::update(&saved_search)?;
+            Ok(saved_search)
+        }
+        None => Ok(saved_search_actions::insert(&models::NewSavedSearch::new(mc, name, query_text))?),
+    }
+}
+
+/// The parsed query of the saved search by that name.
+pub fn load_query(name: &str, mc: &MagicCrypt256) -> Result<query::Query, Box<dyn Error>> {
+    let saved_search = find(name, mc)?.ok_or_else(|| format!("no saved search named: {}", name))?;
+    query::parse(&saved_search.decrypt_query(mc)?)
+}
+
+pub fn rename(saved_search: &models::SavedSearch, new_name: &str, mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    let new_name = new_name.trim();
+    if new_name.is_empty() {
+        return Err("a saved search needs a name".into());
+    }
+    if find(new_name, mc)?.is_some_and(|s| s.id != saved_search.id) {
+        return Err(format!("there already is a saved search named: {}", new_name).into());
+    }
+    let mut saved_search = saved_search.clone();
+    saved_search.name = mc.encrypt_str_to_base64(new_name);
+    saved_search_actions::update(&saved_search)?;
+    Ok(())
+}
+
+/// Re-encrypts the names and queries after the master key changed.
+pub fn change_master_key(conn: &mut SqliteConnection, old_mc: &MagicCrypt256, new_mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    saved_search_actions::reencrypt_all(conn, |saved_search| {
+        saved_search.name = new_mc.encrypt_str_to_base64(saved_search.decrypt_name(old_mc)?);
+        saved_search.query = new_mc.encrypt_str_to_base64(saved_search.decrypt_query(old_mc)?);
+        Ok(())
+    })
+}
@@ -5,5 +5,106 @@ table! {
         contents -> Nullable<Text>,
         date_added -> Timestamp,
         date_last_modified -> Timestamp,
+        item_type -> Text,
+        fields -> Nullable<Text>,
+        deleted_at -> Nullable<Timestamp>,
+        folder_id -> Nullable<Integer>,
+        revision -> Integer,
     }
 }
+
+table! {
+    folders (id) {
+        id -> Integer,
+        parent_id -> Nullable<Integer>,
+        name -> Text,
+        date_added -> Timestamp,
+    }
+}
+
+table! {
+    contacts (id) {
+        id -> Integer,
+        name -> Text,
+        public_key -> Text,
+        date_added -> Timestamp,
+    }
+}
+
+table! {
+    identities (id) {
+        id -> Integer,
+        public_key -> Text,
+        secret_key -> Text,
+        date_added -> Timestamp,
+    }
+}
+
+table! {
+    attachments (id) {
+        id -> Integer,
+        item_id -> Integer,
+        file_name -> Text,
+        mime_type -> Text,
+        size -> BigInt,
+        date_added -> Timestamp,
+    }
+}
+
+table! {
+    attachment_chunks (attachment_id, chunk_index) {
+        attachment_id -> Integer,
+        chunk_index -> Integer,
+        data -> Binary,
+    }
+}
+
+table! {
+    item_history (id) {
+        id -> Integer,
+        item_id -> Integer,
+        contents -> Nullable<Text>,
+        fields -> Nullable<Text>,
+        item_type -> Text,
+        date_saved -> Timestamp,
+    }
+}
+
+table! {
+    tags (id) {
+        id -> Integer,
+        name -> Text,
+        date_added -> Timestamp,
+    }
+}
+
+table! {
+    item_tags (item_id, tag_id) {
+        item_id -> Integer,
+        tag_id -> Integer,
+    }
+}
+
+table! {
+    saved_searches (id) {
+        id -> Integer,
+        name -> Text,
+        query -> Text,
+        date_added -> Timestamp,
+    }
+}
+
+table! {
+    path_title_items (item_id) {
+        item_id -> Integer,
+    }
+}
+
+joinable!(attachments -> items (item_id));
+joinable!(attachment_chunks -> attachments (attachment_id));
+joinable!(item_history -> items (item_id));
+joinable!(items -> folders (folder_id));
+joinable!(item_tags -> items (item_id));
+joinable!(item_tags -> tags (tag_id));
+
+allow_tables_to_appear_in_same_query!(items, folders, path_title_items, attachments, attachment_chunks, item_history, tags, item_tags, saved_searches);
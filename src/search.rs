@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::error::Error;
+use magic_crypt::MagicCrypt256;
+use crate::folders;
+use crate::item_actions;
+use crate::models;
+
+/// Scores of a query term found in each part of an item, titles count the most.
+const TITLE_SCORE: u32 = 30;
+const FIELDS_SCORE: u32 = 20;
+const CONTENTS_SCORE: u32 = 10;
+const FUZZY_SCORE: u32 = 5;
+const SUBSEQUENCE_SCORE: u32 = 2;
+
+/// The decrypted text of an item, lower cased for matching.
+struct Document {
+    title: String,
+    fields: String,
+    contents: String,
+    words: Vec<String>,
+}
+
+impl Document {
+    fn new(title: &str, fields: &[models::Field], contents: &str) -> Document {
+        let title = title.to_lowercase();
+        let fields = models::format_fields(fields).to_lowercase();
+        let contents = contents.to_lowercase();
+        let mut words: Vec<String> = [&title, &fields, &contents].iter().flat_map(|text| split_words(text)).map(|(_, word)| word).collect();
+        words.sort();
+        words.dedup();
+        Document { title, fields, contents, words }
+    }
+
+    /// How well a lower cased term matches, `None` when it doesn't at all.
+    fn score(&self, term: &str) -> Option<u32> {
+        let exact = [(&self.title, TITLE_SCORE), (&self.fields, FIELDS_SCORE), (&self.contents, CONTENTS_SCORE)]
+            .iter()
+            .filter(|(text, _)| text.contains(term))
+            .map(|(_, score)| *score)
+            .max();
+        if exact.is_some() {
+            return exact;
+        }
+        if self.words.iter().any(|word| is_fuzzy_match(word, term)) {
+            return Some(FUZZY_SCORE);
+        }
+        if term.chars().count() >= 3 && is_subsequence(&self.title, term) {
+            return Some(SUBSEQUENCE_SCORE);
+        }
+        None
+    }
+}
+
+/// An item matching a query, a higher score is a closer match.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct SearchHit {
+    pub item_id: i32,
+    pub score: u32,
+}
+
+/// The decrypted titles, contents and fields of the items, kept in memory only while the vault is unlocked and never written anywhere. Building it decrypts every item, so it is left until a search has words to look for.
+#[derive(Default)]
+pub struct SearchIndex {
+    documents: HashMap<i32, Document>,
+    built: bool,
+}
+
+impl SearchIndex {
+    /// Decrypts every item outside the trash into a new index.
+    pub fn build(mc: &MagicCrypt256) -> Result<SearchIndex, Box<dyn Error>> {
+        let folder_tree = folders::FolderTree::load()?;
+        let mut index = SearchIndex { built: true, ..SearchIndex::default() };
+        for item in item_actions::find_all(None)?.iter() {
+            index.insert(item, &folder_tree, mc)?;
+        }
+        info!("indexed {} items", index.len());
+        Ok(index)
+    }
+
+    fn insert(&mut self, item: &models::Item, folder_tree: &folders::FolderTree, mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+        let document = Document::new(&folder_tree.full_title(item), &item.decrypt_fields(mc)?, &item.decrypt_contents(mc)?);
+        self.documents.insert(item.id, document);
+        Ok(())
+    }
+
+    /// Whether the index holds the vault, rather than being left empty until it's needed.
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+
+    /// Re-reads an item after it was edited, moved, removed or restored. An index that isn't built yet reads it when it is.
+    pub fn update(&mut self, item_id: i32, mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+        if !self.built {
+            return Ok(());
+        }
+        match item_actions::find_by_id(item_id)?.filter(|item| item.deleted_at.is_none()) {
+            Some(item) => self.insert(&item, &folders::FolderTree::load()?, mc),
+            None => {
+                self.documents.remove(&item_id);
+                Ok(())
+            }
+        }
+    }
+
+    /// How well a single lower cased term, or quoted phrase, matches an item, `None` when it doesn't.
+    pub fn score(&self, item_id: i32, term: &str) -> Option<u32> {
+        self.documents.get(&item_id)?.score(term)
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// The items matching every word of the query, closest matches first.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let terms = terms(query);
+        if terms.is_empty() {
+            return vec![];
+        }
+        let mut hits: Vec<SearchHit> = self
+            .documents
+            .iter()
+            .filter_map(|(item_id, document)| {
+                let scores = terms.iter().map(|term| document.score(term)).collect::<Option<Vec<u32>>>()?;
+                Some(SearchHit { item_id: *item_id, score: scores.iter().sum() })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.cmp(&a.score).then(a.item_id.cmp(&b.item_id)));
+        hits
+    }
+}
+
+/// The lower cased words of a query.
+pub fn terms(query: &str) -> Vec<String> {
+    query.split_whitespace().map(str::to_lowercase).collect()
+}
+
+/// The words of a text with the offset, in chars, each starts at.
+fn split_words(text: &str) -> Vec<(usize, String)> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut start = 0;
+    for (i, c) in text.chars().enumerate() {
+        if c.is_alphanumeric() {
+            if word.is_empty() {
+                start = i;
+            }
+            word.push(c);
+        } else if !word.is_empty() {
+            words.push((start, std::mem::take(&mut word)));
+        }
+    }
+    if !word.is_empty() {
+        words.push((start, word));
+    }
+    words
+}
+
+/// Longer terms tolerate more typos, short ones have to match exactly.
+fn max_distance(term: &str) -> usize {
+    match term.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+fn is_fuzzy_match(word: &str, term: &str) -> bool {
+    let max = max_distance(term);
+    max > 0 && word.chars().count().abs_diff(term.chars().count()) <= max && edit_distance(word, term) <= max
+}
+
+/// Levenshtein distance between two words, in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Whether the chars of the term appear in the text in order, e.g. "awsrt" in "work/aws/root".
+fn is_subsequence(text: &str, term: &str) -> bool {
+    let mut chars = text.chars();
+    term.chars().all(|t| chars.any(|c| c == t))
+}
+
+/// The ranges of the text, in chars, matching the query exactly or closely enough, to highlight them.
+pub fn highlights(text: &str, query: &str) -> Vec<(usize, usize)> {
+    // lower cased char by char, so offsets into it are offsets into the text
+    let chars: Vec<char> = text.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect();
+    let lowered: String = chars.iter().collect();
+    let words = split_words(&lowered);
+    let mut ranges = vec![];
+    for term in terms(query).iter() {
+        let term_chars: Vec<char> = term.chars().collect();
+        let mut found = false;
+        for start in 0..chars.len().saturating_sub(term_chars.len() - 1) {
+            if chars[start..].starts_with(&term_chars) {
+                ranges.push((start, start + term_chars.len()));
+                found = true;
+            }
+        }
+        if !found {
+            for (start, word) in words.iter().filter(|(_, word)| is_fuzzy_match(word, term)) {
+                ranges.push((*start, start + word.chars().count()));
+            }
+        }
+    }
+    ranges.sort();
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use magic_crypt::MagicCryptTrait;
+    use crate::db;
+
+    #[test]
+    fn index_is_built_when_needed() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let mut new_item = models::NewItem::new("search index test".into());
+        new_item.contents = Some(mc.encrypt_str_to_base64("a lighthouse keeper"));
+        item_actions::insert(&new_item).unwrap();
+        let item = item_actions::find_by_folder_and_title(None, "search index test").unwrap().unwrap();
+
+        // an index that isn't built stays empty until it is
+        let mut index = SearchIndex::default();
+        index.update(item.id, &mc).unwrap();
+        assert!(!index.is_built());
+        assert_eq!(index.score(item.id, "lighthouse"), None);
+
+        let index = SearchIndex::build(&mc).unwrap();
+        assert!(index.is_built());
+        assert_eq!(index.score(item.id, "lighthouse"), Some(CONTENTS_SCORE));
+        assert_eq!(index.search("lighthouse keeper").first().map(|hit| hit.item_id), Some(item.id));
+    }
+}
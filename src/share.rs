@@ -0,0 +1,121 @@
+use std::error::Error;
+use std::io::prelude::*;
+use age::secrecy::ExposeSecret;
+use chrono::prelude::*;
+use diesel::SqliteConnection;
+use magic_crypt::MagicCrypt256;
+use magic_crypt::MagicCryptTrait;
+use crate::archive;
+use crate::contact_actions;
+use crate::db;
+use crate::import::ImportedItem;
+use crate::models;
+
+/// Loads the vault's age identity, generating one the first time it is needed.
+pub fn identity(mc: &MagicCrypt256) -> Result<age::x25519::Identity, Box<dyn Error>> {
+    if let Some(identity) = contact_actions::find_identity()? {
+        let secret_key = mc.decrypt_base64_to_string(&identity.secret_key)?;
+        return Ok(secret_key.parse::<age::x25519::Identity>()?);
+    }
+    if db::is_read_only() {
+        return Err("the vault has no key pair yet, it is made once no other senoru process has the vault open for writing".into());
+    }
+    let identity = age::x25519::Identity::generate();
+    contact_actions::insert_identity(&models::NewIdentity {
+        public_key: identity.to_public().to_string(),
+        secret_key: mc.encrypt_str_to_base64(identity.to_string().expose_secret()),
+        date_added: Utc::now().naive_utc(),
+    })?;
+    info!("generated the vault identity: {}", identity.to_public());
+    Ok(identity)
+}
+
+/// The public key teammates add as a contact to share items with this vault.
+pub fn public_key(mc: &MagicCrypt256) -> Result<String, Box<dyn Error>> {
+    Ok(identity(mc)?.to_public().to_string())
+}
+
+/// Re-encrypts the identity's secret key after the master key changed.
+pub fn change_master_key(conn: &mut SqliteConnection, old_mc: &MagicCrypt256, new_mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    contact_actions::reencrypt_identity(conn, |identity| {
+        let secret_key = old_mc.decrypt_base64_to_string(&identity.secret_key)?;
+        identity.secret_key = new_mc.encrypt_str_to_base64(secret_key);
+        Ok(())
+    })
+}
+
+pub fn parse_public_key(public_key: &str) -> Result<age::x25519::Recipient, Box<dyn Error>> {
+    Ok(public_key.trim().parse::<age::x25519::Recipient>().map_err(|e| format!("invalid public key {}: {}", public_key, e))?)
+}
+
+/// Encrypts the items to the contacts' public keys, as an ASCII armored age message.
+pub fn share(items: &[models::Item], contacts: &[models::Contact], mc: &MagicCrypt256) -> Result<String, Box<dyn Error>> {
+    if contacts.is_empty() {
+        return Err("no contact to share with".into());
+    }
+    let recipients = contacts.iter().map(|c| parse_public_key(&c.public_key)).collect::<Result<Vec<_>, _>>()?;
+    let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))?;
+    let document = archive::Document::from_items(items, mc)?;
+
+    let mut armored = vec![];
+    let armor_writer = age::armor::ArmoredWriter::wrap_output(&mut armored, age::armor::Format::AsciiArmor)?;
+    let mut writer = encryptor.wrap_output(armor_writer)?;
+    serde_json::to_writer(&mut writer, &document)?;
+    writer.finish()?.finish()?;
+    info!("shared {} items with {}", items.len(), contacts.iter().map(|c| c.name.as_str()).collect::<Vec<&str>>().join(", "));
+    Ok(String::from_utf8(armored)?)
+}
+
+/// Decrypts a message shared with this vault's public key.
+pub fn receive(message: &str, mc: &MagicCrypt256) -> Result<Vec<ImportedItem>, Box<dyn Error>> {
+    let identity = identity(mc)?;
+    let decryptor = age::Decryptor::new(age::armor::ArmoredReader::new(message.trim().as_bytes()))?;
+    let mut reader = decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity))?;
+    let mut decrypted = vec![];
+    reader.read_to_end(&mut decrypted)?;
+    let document = archive::parse_json(&decrypted)?;
+    document.into_imported_items()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item_actions;
+
+    #[test]
+    fn shared_items_are_received_with_the_vault_identity() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let fields = vec![models::Field::new(models::FieldKind::Text, "username", "alice")];
+        let mut new_item = models::NewItem::new("share test".into());
+        new_item.item_type = models::ItemType::Login.to_string();
+        new_item.contents = Some(mc.encrypt_str_to_base64("notes to share"));
+        item_actions::insert(&new_item).unwrap();
+        let mut item = item_actions::find_by_folder_and_title(None, "share test").unwrap().unwrap();
+        item.encrypt_fields(&mc, &fields).unwrap();
+        assert!(item_actions::update(&mut item).unwrap());
+
+        // the vault shares with itself, as a teammate's vault would with its public key
+        contact_actions::insert(&models::NewContact::new("share test self".into(), public_key(&mc).unwrap())).unwrap();
+        let contact = contact_actions::find_by_name("share test self").unwrap().unwrap();
+        let message = share(&[item], &[contact], &mc).unwrap();
+        assert!(message.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+        let received = receive(&message, &mc).unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].title, "share test");
+        assert_eq!(received[0].notes.as_deref(), Some("notes to share"));
+        assert_eq!(received[0].fields, fields);
+        assert_eq!(received[0].item_type, Some(models::ItemType::Login));
+
+        // a message for someone else's key can't be read
+        let someone_else = models::Contact {
+            id: 0,
+            name: "someone else".into(),
+            public_key: age::x25519::Identity::generate().to_public().to_string(),
+            date_added: Utc::now().naive_utc(),
+        };
+        let message = share(&[], &[someone_else], &mc).unwrap();
+        assert!(receive(&message, &mc).is_err());
+        assert!(share(&[], &[], &mc).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::error::Error;
+use diesel::debug_query;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+
+use crate::db;
+use crate::models;
+use crate::schema::item_tags;
+use crate::schema::tags;
+
+pub fn find_all() -> Result<Vec<models::Tag>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = tags::table.order(tags::dsl::id).load::<models::Tag>(&mut conn)?;
+    Ok(results)
+}
+
+/// Looks a tag up by its encrypted name.
+pub fn find_by_name(name: &str) -> Result<Option<models::Tag>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = tags::table.filter(tags::dsl::name.eq(name)).first::<models::Tag>(&mut conn).optional()?;
+    Ok(results)
+}
+
+pub fn find_by_item_id(item_id: i32) -> Result<Vec<models::Tag>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let query = tags::table.inner_join(item_tags::table).filter(item_tags::dsl::item_id.eq(item_id)).select(tags::all_columns);
+    debug!("{}", debug_query::<Sqlite, _>(&query));
+    let results = query.load::<models::Tag>(&mut conn)?;
+    Ok(results)
+}
+
+/// Every tagging in the vault, so the tags of all items can be shown without a query per item.
+pub fn find_all_item_tags() -> Result<Vec<models::ItemTag>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = item_tags::table.load::<models::ItemTag>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn find_item_ids(tag_id: i32) -> Result<Vec<i32>, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let results = item_tags::table.filter(item_tags::dsl::tag_id.eq(tag_id)).select(item_tags::dsl::item_id).load::<i32>(&mut conn)?;
+    Ok(results)
+}
+
+pub fn insert(new_tag: &models::NewTag) -> Result<models::Tag, diesel::result::Error> {
+    {
+        let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+        let insert = diesel::insert_into(tags::table).values(new_tag);
+        debug!("{}", debug_query::<Sqlite, _>(&insert));
+        insert.execute(&mut conn)?;
+    }
+    find_by_name(&new_tag.name)?.ok_or(diesel::result::Error::NotFound)
+}
+
+pub fn update(tag: &models::Tag) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let num_updated = diesel::update(tags::table.find(tag.id)).set(tag).execute(&mut conn)?;
+    Ok(num_updated == 1)
+}
+
+/// Rewrites every tag with `reencrypt`, on the caller's connection so it can be part of a larger transaction.
+pub fn reencrypt_all(conn: &mut SqliteConnection, reencrypt: impl Fn(&mut models::Tag) -> Result<(), Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    for mut tag in tags::table.order(tags::dsl::id).load::<models::Tag>(conn)? {
+        reencrypt(&mut tag)?;
+        diesel::update(tags::table.find(tag.id)).set(&tag).execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Deletes a tag and takes it off every item.
+pub fn delete(id: i32) -> Result<bool, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    conn.immediate_transaction(|conn| {
+        diesel::delete(item_tags::table.filter(item_tags::dsl::tag_id.eq(id))).execute(conn)?;
+        let num_deleted = diesel::delete(tags::table.find(id)).execute(conn)?;
+        debug!("num_deleted: {}", num_deleted);
+        Ok(num_deleted == 1)
+    })
+}
+
+/// Tags the items, those already tagged are left as they are.
+pub fn add(item_ids: &[i32], tag_id: i32) -> Result<usize, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let item_tags: Vec<models::ItemTag> = item_ids.iter().map(|&item_id| models::ItemTag { item_id, tag_id }).collect();
+    let insert = diesel::insert_or_ignore_into(item_tags::table).values(&item_tags);
+    debug!("{}", debug_query::<Sqlite, _>(&insert));
+    let num_inserted = insert.execute(&mut conn)?;
+    Ok(num_inserted)
+}
+
+pub fn remove(item_ids: &[i32], tag_id: i32) -> Result<usize, diesel::result::Error> {
+    let mut conn = db::DB_POOL.get().expect("failed to get db connection from pool");
+    let num_deleted = diesel::delete(item_tags::table.filter(item_tags::dsl::tag_id.eq(tag_id)).filter(item_tags::dsl::item_id.eq_any(item_ids))).execute(&mut conn)?;
+    debug!("num_deleted: {}", num_deleted);
+    Ok(num_deleted)
+}
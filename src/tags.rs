@@ -0,0 +1,233 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use diesel::SqliteConnection;
+use magic_crypt::MagicCrypt256;
+use magic_crypt::MagicCryptTrait;
+use crate::models;
+use crate::tag_actions;
+
+/// Splits a comma separated list of tags, as typed in the GUI or imported, leaving out empty names.
+pub fn parse_list(text: &str) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for name in text.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// Every tag and tagging, decrypted once so lists can be filtered and labelled without a query per item.
+pub struct TagIndex {
+    names: HashMap<i32, String>,
+    item_tag_ids: HashMap<i32, Vec<i32>>,
+}
+
+impl TagIndex {
+    pub fn load(mc: &MagicCrypt256) -> Result<TagIndex, Box<dyn Error>> {
+        let mut names = HashMap::new();
+        for tag in tag_actions::find_all()?.iter() {
+            names.insert(tag.id, tag.decrypt_name(mc)?);
+        }
+        let mut item_tag_ids: HashMap<i32, Vec<i32>> = HashMap::new();
+        for item_tag in tag_actions::find_all_item_tags()?.into_iter() {
+            item_tag_ids.entry(item_tag.item_id).or_default().push(item_tag.tag_id);
+        }
+        Ok(TagIndex { names, item_tag_ids })
+    }
+
+    /// The tag names with how many items carry each, by name.
+    pub fn names(&self) -> Vec<(String, usize)> {
+        let mut counts: HashMap<i32, usize> = HashMap::new();
+        self.item_tag_ids.values().flatten().for_each(|id| *counts.entry(*id).or_default() += 1);
+        let mut names: Vec<(String, usize)> = self.names.iter().map(|(id, name)| (name.clone(), counts.get(id).copied().unwrap_or_default())).collect();
+        names.sort_by_key(|(name, _)| name.to_lowercase());
+        names
+    }
+
+    pub fn item_names(&self, item_id: i32) -> Vec<String> {
+        let mut names: Vec<String> = self.item_tag_ids.get(&item_id).into_iter().flatten().filter_map(|id| self.names.get(id).cloned()).collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        names
+    }
+
+    /// Whether the item carries every one of the tags, an empty filter matches every item.
+    pub fn matches(&self, item_id: i32, names: &[String]) -> bool {
+        let item_names = self.item_names(item_id);
+        names.iter().all(|name| item_names.contains(name))
+    }
+}
+
+pub fn find(name: &str, mc: &MagicCrypt256) -> Result<Option<models::Tag>, Box<dyn Error>> {
+    Ok(tag_actions::find_by_name(&mc.encrypt_str_to_base64(name.trim()))?)
+}
+
+pub fn find_or_create(name: &str, mc: &MagicCrypt256) -> Result<models::Tag, Box<dyn Error>> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("a tag needs a name".into());
+    }
+    match find(name, mc)? {
+        Some(tag) => Ok(tag),
+        None => Ok(tag_actions::insert(&models::NewTag::new(mc, name))?),
+    }
+}
+
+/// The names of the tags on an item, by name.
+pub fn item_names(item_id: i32, mc: &MagicCrypt256) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut names = tag_actions::find_by_item_id(item_id)?.iter().map(|t| t.decrypt_name(mc)).collect::<Result<Vec<String>, _>>()?;
+    names.sort_by_key(|name| name.to_lowercase());
+    Ok(names)
+}
+
+/// Tags the items, creating the tag when it is new, and returns how many were not tagged with it yet.
+pub fn tag_items(item_ids: &[i32], name: &str, mc: &MagicCrypt256) -> Result<usize, Box<dyn Error>> {
+    let tag = find_or_create(name, mc)?;
+    Ok(tag_actions::add(item_ids, tag.id)?)
+}
+
+/// Takes the tag off the items, a tag left on no item is deleted.
+pub fn untag_items(item_ids: &[i32], name: &str, mc: &MagicCrypt256) -> Result<usize, Box<dyn Error>> {
+    let tag = match find(name, mc)? {
+        Some(tag) => tag,
+        None => return Ok(0),
+    };
+    let num_removed = tag_actions::remove(item_ids, tag.id)?;
+    if tag_actions::find_item_ids(tag.id)?.is_empty() {
+        tag_actions::delete(tag.id)?;
+    }
+    Ok(num_removed)
+}
+
+/// Moves the items of every tag in `names` onto `into` and deletes the others.
+pub fn merge(names: &[String], into: &str, mc: &MagicCrypt256) -> Result<usize, Box<dyn Error>> {
+    let target = find_or_create(into, mc)?;
+    let mut item_ids: HashSet<i32> = HashSet::new();
+    for name in names.iter() {
+        let tag = find(name, mc)?.ok_or_else(|| format!("no tag named: {}", name))?;
+        if tag.id == target.id {
+            continue;
+        }
+        let tag_item_ids = tag_actions::find_item_ids(tag.id)?;
+        tag_actions::add(&tag_item_ids, target.id)?;
+        item_ids.extend(tag_item_ids);
+        tag_actions::delete(tag.id)?;
+    }
+    info!("merged {} tags into {}", names.len(), into);
+    Ok(item_ids.len())
+}
+
+/// Renames a tag, renaming it to a tag that already exists merges the two.
+pub fn rename(name: &str, new_name: &str, mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    let mut tag = find(name, mc)?.ok_or_else(|| format!("no tag named: {}", name))?;
+    let new_name = new_name.trim();
+    if new_name.is_empty() {
+        return Err("a tag needs a name".into());
+    }
+    if find(new_name, mc)?.is_some_and(|t| t.id != tag.id) {
+        merge(&[name.to_string()], new_name, mc)?;
+        return Ok(());
+    }
+    tag.name = mc.encrypt_str_to_base64(new_name);
+    tag_actions::update(&tag)?;
+    Ok(())
+}
+
+/// Re-encrypts the tag names after the master key changed.
+pub fn change_master_key(conn: &mut SqliteConnection, old_mc: &MagicCrypt256, new_mc: &MagicCrypt256) -> Result<(), Box<dyn Error>> {
+    tag_actions::reencrypt_all(conn, |tag| {
+        tag.name = new_mc.encrypt_str_to_base64(tag.decrypt_name(old_mc)?);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::item_actions;
+
+    fn item(title: &str) -> i32 {
+        item_actions::insert(&models::NewItem::new(title.into())).unwrap();
+        item_actions::find_by_folder_and_title(None, title).unwrap().unwrap().id
+    }
+
+    #[test]
+    fn lists_are_split_without_repeats() {
+        assert_eq!(parse_list(" work, ,home,work,"), vec!["work".to_string(), "home".to_string()]);
+        assert!(parse_list(" , ").is_empty());
+    }
+
+    #[test]
+    fn untagging_the_last_item_deletes_the_tag() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let (first, second) = (item("tags untag test 1"), item("tags untag test 2"));
+        assert_eq!(tag_items(&[first, second], " untag test ", &mc).unwrap(), 2);
+        assert_eq!(tag_items(&[first], "untag test", &mc).unwrap(), 0);
+        assert_eq!(item_names(first, &mc).unwrap(), vec!["untag test"]);
+
+        assert_eq!(untag_items(&[first], "untag test", &mc).unwrap(), 1);
+        assert!(find("untag test", &mc).unwrap().is_some());
+        assert_eq!(untag_items(&[second], "untag test", &mc).unwrap(), 1);
+        assert!(find("untag test", &mc).unwrap().is_none());
+        assert_eq!(untag_items(&[second], "untag test", &mc).unwrap(), 0);
+    }
+
+    #[test]
+    fn renaming_onto_a_tag_merges_them() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let (both, only_old) = (item("tags rename test both"), item("tags rename test old"));
+        tag_items(&[both, only_old], "rename test old", &mc).unwrap();
+        rename("rename test old", "rename test new", &mc).unwrap();
+        assert!(find("rename test old", &mc).unwrap().is_none());
+        assert_eq!(item_names(only_old, &mc).unwrap(), vec!["rename test new"]);
+
+        // the names are encrypted deterministically, so the same name is the same tag
+        tag_items(&[both], "rename test other", &mc).unwrap();
+        rename("rename test new", "rename test other", &mc).unwrap();
+        assert_eq!(item_names(both, &mc).unwrap(), vec!["rename test other"]);
+        assert_eq!(tag_actions::find_item_ids(find("rename test other", &mc).unwrap().unwrap().id).unwrap().len(), 2);
+        assert!(rename("rename test other", " ", &mc).is_err());
+        assert!(rename("rename test missing", "rename test x", &mc).is_err());
+    }
+
+    #[test]
+    fn merging_keeps_one_tagging_per_item() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let (first, second, third) = (item("tags merge test 1"), item("tags merge test 2"), item("tags merge test 3"));
+        tag_items(&[first, second], "merge test a", &mc).unwrap();
+        tag_items(&[second, third], "merge test b", &mc).unwrap();
+        tag_items(&[third], "merge test into", &mc).unwrap();
+
+        let names = vec!["merge test a".to_string(), "merge test b".to_string(), "merge test into".to_string()];
+        assert_eq!(merge(&names, "merge test into", &mc).unwrap(), 3);
+        assert!(find("merge test a", &mc).unwrap().is_none());
+        assert!(find("merge test b", &mc).unwrap().is_none());
+        let into = find("merge test into", &mc).unwrap().unwrap();
+        let mut item_ids = tag_actions::find_item_ids(into.id).unwrap();
+        item_ids.sort();
+        assert_eq!(item_ids, vec![first, second, third]);
+        assert_eq!(item_names(second, &mc).unwrap(), vec!["merge test into"]);
+        assert!(merge(&["merge test a".to_string()], "merge test into", &mc).is_err());
+    }
+
+    #[test]
+    fn the_index_names_the_tags_of_each_item() {
+        db::init_test_db();
+        let mc = new_magic_crypt!("test", 256);
+        let (tagged, untagged) = (item("tags index test tagged"), item("tags index test untagged"));
+        tag_items(&[tagged], "index test Zeta", &mc).unwrap();
+        tag_items(&[tagged], "index test alpha", &mc).unwrap();
+
+        let index = TagIndex::load(&mc).unwrap();
+        assert_eq!(index.item_names(tagged), vec!["index test alpha", "index test Zeta"]);
+        assert!(index.item_names(untagged).is_empty());
+        assert!(index.matches(tagged, &["index test Zeta".to_string()]));
+        assert!(!index.matches(untagged, &["index test Zeta".to_string()]));
+        assert!(index.matches(untagged, &[]));
+        assert!(index.names().contains(&("index test alpha".to_string(), 1)));
+    }
+}
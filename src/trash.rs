@@ -0,0 +1,86 @@
+use std::env;
+use std::error::Error;
+use chrono::prelude::*;
+use crate::item_actions;
+
+pub const DEFAULT_PURGE_DAYS: i64 = 30;
+
+/// How long removed items stay in the trash, from `SENORU_TRASH_DAYS` (set by `--trash-days`), `None` when they stay until it is emptied.
+pub fn purge_days() -> Option<i64> {
+    match env::var("SENORU_TRASH_DAYS").ok().and_then(|v| v.parse::<i64>().ok()) {
+        Some(0) => None,
+        Some(days) => Some(days),
+        None => Some(DEFAULT_PURGE_DAYS),
+    }
+}
+
+/// Deletes the items that have been in the trash longer than `purge_days`.
+pub fn purge_expired() -> Result<usize, Box<dyn Error>> {
+    let days = match purge_days() {
+        Some(days) => days,
+        None => return Ok(0),
+    };
+    let mut num_deleted = 0;
+    for id in item_actions::find_trashed_before(Utc::now().naive_utc() - chrono::Duration::days(days))? {
+        if item_actions::delete(&id)? {
+            num_deleted += 1;
+        }
+    }
+    if num_deleted > 0 {
+        info!("purged {} items that were in the trash for more than {} days", num_deleted, days);
+    }
+    Ok(num_deleted)
+}
+
+/// Deletes every item in the trash for good.
+pub fn empty() -> Result<usize, Box<dyn Error>> {
+    let mut num_deleted = 0;
+    for item in item_actions::find_trash()?.iter() {
+        if item_actions::delete(&item.id)? {
+            num_deleted += 1;
+        }
+    }
+    info!("emptied the trash, {} items deleted", num_deleted);
+    Ok(num_deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::models;
+
+    fn trashed_item(title: &str, days_ago: i64) -> i32 {
+        let new_item = models::NewItem::new(title.into());
+        item_actions::insert(&new_item).unwrap();
+        let mut item = item_actions::find_by_folder_and_title(None, title).unwrap().unwrap();
+        item.deleted_at = Some(Utc::now().naive_utc() - chrono::Duration::days(days_ago));
+        assert!(item_actions::update(&mut item).unwrap());
+        item.id
+    }
+
+    /// One test, as the purge follows `SENORU_TRASH_DAYS`.
+    #[test]
+    fn purge_after_the_configured_days() {
+        db::init_test_db();
+        env::remove_var("SENORU_TRASH_DAYS");
+        assert_eq!(purge_days(), Some(DEFAULT_PURGE_DAYS));
+        env::set_var("SENORU_TRASH_DAYS", "0");
+        assert_eq!(purge_days(), None);
+
+        let old_id = trashed_item("trash old test", DEFAULT_PURGE_DAYS + 1);
+        let recent_id = trashed_item("trash recent test", 2);
+        assert_eq!(purge_expired().unwrap(), 0);
+        assert!(item_actions::find_by_id(old_id).unwrap().is_some());
+
+        env::remove_var("SENORU_TRASH_DAYS");
+        assert_eq!(purge_expired().unwrap(), 1);
+        assert!(item_actions::find_by_id(old_id).unwrap().is_none());
+        assert!(item_actions::find_by_id(recent_id).unwrap().is_some());
+
+        env::set_var("SENORU_TRASH_DAYS", "1");
+        assert_eq!(purge_expired().unwrap(), 1);
+        assert!(item_actions::find_by_id(recent_id).unwrap().is_none());
+        env::remove_var("SENORU_TRASH_DAYS");
+    }
+}
@@ -0,0 +1,125 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::os::unix::io::AsRawFd;
+use std::path;
+use std::thread;
+use std::time::{Duration, Instant};
+use sha2::{Digest, Sha256};
+
+/// How long a command waits for another one to finish changing the vault. A window holds the lock for as long as it is open, so there's no point waiting much longer.
+pub const WAIT: Duration = Duration::from_secs(5);
+
+/// The lock file next to the vault, e.g. `senoru.db.lock`.
+pub fn lock_path(db_path: &path::Path) -> path::PathBuf {
+    let mut file_name = db_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".lock");
+    db_path.with_file_name(file_name)
+}
+
+/// The advisory write lock on a vault, held by the window that may change it until the lock is dropped with the process. Other windows on the same vault open it read-only, so neither overwrites what the other has on screen.
+#[derive(Debug)]
+pub struct VaultLock {
+    file: fs::File,
+}
+
+/// Takes the write lock on the vault, `None` when another process holds it.
+pub fn try_acquire(db_path: &path::Path) -> Result<Option<VaultLock>, Box<dyn Error>> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(lock_path(db_path))?;
+    // the lock goes with the open file, so a process that dies can't leave the vault locked
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let e = io::Error::last_os_error();
+        return match e.kind() {
+            io::ErrorKind::WouldBlock => Ok(None),
+            _ => Err(e.into()),
+        };
+    }
+    // the pid is only there for people wondering who holds the lock
+    file.set_len(0)?;
+    write!(file, "{}", std::process::id())?;
+    debug!("locked {:?}", lock_path(db_path));
+    Ok(Some(VaultLock { file }))
+}
+
+/// Takes the write lock on the vault, waiting up to `timeout` for the process holding it to let go. `None` when it didn't.
+pub fn acquire(db_path: &path::Path, timeout: Duration) -> Result<Option<VaultLock>, Box<dyn Error>> {
+    let started = Instant::now();
+    loop {
+        if let Some(lock) = try_acquire(db_path)? {
+            return Ok(Some(lock));
+        }
+        if started.elapsed() >= timeout {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// The process holding the write lock, as it wrote itself into the lock file.
+pub fn holder(db_path: &path::Path) -> Option<u32> {
+    fs::read_to_string(lock_path(db_path)).ok()?.trim().parse().ok()
+}
+
+impl Drop for VaultLock {
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
+
+/// The GApplication id of the vault, so launching senoru again on an open vault brings its window to the front, while other vaults still get windows of their own.
+pub fn application_id(db_path: &path::Path) -> String {
+    // the vault may not exist yet, its folder does
+    let folder = db_path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or_else(|| path::Path::new("."));
+    let vault = match (fs::canonicalize(folder), db_path.file_name()) {
+        (Ok(folder), Some(file_name)) => folder.join(file_name),
+        _ => db_path.to_path_buf(),
+    };
+    let digest = Sha256::digest(vault.to_string_lossy().as_bytes());
+    let hash: String = digest.iter().take(8).map(|b| format!("{:02x}", b)).collect();
+    format!("com.kiluet.senoru.vault_{}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_files_sit_next_to_the_vault() {
+        assert_eq!(lock_path(path::Path::new("/home/me/.senoru/senoru.db")), path::PathBuf::from("/home/me/.senoru/senoru.db.lock"));
+        assert_eq!(lock_path(path::Path::new("vault.db")), path::PathBuf::from("vault.db.lock"));
+    }
+
+    #[test]
+    fn one_lock_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("senoru.db");
+        assert_eq!(holder(&db_path), None);
+        let lock = try_acquire(&db_path).unwrap().unwrap();
+        assert_eq!(holder(&db_path), Some(std::process::id()));
+        // flock goes with the open file, so a second one is refused in the same process too
+        assert!(try_acquire(&db_path).unwrap().is_none());
+        let started = Instant::now();
+        assert!(acquire(&db_path, Duration::from_millis(200)).unwrap().is_none());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        drop(lock);
+        let lock = try_acquire(&db_path).unwrap();
+        assert!(lock.is_some());
+        drop(lock);
+        assert!(acquire(&db_path, Duration::ZERO).unwrap().is_some());
+    }
+
+    #[test]
+    fn a_waiting_lock_is_taken_once_let_go() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("senoru.db");
+        let lock = try_acquire(&db_path).unwrap().unwrap();
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            drop(lock);
+        });
+        assert!(acquire(&db_path, WAIT).unwrap().is_some());
+        release.join().unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use std::fs;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+
+/// How many processes write to the vault at once, each importing items of its own.
+const WRITERS: usize = 4;
+
+/// How many imports each writer runs, every one adding an item and editing another.
+const ROUNDS: usize = 8;
+
+/// A vault in a temporary directory, with the key in a file so the processes don't ask for it.
+struct Vault {
+    dir: tempfile::TempDir,
+    database: PathBuf,
+    key_file: PathBuf,
+}
+
+impl Vault {
+    fn new() -> Vault {
+        let dir = tempfile::tempdir().unwrap();
+        let database = dir.path().join("senoru.db");
+        let key_file = dir.path().join("key");
+        fs::write(&key_file, "stress test key\n").unwrap();
+        Vault { dir, database, key_file }
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_senoru"))
+            .arg("--database")
+            .arg(&self.database)
+            .arg("--key-file")
+            .arg(&self.key_file)
+            .args(args)
+            .env("HOME", self.dir.path())
+            .output()
+            .unwrap()
+    }
+
+    /// Runs senoru on the vault, failing the test when it doesn't succeed.
+    fn senoru(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "senoru {} failed: {}", args.join(" "), stderr.trim());
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Imports a JSON document with the items, replacing the ones already there.
+    fn import(&self, name: &str, items: &[(String, String)]) {
+        let items: Vec<serde_json::Value> = items.iter().map(|(title, contents)| serde_json::json!({ "title": title, "contents": contents })).collect();
+        let document = serde_json::json!({ "format_version": 2, "items": items });
+        let path = self.dir.path().join(format!("{}.json", name));
+        fs::write(&path, document.to_string()).unwrap();
+        self.senoru(&["import", path.to_str().unwrap(), "--format", "json", "--on-conflict", "overwrite"]);
+    }
+
+    fn titles(&self) -> Vec<String> {
+        self.senoru(&["list"]).lines().map(String::from).collect()
+    }
+}
+
+#[derive(QueryableByName)]
+struct IntegrityCheck {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    integrity_check: String,
+}
+
+fn integrity_check(database: &Path) -> Vec<String> {
+    let mut conn = SqliteConnection::establish(database.to_str().unwrap()).unwrap();
+    let rows = diesel::sql_query("PRAGMA integrity_check").load::<IntegrityCheck>(&mut conn).unwrap();
+    rows.into_iter().map(|row| row.integrity_check).collect()
+}
+
+/// Several processes add and edit items while others list and search the vault, then every item is there with its last edit, and the database is sound.
+#[test]
+fn processes_share_a_vault() {
+    let vault = Arc::new(Vault::new());
+    // the first process changing the vault sets it up
+    vault.import("setup", &[]);
+
+    let writing = Arc::new(AtomicBool::new(true));
+    let readers: Vec<_> = (0..2)
+        .map(|reader| {
+            let (vault, writing) = (Arc::clone(&vault), Arc::clone(&writing));
+            thread::spawn(move || {
+                let mut reads = 0;
+                while writing.load(Ordering::SeqCst) {
+                    match reader {
+                        0 => vault.senoru(&["list"]),
+                        _ => vault.senoru(&["search", "writer"]),
+                    };
+                    reads += 1;
+                }
+                reads
+            })
+        })
+        .collect();
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                for round in 0..ROUNDS {
+                    let items = [
+                        (format!("writer {} item {}", writer, round), format!("added in round {}", round)),
+                        (format!("writer {} notes", writer), format!("edited in round {}", round)),
+                    ];
+                    vault.import(&format!("writer-{}-{}", writer, round), &items);
+                    assert!(vault.titles().contains(&items[0].0), "writer {} doesn't see its item {}", writer, round);
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    writing.store(false, Ordering::SeqCst);
+    for reader in readers {
+        assert!(reader.join().unwrap() > 0);
+    }
+
+    let titles = vault.titles();
+    assert_eq!(titles.len(), WRITERS * (ROUNDS + 1));
+    let export = vault.dir.path().join("export.json");
+    vault.senoru(&["export", export.to_str().unwrap(), "--format", "json", "--yes"]);
+    let document: serde_json::Value = serde_json::from_str(&fs::read_to_string(&export).unwrap()).unwrap();
+    for writer in 0..WRITERS {
+        let title = format!("writer {} notes", writer);
+        let notes = document["items"].as_array().unwrap().iter().find(|item| item["title"] == title.as_str()).unwrap();
+        assert_eq!(notes["contents"], format!("edited in round {}", ROUNDS - 1).as_str());
+    }
+    assert_eq!(integrity_check(&vault.database), vec!["ok"]);
+}
+
+/// While another process has the vault open for writing, as a window does, commands changing it give up and the others still read it.
+#[test]
+fn commands_wait_for_the_write_lock() {
+    let vault = Vault::new();
+    vault.import("setup", &[("notes".to_string(), "one".to_string())]);
+    let lock_file = fs::OpenOptions::new().write(true).open(vault.dir.path().join("senoru.db.lock")).unwrap();
+    assert_eq!(unsafe { libc::flock(lock_file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) }, 0);
+
+    let output = vault.run(&["tags", "add", "work", "notes"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("has the vault open for writing"));
+    assert_eq!(vault.titles(), vec!["notes"]);
+    assert_eq!(vault.senoru(&["tags"]), "");
+
+    drop(lock_file);
+    vault.senoru(&["tags", "add", "work", "notes"]);
+    assert!(vault.senoru(&["tags"]).contains("work"));
+}
+
+/// A title with a / imported at the top level stays one item once the vault is opened again, instead of being filed into folders.
+#[test]
+fn imported_titles_keep_their_separators() {
+    let vault = Vault::new();
+    vault.import("slashed", &[("example.com/login".to_string(), "secret".to_string())]);
+    // opening the vault for writing again runs what every start does
+    vault.import("restart", &[]);
+    let export = vault.dir.path().join("export.json");
+    vault.senoru(&["export", export.to_str().unwrap(), "--format", "json", "--yes"]);
+    let document: serde_json::Value = serde_json::from_str(&fs::read_to_string(&export).unwrap()).unwrap();
+    let items = document["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["title"], "example.com/login");
+    // an item at the top level has no path
+    assert!(items[0].get("path").is_none());
+    assert_eq!(vault.titles(), vec!["example.com/login"]);
+}